@@ -0,0 +1,353 @@
+//! iMERA ↔ SPL bridge bookkeeping.
+//!
+//! Every bridge transfer is a record in stable memory that moves through a small state machine:
+//!
+//! * IC → Solana: `Pending` → `Locked` (iMERA moved into the bridge's custody account on
+//!   `bikera_token`) → `Submitted` (SPL release sent from the bridge vault) → `Completed`.
+//!   A transfer that fails after locking goes to `Failed` and is then `Refunded` on the IC.
+//! * Solana → IC: a deposit to the bridge vault is claimed as `Pending` and becomes
+//!   `Completed` once the locked iMERA is released to the depositor. A failed release stays
+//!   `Pending` and is retried.
+//!
+//! Each status change is also appended to a stable event log.
+
+pub mod ledger;
+
+use crate::memory::{
+    get_memory, Memory, BRIDGE_EVENTS_DATA_MEMORY_ID, BRIDGE_EVENTS_INDEX_MEMORY_ID,
+    BRIDGE_NONCES_MEMORY_ID, BRIDGE_TRANSFERS_MEMORY_ID, CLAIMED_DEPOSITS_MEMORY_ID,
+};
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_stable_structures::{StableBTreeMap, StableLog};
+use std::{cell::RefCell, collections::BTreeSet};
+
+#[cfg(test)]
+mod tests;
+
+/// Prefix of the SPL memo a depositor attaches to claim a deposit, followed by their principal.
+pub const DEPOSIT_MEMO_PREFIX: &str = "bikera:";
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BridgeDirection {
+    IcToSolana,
+    SolanaToIc,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BridgeStatus {
+    Pending,
+    Locked,
+    Submitted,
+    Completed,
+    Failed,
+    Refunded,
+}
+
+impl BridgeStatus {
+    /// Transitions allowed by the bridge state machine.
+    pub fn can_transition_to(self, next: BridgeStatus) -> bool {
+        use BridgeStatus::*;
+        matches!(
+            (self, next),
+            (Pending, Locked)
+                | (Pending, Completed)
+                | (Pending, Failed)
+                | (Locked, Submitted)
+                | (Locked, Failed)
+                | (Submitted, Completed)
+                | (Submitted, Failed)
+                | (Failed, Refunded)
+        )
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BridgeTransfer {
+    pub id: u64,
+    pub direction: BridgeDirection,
+    pub owner: Principal,
+    /// Client-chosen nonce, `None` for deposits detected on Solana.
+    pub nonce: Option<u64>,
+    /// Amount in base units; iMERA and the SPL mint use the same number of decimals.
+    pub amount: Nat,
+    pub solana_wallet: String,
+    pub status: BridgeStatus,
+    /// `bikera_token` block index of the lock (IC → Solana) or release (Solana → IC).
+    pub ic_block_index: Option<Nat>,
+    pub solana_signature: Option<String>,
+    pub refund_block_index: Option<Nat>,
+    pub failure_reason: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BridgeEvent {
+    pub transfer_id: u64,
+    pub status: BridgeStatus,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BridgeToSolanaArgs {
+    /// Must equal `bridge_nonce(caller)`; each nonce can only be used once.
+    pub nonce: u64,
+    pub solana_wallet: String,
+    pub amount: Nat,
+}
+
+// Transfers and events go through the versioned `stable_schema` envelope. Both grow with
+// every bridge transfer, so they are never rewritten in bulk: values stored as raw candid are
+// read as version 0, and a transfer is re-enveloped on its next status change.
+stable_schema::versioned_since_raw_candid!(BridgeTransfer, "basic_solana::BridgeTransfer");
+stable_schema::versioned_since_raw_candid!(BridgeEvent, "basic_solana::BridgeEvent");
+
+thread_local! {
+    static TRANSFERS: RefCell<StableBTreeMap<u64, BridgeTransfer, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(BRIDGE_TRANSFERS_MEMORY_ID))
+    );
+
+    static EVENTS: RefCell<StableLog<BridgeEvent, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            get_memory(BRIDGE_EVENTS_INDEX_MEMORY_ID),
+            get_memory(BRIDGE_EVENTS_DATA_MEMORY_ID),
+        )
+        .expect("failed to initialize the bridge event log")
+    );
+
+    /// Next expected nonce per principal.
+    static NONCES: RefCell<StableBTreeMap<Principal, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(BRIDGE_NONCES_MEMORY_ID))
+    );
+
+    /// Solana signature of a claimed deposit -> bridge transfer id.
+    static CLAIMED_DEPOSITS: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(CLAIMED_DEPOSITS_MEMORY_ID))
+    );
+
+    /// Transfers with an inter-canister call in flight. Kept on the heap on purpose: upgrades
+    /// stop the canister, so no call can be in flight across an upgrade.
+    static IN_FLIGHT: RefCell<BTreeSet<u64>> = RefCell::default();
+}
+
+/// Marks a transfer as being processed until the guard is dropped, so two concurrent calls
+/// cannot both release or refund the same transfer.
+pub struct TransferGuard(u64);
+
+impl TransferGuard {
+    pub fn new(id: u64) -> Result<Self, String> {
+        if !IN_FLIGHT.with(|f| f.borrow_mut().insert(id)) {
+            return Err(format!("Bridge transfer {id} is already being processed"));
+        }
+        Ok(Self(id))
+    }
+}
+
+impl Drop for TransferGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|f| f.borrow_mut().remove(&self.0));
+    }
+}
+
+pub fn next_nonce(owner: Principal) -> u64 {
+    NONCES.with(|n| n.borrow().get(&owner).unwrap_or_default())
+}
+
+/// Consumes `nonce` for `owner`, rejecting anything but the next expected value.
+///
+/// The nonce is consumed before any inter-canister call, so a replayed or concurrent request
+/// with the same nonce is rejected even if the first one is still in flight.
+pub fn consume_nonce(owner: Principal, nonce: u64) -> Result<(), String> {
+    let expected = next_nonce(owner);
+    if nonce != expected {
+        return Err(format!("Invalid nonce {nonce}, expected {expected}"));
+    }
+    NONCES.with(|n| n.borrow_mut().insert(owner, expected + 1));
+    Ok(())
+}
+
+pub fn claimed_deposit(signature: &str) -> Option<u64> {
+    CLAIMED_DEPOSITS.with(|c| c.borrow().get(&signature.to_string()))
+}
+
+/// Creates a transfer in the `Pending` state and returns its id.
+pub fn create_transfer(
+    direction: BridgeDirection,
+    owner: Principal,
+    nonce: Option<u64>,
+    amount: Nat,
+    solana_wallet: String,
+    solana_signature: Option<String>,
+    now: u64,
+) -> u64 {
+    let id = TRANSFERS.with(|t| t.borrow().len());
+    if let Some(signature) = solana_signature.clone() {
+        CLAIMED_DEPOSITS.with(|c| c.borrow_mut().insert(signature, id));
+    }
+    TRANSFERS.with(|t| {
+        t.borrow_mut().insert(
+            id,
+            BridgeTransfer {
+                id,
+                direction,
+                owner,
+                nonce,
+                amount,
+                solana_wallet,
+                status: BridgeStatus::Pending,
+                ic_block_index: None,
+                solana_signature,
+                refund_block_index: None,
+                failure_reason: None,
+                created_at: now,
+                updated_at: now,
+            },
+        )
+    });
+    record_event(id, BridgeStatus::Pending, now);
+    id
+}
+
+/// Moves a transfer to `status`, applying `update` to the record.
+///
+/// Panics on transitions the state machine does not allow, which traps and rolls back the
+/// state changes made since the last inter-canister call.
+pub fn advance(id: u64, status: BridgeStatus, now: u64, update: impl FnOnce(&mut BridgeTransfer)) {
+    TRANSFERS.with(|t| {
+        let mut transfers = t.borrow_mut();
+        let mut transfer = transfers
+            .get(&id)
+            .unwrap_or_else(|| panic!("unknown bridge transfer {id}"));
+        assert!(
+            transfer.status.can_transition_to(status),
+            "invalid bridge transition {:?} -> {:?} for transfer {id}",
+            transfer.status,
+            status
+        );
+        update(&mut transfer);
+        transfer.status = status;
+        transfer.updated_at = now;
+        transfers.insert(id, transfer);
+    });
+    record_event(id, status, now);
+}
+
+fn record_event(transfer_id: u64, status: BridgeStatus, timestamp: u64) {
+    EVENTS.with(|e| {
+        e.borrow()
+            .append(&BridgeEvent {
+                transfer_id,
+                status,
+                timestamp,
+            })
+            .expect("failed to append to the bridge event log")
+    });
+}
+
+pub fn get_transfer(id: u64) -> Option<BridgeTransfer> {
+    TRANSFERS.with(|t| t.borrow().get(&id))
+}
+
+/// Transfers of `owner`, newest first.
+pub fn transfers_of(owner: Principal, offset: usize, limit: usize) -> Vec<BridgeTransfer> {
+    TRANSFERS.with(|t| {
+        let transfers = t.borrow();
+        (0..transfers.len())
+            .rev()
+            .filter_map(|id| transfers.get(&id))
+            .filter(|transfer| transfer.owner == owner)
+            .skip(offset)
+            .take(limit)
+            .collect()
+    })
+}
+
+/// Transfers that have not reached a final state yet.
+pub fn unfinished_transfers() -> Vec<BridgeTransfer> {
+    TRANSFERS.with(|t| {
+        t.borrow()
+            .iter()
+            .map(|(_, transfer)| transfer)
+            .filter(|transfer| {
+                matches!(
+                    transfer.status,
+                    BridgeStatus::Pending | BridgeStatus::Locked | BridgeStatus::Submitted
+                ) || (transfer.status == BridgeStatus::Failed
+                    && transfer.direction == BridgeDirection::IcToSolana
+                    && transfer.ic_block_index.is_some())
+            })
+            .collect()
+    })
+}
+
+pub fn events(start: u64, limit: u64) -> Vec<BridgeEvent> {
+    EVENTS.with(|e| {
+        let events = e.borrow();
+        (start..events.len().min(start.saturating_add(limit)))
+            .filter_map(|index| events.get(index))
+            .collect()
+    })
+}
+
+/// Memo placed on ledger operations so they can be traced back to the bridge transfer.
+pub fn ledger_memo(id: u64) -> Vec<u8> {
+    [b"bridge:".as_slice(), &id.to_be_bytes()].concat()
+}
+
+/// Token balance of one account before and after a Solana transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenBalanceChange {
+    pub mint: String,
+    pub owner: String,
+    pub pre_amount: u64,
+    pub post_amount: u64,
+}
+
+/// A deposit into the bridge vault found in a finalized Solana transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deposit {
+    pub sender: String,
+    pub amount: u64,
+}
+
+/// Finds how much of `mint` the transaction moved into `vault_owner`'s token account, and from
+/// which wallet. The depositor must have tagged the transaction with the memo
+/// `bikera:<claimant principal>` so that nobody else can claim it.
+pub fn detect_deposit(
+    changes: &[TokenBalanceChange],
+    log_messages: &[String],
+    mint: &str,
+    vault_owner: &str,
+    claimant: Principal,
+) -> Result<Deposit, String> {
+    let expected_memo = format!("{DEPOSIT_MEMO_PREFIX}{claimant}");
+    if !log_messages
+        .iter()
+        .any(|line| line.contains("Memo") && line.contains(&expected_memo))
+    {
+        return Err(format!(
+            "Deposit is missing the memo \"{expected_memo}\" identifying the claimant"
+        ));
+    }
+
+    let amount = changes
+        .iter()
+        .filter(|change| change.mint == mint && change.owner == vault_owner)
+        .map(|change| change.post_amount.saturating_sub(change.pre_amount))
+        .sum::<u64>();
+    if amount == 0 {
+        return Err("Transaction does not deposit the bridged token into the vault".to_string());
+    }
+
+    let sender = changes
+        .iter()
+        .find(|change| {
+            change.mint == mint
+                && change.owner != vault_owner
+                && change.pre_amount.saturating_sub(change.post_amount) >= amount
+        })
+        .map(|change| change.owner.clone())
+        .ok_or_else(|| "Could not find the sending wallet of the deposit".to_string())?;
+
+    Ok(Deposit { sender, amount })
+}
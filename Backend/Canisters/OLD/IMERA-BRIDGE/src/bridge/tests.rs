@@ -0,0 +1,91 @@
+use crate::bridge::{detect_deposit, BridgeStatus, TokenBalanceChange};
+use candid::Principal;
+
+const MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const VAULT: &str = "AAAGuCgkmxYDTiBvzx1QT5XEjqXPRtQaiEXQo4gatD2o";
+const SENDER: &str = "Cra8woRQhnHsGAmFWcCN1m7A9J44ykNfGpehi6dMBuKR";
+
+fn claimant() -> Principal {
+    Principal::from_slice(&[1, 2, 3])
+}
+
+fn deposit_changes(amount: u64) -> Vec<TokenBalanceChange> {
+    vec![
+        TokenBalanceChange {
+            mint: MINT.to_string(),
+            owner: SENDER.to_string(),
+            pre_amount: 5_000,
+            post_amount: 5_000 - amount,
+        },
+        TokenBalanceChange {
+            mint: MINT.to_string(),
+            owner: VAULT.to_string(),
+            pre_amount: 100,
+            post_amount: 100 + amount,
+        },
+    ]
+}
+
+fn memo_logs(principal: Principal) -> Vec<String> {
+    vec![format!(
+        "Program log: Memo (len 34): \"bikera:{principal}\""
+    )]
+}
+
+#[test]
+fn should_follow_bridge_state_machine() {
+    use BridgeStatus::*;
+
+    assert!(Pending.can_transition_to(Locked));
+    assert!(Locked.can_transition_to(Submitted));
+    assert!(Submitted.can_transition_to(Completed));
+    assert!(Submitted.can_transition_to(Failed));
+    assert!(Failed.can_transition_to(Refunded));
+
+    assert!(!Completed.can_transition_to(Refunded));
+    assert!(!Refunded.can_transition_to(Pending));
+    assert!(!Pending.can_transition_to(Submitted));
+    assert!(!Locked.can_transition_to(Completed));
+}
+
+#[test]
+fn should_detect_deposit_into_vault() {
+    let deposit = detect_deposit(
+        &deposit_changes(1_234),
+        &memo_logs(claimant()),
+        MINT,
+        VAULT,
+        claimant(),
+    )
+    .unwrap();
+
+    assert_eq!(deposit.amount, 1_234);
+    assert_eq!(deposit.sender, SENDER);
+}
+
+#[test]
+fn should_reject_deposit_without_claimant_memo() {
+    let other = Principal::from_slice(&[9, 9, 9]);
+
+    assert!(detect_deposit(
+        &deposit_changes(1_234),
+        &memo_logs(other),
+        MINT,
+        VAULT,
+        claimant()
+    )
+    .is_err());
+    assert!(detect_deposit(&deposit_changes(1_234), &[], MINT, VAULT, claimant()).is_err());
+}
+
+#[test]
+fn should_reject_deposit_of_other_mint() {
+    assert!(detect_deposit(
+        &deposit_changes(1_234),
+        &memo_logs(claimant()),
+        "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb",
+        VAULT,
+        claimant()
+    )
+    .is_err());
+}
@@ -1,7 +1,10 @@
+pub mod bridge;
 mod ed25519;
+mod memory;
 pub mod solana_wallet;
 pub mod spl;
 pub mod state;
+pub mod transactions;
 
 use crate::state::{read_state, State};
 use candid::{CandidType, Deserialize, Principal};
@@ -34,6 +37,8 @@ pub struct InitArg {
     pub solana_network: Option<SolanaNetwork>,
     pub ed25519_key_name: Option<Ed25519KeyName>,
     pub solana_commitment_level: Option<CommitmentLevel>,
+    pub bikera_token_canister_id: Option<Principal>,
+    pub imera_spl_mint: Option<String>,
 }
 
 #[derive(CandidType, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
@@ -1,29 +1,44 @@
 use basic_solana::{
-    client, solana_wallet::SolanaWallet, spl, state::init_state, validate_caller_not_anonymous,
-    InitArg,
+    bridge::{
+        self,
+        ledger::{self, TransferArg, TransferFromArgs},
+        BridgeDirection, BridgeEvent, BridgeStatus, BridgeToSolanaArgs, BridgeTransfer,
+        TokenBalanceChange, TransferGuard,
+    },
+    client,
+    solana_wallet::SolanaWallet,
+    spl,
+    state::{init_state, read_state, restore_state},
+    transactions, validate_caller_not_anonymous, InitArg,
 };
 use candid::{Nat, Principal};
-use ic_cdk::{init, post_upgrade, update};
+use ic_cdk::{init, post_upgrade, query, update};
 use num::ToPrimitive;
 use sol_rpc_client::nonce::nonce_from_account;
-use sol_rpc_types::{GetAccountInfoEncoding, GetAccountInfoParams, TokenAmount};
+use sol_rpc_types::{
+    GetAccountInfoEncoding, GetAccountInfoParams, TokenAmount, TransactionConfirmationStatus,
+    TransactionStatusMeta,
+};
 use solana_hash::Hash;
-use solana_message::Message;
 use solana_pubkey::Pubkey;
-use solana_system_interface::instruction;
+use solana_signature::Signature;
 use solana_transaction::Transaction;
 use std::str::FromStr;
 
+/// How long a submitted SPL release may stay unseen on Solana before it is considered expired
+/// and refunded. A blockhash is only valid for about 150 slots (~1 minute).
+const SUBMISSION_TIMEOUT_NANOS: u64 = 5 * 60 * 1_000_000_000;
+
 #[init]
 pub fn init(init_arg: InitArg) {
     init_state(init_arg)
 }
 
+/// The state survives upgrades in stable memory. Fields set in the optional argument replace
+/// the stored ones; fields left out keep their current value.
 #[post_upgrade]
-fn post_upgrade(init_arg: Option<InitArg>) {
-    if let Some(init_arg) = init_arg {
-        init_state(init_arg)
-    }
+fn post_upgrade(upgrade_arg: Option<InitArg>) {
+    restore_state(upgrade_arg)
 }
 
 #[update]
@@ -129,16 +144,10 @@ pub async fn create_nonce_account(owner: Option<Principal>) -> String {
         return nonce_account.as_ref().to_string();
     }
 
-    let instructions = instruction::create_nonce_account(
+    let message = transactions::create_nonce_account_message(
         payer.as_ref(),
         nonce_account.as_ref(),
-        payer.as_ref(),
         1_500_000,
-    );
-
-    let message = Message::new_with_blockhash(
-        instructions.as_slice(),
-        Some(payer.as_ref()),
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
 
@@ -175,12 +184,9 @@ pub async fn create_associated_token_account(
     let payer = wallet.solana_account();
     let mint = Pubkey::from_str(&mint_account).unwrap();
 
-    let (associated_token_account, instruction) = spl::create_associated_token_account_instruction(
-        payer.as_ref(),
-        payer.as_ref(),
-        &mint,
-        &get_account_owner(&mint).await,
-    );
+    let token_program = get_account_owner(&mint).await;
+    let associated_token_account =
+        spl::get_associated_token_address(payer.as_ref(), &mint, &token_program);
 
     if let Some(_account) = client
         .get_account_info(associated_token_account)
@@ -199,9 +205,11 @@ pub async fn create_associated_token_account(
         return associated_token_account.to_string();
     }
 
-    let message = Message::new_with_blockhash(
-        &[instruction],
-        Some(payer.as_ref()),
+    let (_, message) = transactions::create_associated_token_account_message(
+        payer.as_ref(),
+        payer.as_ref(),
+        &mint,
+        &token_program,
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
 
@@ -237,11 +245,10 @@ pub async fn send_sol(owner: Option<Principal>, to: String, amount: Nat) -> Stri
         "Instruction to transfer {amount} lamports from {} to {recipient}",
         payer.as_ref()
     );
-    let instruction = instruction::transfer(payer.as_ref(), &recipient, amount);
-
-    let message = Message::new_with_blockhash(
-        &[instruction],
-        Some(payer.as_ref()),
+    let message = transactions::transfer_sol_message(
+        payer.as_ref(),
+        &recipient,
+        amount,
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
     let signatures = vec![payer.sign_message(&message).await];
@@ -275,14 +282,15 @@ pub async fn send_sol_with_durable_nonce(
     let amount = amount.0.to_u64().unwrap();
     let nonce_account = wallet.derived_nonce_account();
 
-    let instructions = &[
-        instruction::advance_nonce_account(nonce_account.as_ref(), payer.as_ref()),
-        instruction::transfer(payer.as_ref(), &recipient, amount),
-    ];
-
-    let blockhash = Hash::from(get_nonce(Some(nonce_account.as_ref().into())).await);
+    let durable_nonce = Hash::from(get_nonce(Some(nonce_account.as_ref().into())).await);
 
-    let message = Message::new_with_blockhash(instructions, Some(payer.as_ref()), &blockhash);
+    let message = transactions::transfer_sol_with_durable_nonce_message(
+        payer.as_ref(),
+        nonce_account.as_ref(),
+        &recipient,
+        amount,
+        &durable_nonce,
+    );
     let signatures = vec![payer.sign_message(&message).await];
     let transaction = Transaction {
         message,
@@ -317,20 +325,12 @@ pub async fn send_spl_token(
 
     let token_program = get_account_owner(&mint).await;
 
-    let from = spl::get_associated_token_address(payer.as_ref(), &mint, &token_program);
-    let to = spl::get_associated_token_address(&recipient, &mint, &token_program);
-
-    let instruction = spl::transfer_instruction_with_program_id(
-        &from,
-        &to,
+    let message = transactions::transfer_spl_token_message(
         payer.as_ref(),
-        amount,
+        &recipient,
+        &mint,
         &token_program,
-    );
-
-    let message = Message::new_with_blockhash(
-        &[instruction],
-        Some(payer.as_ref()),
+        amount,
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
     let signatures = vec![payer.sign_message(&message).await];
@@ -348,17 +348,476 @@ pub async fn send_spl_token(
         .to_string()
 }
 
+/// Bridges iMERA from the IC to Solana.
+///
+/// The caller must first approve the bridge canister on `bikera_token` (ICRC-2) for `amount`
+/// plus the ledger fee. The iMERA is locked in the bridge's custody account and the same amount
+/// of the SPL token is released from the bridge vault to `solana_wallet`.
+#[update]
+pub async fn bridge_to_solana(args: BridgeToSolanaArgs) -> Result<BridgeTransfer, String> {
+    let owner = validate_caller_not_anonymous();
+    let ledger_id = bikera_token_canister_id()?;
+    let mint = imera_spl_mint()?;
+    let recipient = Pubkey::from_str(&args.solana_wallet)
+        .map_err(|e| format!("Invalid Solana wallet {}: {e}", args.solana_wallet))?;
+    let amount = args
+        .amount
+        .0
+        .to_u64()
+        .ok_or("Amount does not fit in a u64 SPL amount")?;
+    if amount == 0 {
+        return Err("Amount must be positive".to_string());
+    }
+    let vault_account = vault_token_account(&mint).await?;
+    if vault_account.balance < amount {
+        return Err(format!(
+            "Bridge vault holds {} base units, cannot release {amount}",
+            vault_account.balance
+        ));
+    }
+
+    bridge::consume_nonce(owner, args.nonce)?;
+    let id = bridge::create_transfer(
+        BridgeDirection::IcToSolana,
+        owner,
+        Some(args.nonce),
+        args.amount.clone(),
+        args.solana_wallet,
+        None,
+        ic_cdk::api::time(),
+    );
+    let _guard = TransferGuard::new(id)?;
+
+    // 1. Lock the iMERA in the bridge's custody account
+    let lock = ledger::icrc2_transfer_from(
+        ledger_id,
+        TransferFromArgs {
+            spender_subaccount: None,
+            from: owner.into(),
+            to: ic_cdk::id().into(),
+            amount: args.amount,
+            fee: None,
+            memo: Some(bridge::ledger_memo(id)),
+            created_at_time: None,
+        },
+    )
+    .await;
+    match lock {
+        Ok(block_index) => bridge::advance(id, BridgeStatus::Locked, ic_cdk::api::time(), |t| {
+            t.ic_block_index = Some(block_index)
+        }),
+        Err(reason) => {
+            bridge::advance(id, BridgeStatus::Failed, ic_cdk::api::time(), |t| {
+                t.failure_reason = Some(reason.clone())
+            });
+            return Err(reason);
+        }
+    }
+
+    // 2. Release the SPL token from the bridge vault. The transfer is `Submitted` as soon as the
+    //    transaction is signed; `confirm_bridge_transfer` settles it or refunds it.
+    if let Err(reason) = release_spl(id, &mint, &vault_account, &recipient, amount).await {
+        fail_and_refund(id, reason.clone()).await?;
+        return Err(reason);
+    }
+
+    Ok(bridge::get_transfer(id).expect("bridge transfer was just created"))
+}
+
+/// Claims a deposit of the SPL token into the bridge vault and releases the same amount of
+/// iMERA (minus the ledger fee) to the caller.
+///
+/// The deposit transaction must be finalized and carry the SPL memo `bikera:<caller principal>`.
+#[update]
+pub async fn claim_solana_deposit(signature: String) -> Result<BridgeTransfer, String> {
+    let owner = validate_caller_not_anonymous();
+    let mint = imera_spl_mint()?;
+    if let Some(id) = bridge::claimed_deposit(&signature) {
+        return Err(format!(
+            "Deposit {signature} was already claimed as bridge transfer {id}"
+        ));
+    }
+
+    let parsed_signature =
+        Signature::from_str(&signature).map_err(|e| format!("Invalid signature: {e}"))?;
+    let transaction = client()
+        .get_transaction(parsed_signature)
+        .send()
+        .await
+        .expect_consistent()
+        .map_err(|e| format!("Call to `getTransaction` failed: {e:?}"))?
+        .ok_or_else(|| format!("Transaction {signature} not found or not finalized"))?;
+    let meta = transaction
+        .transaction
+        .meta
+        .ok_or_else(|| format!("Transaction {signature} has no status metadata"))?;
+    if let Some(err) = meta.err.as_ref() {
+        return Err(format!("Transaction {signature} failed on Solana: {err:?}"));
+    }
+
+    let vault = SolanaWallet::new(ic_cdk::id()).await.solana_account();
+    let deposit = bridge::detect_deposit(
+        &token_balance_changes(&meta),
+        meta.log_messages.as_deref().unwrap_or_default(),
+        &mint.to_string(),
+        &vault.to_string(),
+        owner,
+    )?;
+
+    // Recheck after the awaits above: another claim of the same signature may have won the race
+    if let Some(id) = bridge::claimed_deposit(&signature) {
+        return Err(format!(
+            "Deposit {signature} was already claimed as bridge transfer {id}"
+        ));
+    }
+    let id = bridge::create_transfer(
+        BridgeDirection::SolanaToIc,
+        owner,
+        None,
+        Nat::from(deposit.amount),
+        deposit.sender,
+        Some(signature),
+        ic_cdk::api::time(),
+    );
+    let _guard = TransferGuard::new(id)?;
+
+    release_imera(id).await?;
+    Ok(bridge::get_transfer(id).expect("bridge transfer was just created"))
+}
+
+/// Drives an unfinished bridge transfer forward:
+/// * a submitted SPL release is completed once finalized, or refunded if it failed or expired;
+/// * a locked IC → Solana transfer whose release was never signed is released again, or
+///   refunded if the release fails;
+/// * a failed IC → Solana transfer whose iMERA was locked is refunded;
+/// * a claimed deposit whose iMERA release failed is retried.
+#[update]
+pub async fn confirm_bridge_transfer(id: u64) -> Result<BridgeTransfer, String> {
+    let transfer = bridge::get_transfer(id).ok_or(format!("Unknown bridge transfer {id}"))?;
+    let _guard = TransferGuard::new(id)?;
+
+    match (transfer.direction, transfer.status) {
+        (BridgeDirection::IcToSolana, BridgeStatus::Submitted) => {
+            let signature = transfer
+                .solana_signature
+                .as_deref()
+                .expect("submitted transfer has a signature");
+            let status = client()
+                .get_signature_statuses(&[Signature::from_str(signature).unwrap()])
+                .send()
+                .await
+                .expect_consistent()
+                .map_err(|e| format!("Call to `getSignatureStatuses` failed: {e:?}"))?
+                .into_iter()
+                .next()
+                .flatten();
+            let now = ic_cdk::api::time();
+            match status {
+                Some(status) if status.err.is_some() => {
+                    bridge::advance(id, BridgeStatus::Failed, now, |t| {
+                        t.failure_reason = Some(format!("SPL release failed: {:?}", status.err))
+                    });
+                    refund(id).await?;
+                }
+                Some(status)
+                    if status.confirmation_status
+                        == Some(TransactionConfirmationStatus::Finalized) =>
+                {
+                    bridge::advance(id, BridgeStatus::Completed, now, |_| {});
+                }
+                None if now.saturating_sub(transfer.updated_at) > SUBMISSION_TIMEOUT_NANOS => {
+                    bridge::advance(id, BridgeStatus::Failed, now, |t| {
+                        t.failure_reason = Some("SPL release expired".to_string())
+                    });
+                    refund(id).await?;
+                }
+                // Not finalized yet
+                _ => {}
+            }
+        }
+        (BridgeDirection::IcToSolana, BridgeStatus::Locked) => {
+            // No signature was recorded, so nothing was sent to Solana and the release can be
+            // retried without paying out twice
+            if let Err(reason) = release_locked(&transfer).await {
+                fail_and_refund(id, reason).await?;
+            }
+        }
+        (BridgeDirection::IcToSolana, BridgeStatus::Failed)
+            if transfer.ic_block_index.is_some() =>
+        {
+            refund(id).await?;
+        }
+        (BridgeDirection::SolanaToIc, BridgeStatus::Pending) => {
+            release_imera(id).await?;
+        }
+        _ => {}
+    }
+
+    Ok(bridge::get_transfer(id).expect("bridge transfer exists"))
+}
+
+#[query]
+pub fn get_bridge_transfer(id: u64) -> Option<BridgeTransfer> {
+    bridge::get_transfer(id)
+}
+
+/// Bridge transfers of `owner` (the caller if omitted), newest first.
+#[query]
+pub fn list_bridge_transfers(
+    owner: Option<Principal>,
+    offset: u64,
+    limit: u64,
+) -> Vec<BridgeTransfer> {
+    let owner = owner.unwrap_or_else(ic_cdk::caller);
+    bridge::transfers_of(owner, offset as usize, limit.min(100) as usize)
+}
+
+#[query]
+pub fn list_unfinished_bridge_transfers() -> Vec<BridgeTransfer> {
+    bridge::unfinished_transfers()
+}
+
+#[query]
+pub fn get_bridge_events(start: u64, limit: u64) -> Vec<BridgeEvent> {
+    bridge::events(start, limit.min(1_000))
+}
+
+/// Next nonce `owner` (the caller if omitted) must use in `bridge_to_solana`.
+#[query]
+pub fn bridge_nonce(owner: Option<Principal>) -> u64 {
+    bridge::next_nonce(owner.unwrap_or_else(ic_cdk::caller))
+}
+
+/// Solana address of the bridge vault, which holds the SPL tokens released to users and
+/// receives their deposits.
+#[update]
+pub async fn bridge_vault_address() -> String {
+    SolanaWallet::new(ic_cdk::id())
+        .await
+        .solana_account()
+        .to_string()
+}
+
+fn bikera_token_canister_id() -> Result<Principal, String> {
+    read_state(|s| s.bikera_token_canister_id())
+        .ok_or_else(|| "Bridge is not configured: missing `bikera_token_canister_id`".to_string())
+}
+
+fn imera_spl_mint() -> Result<Pubkey, String> {
+    let mint = read_state(|s| s.imera_spl_mint())
+        .ok_or_else(|| "Bridge is not configured: missing `imera_spl_mint`".to_string())?;
+    Pubkey::from_str(&mint).map_err(|e| format!("Invalid `imera_spl_mint` {mint}: {e}"))
+}
+
+/// Token account of the bridge vault for the iMERA SPL mint.
+struct VaultTokenAccount {
+    token_program: Pubkey,
+    decimals: u8,
+    balance: u64,
+}
+
+/// Looks up the token program owning `mint` (legacy Token or Token-2022) and the decimals and
+/// balance of the vault's associated token account.
+async fn vault_token_account(mint: &Pubkey) -> Result<VaultTokenAccount, String> {
+    let token_program = try_get_account_owner(mint).await?;
+    if !spl::is_token_program(&token_program) {
+        return Err(format!(
+            "Mint {mint} is owned by {token_program}, which is not a token program"
+        ));
+    }
+    let vault = SolanaWallet::new(ic_cdk::id()).await.solana_account();
+    let vault_ata = spl::get_associated_token_address(vault.as_ref(), mint, &token_program);
+    let balance: TokenAmount = client()
+        .get_token_account_balance(vault_ata)
+        .send()
+        .await
+        .expect_consistent()
+        .map_err(|e| format!("Bridge vault token account {vault_ata} unavailable: {e:?}"))?
+        .into();
+    Ok(VaultTokenAccount {
+        token_program,
+        decimals: balance.decimals,
+        balance: balance.amount.parse().unwrap_or_default(),
+    })
+}
+
+/// Signs and sends the SPL release of an IC → Solana transfer from the bridge vault, creating
+/// the recipient's associated token account if needed. The transaction carries an SPL memo
+/// with the ledger block index of the locked iMERA.
+///
+/// An error means nothing was signed: the transfer is still `Locked` and must be failed and
+/// refunded. Once signed, the transfer is `Submitted` even if sending it failed, since only
+/// Solana can tell whether it landed.
+async fn release_spl(
+    id: u64,
+    mint: &Pubkey,
+    vault_account: &VaultTokenAccount,
+    recipient: &Pubkey,
+    amount: u64,
+) -> Result<(), String> {
+    let client = client();
+    let vault = SolanaWallet::new(ic_cdk::id()).await.solana_account();
+    let ic_block_index = bridge::get_transfer(id)
+        .and_then(|t| t.ic_block_index)
+        .and_then(|block_index| block_index.0.to_u64())
+        .ok_or_else(|| format!("Bridge transfer {id} has no u64 lock block index"))?;
+    let recent_blockhash = client
+        .estimate_recent_blockhash()
+        .send()
+        .await
+        .map_err(|e| format!("Estimating a recent blockhash failed: {e:?}"))?;
+
+    let message = transactions::release_spl_token_message(
+        vault.as_ref(),
+        recipient,
+        mint,
+        &vault_account.token_program,
+        amount,
+        vault_account.decimals,
+        &transactions::bridge_release_memo(id, ic_block_index),
+        &recent_blockhash,
+    );
+    let signatures = vec![vault.sign_message(&message).await];
+    let transaction = Transaction {
+        message,
+        signatures,
+    };
+
+    // Record the signature before sending so the outcome can always be looked up on Solana
+    let signature = transaction.signatures[0].to_string();
+    bridge::advance(id, BridgeStatus::Submitted, ic_cdk::api::time(), |t| {
+        t.solana_signature = Some(signature.clone())
+    });
+
+    if let Err(e) = client
+        .send_transaction(transaction)
+        .send()
+        .await
+        .expect_consistent()
+    {
+        ic_cdk::println!(
+            "[release_spl]: sending {signature} for bridge transfer {id} failed: {e:?}"
+        );
+    }
+    Ok(())
+}
+
+/// Retries the SPL release of a transfer left `Locked`, e.g. because a call trapped before
+/// the release was signed.
+async fn release_locked(transfer: &BridgeTransfer) -> Result<(), String> {
+    let mint = imera_spl_mint()?;
+    let recipient = Pubkey::from_str(&transfer.solana_wallet)
+        .map_err(|e| format!("Invalid Solana wallet {}: {e}", transfer.solana_wallet))?;
+    let amount = transfer
+        .amount
+        .0
+        .to_u64()
+        .ok_or("Amount does not fit in a u64 SPL amount")?;
+    let vault_account = vault_token_account(&mint).await?;
+    if vault_account.balance < amount {
+        return Err(format!(
+            "Bridge vault holds {} base units, cannot release {amount}",
+            vault_account.balance
+        ));
+    }
+    release_spl(transfer.id, &mint, &vault_account, &recipient, amount).await
+}
+
+/// Fails a locked IC → Solana transfer whose SPL release could not be signed and refunds it.
+/// If the refund fails, `confirm_bridge_transfer` retries it.
+async fn fail_and_refund(id: u64, reason: String) -> Result<(), String> {
+    bridge::advance(id, BridgeStatus::Failed, ic_cdk::api::time(), |t| {
+        t.failure_reason = Some(format!("SPL release failed: {reason}"))
+    });
+    refund(id).await
+}
+
+/// Returns the locked iMERA of a failed IC → Solana transfer to its owner, minus the ledger fee.
+async fn refund(id: u64) -> Result<(), String> {
+    let transfer = bridge::get_transfer(id).expect("bridge transfer exists");
+    let block_index = transfer_from_custody(id, transfer.owner, transfer.amount).await?;
+    bridge::advance(id, BridgeStatus::Refunded, ic_cdk::api::time(), |t| {
+        t.refund_block_index = Some(block_index)
+    });
+    Ok(())
+}
+
+/// Releases the iMERA of a claimed Solana deposit to its owner, minus the ledger fee.
+async fn release_imera(id: u64) -> Result<(), String> {
+    let transfer = bridge::get_transfer(id).expect("bridge transfer exists");
+    let block_index = transfer_from_custody(id, transfer.owner, transfer.amount).await?;
+    bridge::advance(id, BridgeStatus::Completed, ic_cdk::api::time(), |t| {
+        t.ic_block_index = Some(block_index)
+    });
+    Ok(())
+}
+
+async fn transfer_from_custody(id: u64, owner: Principal, amount: Nat) -> Result<Nat, String> {
+    let ledger_id = bikera_token_canister_id()?;
+    let fee = ledger::icrc1_fee(ledger_id).await?;
+    if amount <= fee {
+        return Err(format!(
+            "Amount {amount} does not cover the ledger fee {fee}"
+        ));
+    }
+    ledger::icrc1_transfer(
+        ledger_id,
+        TransferArg {
+            from_subaccount: None,
+            to: owner.into(),
+            amount: amount - fee.clone(),
+            fee: Some(fee),
+            memo: Some(bridge::ledger_memo(id)),
+            created_at_time: None,
+        },
+    )
+    .await
+}
+
+/// Flattens the pre/post token balances of a transaction into per-account changes.
+fn token_balance_changes(meta: &TransactionStatusMeta) -> Vec<TokenBalanceChange> {
+    let pre = meta.pre_token_balances.clone().unwrap_or_default();
+    let post = meta.post_token_balances.clone().unwrap_or_default();
+    let amount_of = |amount: &TokenAmount| amount.amount.parse::<u64>().unwrap_or_default();
+
+    post.iter()
+        .map(|post_balance| {
+            let pre_amount = pre
+                .iter()
+                .find(|pre_balance| pre_balance.account_index == post_balance.account_index)
+                .map(|pre_balance| amount_of(&pre_balance.ui_token_amount))
+                .unwrap_or_default();
+            TokenBalanceChange {
+                mint: post_balance.mint.to_string(),
+                owner: post_balance
+                    .owner
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_default(),
+                pre_amount,
+                post_amount: amount_of(&post_balance.ui_token_amount),
+            }
+        })
+        .collect()
+}
+
 async fn get_account_owner(account: &Pubkey) -> Pubkey {
+    try_get_account_owner(account)
+        .await
+        .unwrap_or_else(|e| panic!("{e}"))
+}
+
+async fn try_get_account_owner(account: &Pubkey) -> Result<Pubkey, String> {
     let owner = client()
         .get_account_info(*account)
         .with_encoding(GetAccountInfoEncoding::Base64)
         .send()
         .await
         .expect_consistent()
-        .expect("Call to `getAccountInfo` failed")
-        .unwrap_or_else(|| panic!("Account not found for pubkey `{account}`"))
+        .map_err(|e| format!("Call to `getAccountInfo` failed: {e:?}"))?
+        .ok_or_else(|| format!("Account not found for pubkey `{account}`"))?
         .owner;
-    Pubkey::from_str(&owner).unwrap()
+    Pubkey::from_str(&owner).map_err(|e| format!("Invalid owner {owner} of `{account}`: {e}"))
 }
 
 fn main() {}
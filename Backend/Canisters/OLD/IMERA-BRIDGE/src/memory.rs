@@ -0,0 +1,24 @@
+use ic_stable_structures::{
+    memory_manager::{MemoryId, MemoryManager, VirtualMemory},
+    DefaultMemoryImpl,
+};
+use std::cell::RefCell;
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+pub const BRIDGE_TRANSFERS_MEMORY_ID: MemoryId = MemoryId::new(0);
+pub const BRIDGE_EVENTS_INDEX_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub const BRIDGE_EVENTS_DATA_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub const BRIDGE_NONCES_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const CLAIMED_DEPOSITS_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const STATE_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const SCHEMA_REGISTRY_MEMORY_ID: MemoryId = MemoryId::new(6);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+}
+
+pub fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(id))
+}
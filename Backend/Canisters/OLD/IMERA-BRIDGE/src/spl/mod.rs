@@ -16,6 +16,16 @@ pub mod token_program {
 pub mod token_2022_program {
     solana_pubkey::declare_id!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
 }
+pub mod memo_program {
+    solana_pubkey::declare_id!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+}
+
+/// Returns `true` for the legacy Token program and the Token-2022 program, the only owners a
+/// mint handled by this module may have. Associated token accounts of both programs are
+/// derived the same way, with the token program id as part of the seeds.
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    program_id == &token_program::id() || program_id == &token_2022_program::id()
+}
 
 /// Derives the Associated Token Account address for the given mint address.
 /// This implementation was taken from [the associated token account repository](https://github.com/solana-program/associated-token-account/blob/main/interface/src/address.rs).
@@ -62,6 +72,26 @@ pub fn create_associated_token_account_instruction(
     (associated_account_address, instruction)
 }
 
+/// Same as [`create_associated_token_account_instruction`], but uses the `CreateIdempotent`
+/// instruction, which succeeds without changes if the account already exists.
+pub fn create_associated_token_account_idempotent_instruction(
+    funding_address: &Pubkey,
+    wallet_address: &Pubkey,
+    token_mint_address: &Pubkey,
+    token_program_id: &Pubkey,
+) -> (Pubkey, Instruction) {
+    let (associated_account_address, mut instruction) = create_associated_token_account_instruction(
+        funding_address,
+        wallet_address,
+        token_mint_address,
+        token_program_id,
+    );
+    instruction.data = vec![
+        1, // SPL Associated Token Account program "create idempotent" instruction
+    ];
+    (associated_account_address, instruction)
+}
+
 /// Creates an instruction to run the [`Transfer` instruction](https://github.com/solana-program/token/blob/main/interface/src/instruction.rs)
 /// in the SPL Token program.
 pub fn transfer_instruction_with_program_id(
@@ -81,3 +111,43 @@ pub fn transfer_instruction_with_program_id(
         data: [vec![3], amount.to_le_bytes().to_vec()].concat(), // SPL token program "transfer" instruction
     }
 }
+
+/// Creates an instruction to run the [`TransferChecked` instruction](https://github.com/solana-program/token/blob/main/interface/src/instruction.rs)
+/// in the SPL Token or Token-2022 program.
+///
+/// Unlike `Transfer`, the program checks the mint and its decimals, and it is the transfer
+/// instruction supported by Token-2022 mints with extensions.
+pub fn transfer_checked_instruction_with_program_id(
+    source_address: &Pubkey,
+    token_mint_address: &Pubkey,
+    destination_address: &Pubkey,
+    authority_address: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    token_program_id: &Pubkey,
+) -> Instruction {
+    Instruction {
+        program_id: *token_program_id,
+        accounts: vec![
+            AccountMeta::new(*source_address, false),
+            AccountMeta::new_readonly(*token_mint_address, false),
+            AccountMeta::new(*destination_address, false),
+            AccountMeta::new_readonly(*authority_address, true),
+        ],
+        data: [vec![12], amount.to_le_bytes().to_vec(), vec![decimals]].concat(), // SPL token program "transfer checked" instruction
+    }
+}
+
+/// Creates an instruction for the [SPL Memo program](https://github.com/solana-program/memo),
+/// which records `memo` in the transaction logs. Every `signer_address` must sign the
+/// transaction.
+pub fn memo_instruction(memo: &str, signer_addresses: &[&Pubkey]) -> Instruction {
+    Instruction {
+        program_id: memo_program::id(),
+        accounts: signer_addresses
+            .iter()
+            .map(|address| AccountMeta::new_readonly(**address, true))
+            .collect(),
+        data: memo.as_bytes().to_vec(),
+    }
+}
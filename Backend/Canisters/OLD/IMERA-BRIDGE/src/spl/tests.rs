@@ -1,4 +1,8 @@
-use crate::spl::{get_associated_token_address, token_2022_program, token_program};
+use crate::spl::{
+    create_associated_token_account_idempotent_instruction, get_associated_token_address,
+    is_token_program, memo_instruction, memo_program, token_2022_program, token_program,
+    transfer_checked_instruction_with_program_id,
+};
 use solana_pubkey::{pubkey, Pubkey};
 use std::str::FromStr;
 
@@ -40,3 +44,87 @@ fn should_compute_ata_with_token_2022_program() {
         pubkey!("GPtCoaz35vdCrFbyhxcRrkYvECrUkrBX6CoRZEv8EQDw")
     )
 }
+
+#[test]
+fn should_only_accept_token_programs() {
+    assert!(is_token_program(&token_program::id()));
+    assert!(is_token_program(&token_2022_program::id()));
+    assert!(!is_token_program(&memo_program::id()));
+    assert!(!is_token_program(&Pubkey::default()));
+}
+
+#[test]
+fn should_create_token_2022_ata_with_token_2022_program() {
+    let wallet = Pubkey::from_str(WALLET_ADDRESS).unwrap();
+    let mint = Pubkey::from_str(BERN_MINT_ADDRESS).unwrap();
+
+    let (associated_token_address, instruction) =
+        create_associated_token_account_idempotent_instruction(
+            &wallet,
+            &wallet,
+            &mint,
+            &token_2022_program::id(),
+        );
+
+    assert_eq!(
+        associated_token_address,
+        pubkey!("GPtCoaz35vdCrFbyhxcRrkYvECrUkrBX6CoRZEv8EQDw")
+    );
+    assert_eq!(instruction.accounts[1].pubkey, associated_token_address);
+    assert_eq!(instruction.accounts[5].pubkey, token_2022_program::id());
+}
+
+#[test]
+fn should_encode_transfer_checked_instruction() {
+    let wallet = Pubkey::from_str(WALLET_ADDRESS).unwrap();
+    let mint = Pubkey::from_str(BERN_MINT_ADDRESS).unwrap();
+    let source = get_associated_token_address(&wallet, &mint, &token_2022_program::id());
+    let destination = Pubkey::new_from_array([0x11; 32]);
+
+    let instruction = transfer_checked_instruction_with_program_id(
+        &source,
+        &mint,
+        &destination,
+        &wallet,
+        1_234_567,
+        5,
+        &token_2022_program::id(),
+    );
+
+    assert_eq!(instruction.program_id, token_2022_program::id());
+    // Tag 12, little-endian amount, decimals
+    assert_eq!(
+        instruction.data,
+        vec![12, 0x87, 0xd6, 0x12, 0, 0, 0, 0, 0, 5]
+    );
+    let accounts: Vec<_> = instruction
+        .accounts
+        .iter()
+        .map(|meta| (meta.pubkey, meta.is_writable, meta.is_signer))
+        .collect();
+    assert_eq!(
+        accounts,
+        vec![
+            (source, true, false),
+            (mint, false, false),
+            (destination, true, false),
+            (wallet, false, true),
+        ]
+    );
+}
+
+#[test]
+fn should_encode_memo_instruction() {
+    let wallet = Pubkey::from_str(WALLET_ADDRESS).unwrap();
+
+    let instruction = memo_instruction("bikera:bridge:7:ic_block:42", &[&wallet]);
+
+    assert_eq!(
+        instruction.program_id,
+        pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr")
+    );
+    assert_eq!(instruction.data, b"bikera:bridge:7:ic_block:42".to_vec());
+    assert_eq!(instruction.accounts.len(), 1);
+    assert!(instruction.accounts[0].is_signer);
+    assert!(!instruction.accounts[0].is_writable);
+}
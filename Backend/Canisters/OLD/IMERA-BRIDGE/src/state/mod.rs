@@ -0,0 +1,235 @@
+use crate::{
+    ed25519::{get_ed25519_public_key, Ed25519ExtendedPublicKey},
+    memory::{get_memory, Memory, SCHEMA_REGISTRY_MEMORY_ID, STATE_MEMORY_ID},
+    Ed25519KeyName, InitArg, SolanaNetwork,
+};
+use candid::{CandidType, Deserialize, Principal};
+use ic_stable_structures::StableCell;
+use sol_rpc_types::CommitmentLevel;
+use stable_schema::{SchemaRegistry, Versioned};
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+};
+
+#[cfg(test)]
+mod tests;
+
+thread_local! {
+    /// Working copy of the state. Every mutation is written through to `STABLE_STATE`.
+    pub static STATE: RefCell<State> = RefCell::default();
+
+    static STABLE_STATE: RefCell<StableCell<StoredState, Memory>> = RefCell::new(
+        StableCell::init(get_memory(STATE_MEMORY_ID), StoredState::Uninitialized)
+            .expect("failed to initialize the stable state cell")
+    );
+
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> = RefCell::new(
+        SchemaRegistry::init(get_memory(SCHEMA_REGISTRY_MEMORY_ID))
+    );
+}
+
+pub fn init_state(init_arg: InitArg) {
+    mutate_state(|s| *s = State::from(init_arg));
+}
+
+/// Restores the state from stable memory and applies the fields set in `upgrade_arg` on top.
+///
+/// Canisters upgraded from a version that kept the state on the heap only have
+/// `StoredState::Uninitialized`; they start from the defaults, like a fresh `init`.
+pub fn restore_state(upgrade_arg: Option<InitArg>) {
+    migrate_stable_schema();
+    let stored = STABLE_STATE.with(|s| s.borrow().get().clone());
+    let mut state = match stored.into_latest() {
+        Some(stored) => State::from(stored),
+        None => {
+            ic_cdk::println!("[restore_state]: no stable state found, starting from defaults");
+            State::default()
+        }
+    };
+    if let Some(upgrade_arg) = upgrade_arg {
+        state.apply_upgrade(upgrade_arg);
+    }
+    mutate_state(|s| *s = state);
+}
+
+/// Rewrites the state cell once per `StoredState` envelope version. The bridge transfers and
+/// events grow with usage and are migrated on read instead.
+fn migrate_stable_schema() {
+    let migrated = SCHEMA_REGISTRY.with(|registry| {
+        registry.borrow_mut().migrate::<StoredState>("state", || {
+            STABLE_STATE.with(|s| stable_schema::rewrite_cell(&mut s.borrow_mut()))
+        })
+    });
+    if let Some(count) = migrated {
+        ic_cdk::println!(
+            "[migrate_stable_schema]: migrated {count} state to schema v{}",
+            StoredState::VERSION
+        );
+    }
+}
+
+pub fn read_state<R>(f: impl FnOnce(&State) -> R) -> R {
+    STATE.with(|s| f(s.borrow().deref()))
+}
+
+pub fn mutate_state<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut State) -> R,
+{
+    let result = STATE.with(|s| f(s.borrow_mut().deref_mut()));
+    let stored = read_state(|s| StoredState::V1(StateV1::from(s)));
+    STABLE_STATE.with(|s| {
+        s.borrow_mut()
+            .set(stored)
+            .expect("failed to write the stable state cell")
+    });
+    result
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct State {
+    sol_rpc_canister_id: Option<Principal>,
+    solana_network: SolanaNetwork,
+    solana_commitment_level: CommitmentLevel,
+    ed25519_public_key: Option<Ed25519ExtendedPublicKey>,
+    ed25519_key_name: Ed25519KeyName,
+    bikera_token_canister_id: Option<Principal>,
+    imera_spl_mint: Option<String>,
+}
+
+impl State {
+    pub fn ed25519_key_name(&self) -> Ed25519KeyName {
+        self.ed25519_key_name
+    }
+
+    pub fn solana_network(&self) -> &SolanaNetwork {
+        &self.solana_network
+    }
+
+    pub fn solana_commitment_level(&self) -> CommitmentLevel {
+        self.solana_commitment_level.clone()
+    }
+
+    pub fn sol_rpc_canister_id(&self) -> Option<Principal> {
+        self.sol_rpc_canister_id
+    }
+
+    pub fn bikera_token_canister_id(&self) -> Option<Principal> {
+        self.bikera_token_canister_id
+    }
+
+    pub fn imera_spl_mint(&self) -> Option<String> {
+        self.imera_spl_mint.clone()
+    }
+
+    /// Overwrites only the fields that are set in `upgrade_arg`.
+    pub fn apply_upgrade(&mut self, upgrade_arg: InitArg) {
+        if let Some(sol_rpc_canister_id) = upgrade_arg.sol_rpc_canister_id {
+            self.sol_rpc_canister_id = Some(sol_rpc_canister_id);
+        }
+        if let Some(solana_network) = upgrade_arg.solana_network {
+            self.solana_network = solana_network;
+        }
+        if let Some(solana_commitment_level) = upgrade_arg.solana_commitment_level {
+            self.solana_commitment_level = solana_commitment_level;
+        }
+        if let Some(ed25519_key_name) = upgrade_arg.ed25519_key_name {
+            if ed25519_key_name != self.ed25519_key_name {
+                // The cached key belongs to the previous key name
+                self.ed25519_public_key = None;
+            }
+            self.ed25519_key_name = ed25519_key_name;
+        }
+        if let Some(bikera_token_canister_id) = upgrade_arg.bikera_token_canister_id {
+            self.bikera_token_canister_id = Some(bikera_token_canister_id);
+        }
+        if let Some(imera_spl_mint) = upgrade_arg.imera_spl_mint {
+            self.imera_spl_mint = Some(imera_spl_mint);
+        }
+    }
+}
+
+impl From<InitArg> for State {
+    fn from(init_arg: InitArg) -> Self {
+        State {
+            sol_rpc_canister_id: init_arg.sol_rpc_canister_id,
+            solana_network: init_arg.solana_network.unwrap_or_default(),
+            solana_commitment_level: init_arg.solana_commitment_level.unwrap_or_default(),
+            ed25519_public_key: None,
+            ed25519_key_name: init_arg.ed25519_key_name.unwrap_or_default(),
+            bikera_token_canister_id: init_arg.bikera_token_canister_id,
+            imera_spl_mint: init_arg.imera_spl_mint,
+        }
+    }
+}
+
+/// Versioned schema of the state kept in stable memory.
+///
+/// Never change a version in place: add a `V2`, convert from `V1` in
+/// [`StoredState::into_latest`] and store only the newest version.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum StoredState {
+    Uninitialized,
+    V1(StateV1),
+}
+
+impl StoredState {
+    pub fn into_latest(self) -> Option<StateV1> {
+        match self {
+            StoredState::Uninitialized => None,
+            StoredState::V1(state) => Some(state),
+        }
+    }
+}
+
+/// The ed25519 public key is not stored: it is a cache that is fetched again on first use.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct StateV1 {
+    pub sol_rpc_canister_id: Option<Principal>,
+    pub solana_network: SolanaNetwork,
+    pub solana_commitment_level: CommitmentLevel,
+    pub ed25519_key_name: Ed25519KeyName,
+    pub bikera_token_canister_id: Option<Principal>,
+    pub imera_spl_mint: Option<String>,
+}
+
+impl From<&State> for StateV1 {
+    fn from(state: &State) -> Self {
+        StateV1 {
+            sol_rpc_canister_id: state.sol_rpc_canister_id,
+            solana_network: state.solana_network.clone(),
+            solana_commitment_level: state.solana_commitment_level.clone(),
+            ed25519_key_name: state.ed25519_key_name,
+            bikera_token_canister_id: state.bikera_token_canister_id,
+            imera_spl_mint: state.imera_spl_mint.clone(),
+        }
+    }
+}
+
+impl From<StateV1> for State {
+    fn from(stored: StateV1) -> Self {
+        State {
+            sol_rpc_canister_id: stored.sol_rpc_canister_id,
+            solana_network: stored.solana_network,
+            solana_commitment_level: stored.solana_commitment_level,
+            ed25519_public_key: None,
+            ed25519_key_name: stored.ed25519_key_name,
+            bikera_token_canister_id: stored.bikera_token_canister_id,
+            imera_spl_mint: stored.imera_spl_mint,
+        }
+    }
+}
+
+// The envelope versions the encoding of the cell; `StoredState` versions the state itself
+stable_schema::versioned_since_raw_candid!(StoredState, "basic_solana::StoredState");
+
+pub async fn lazy_call_ed25519_public_key() -> Ed25519ExtendedPublicKey {
+    if let Some(public_key) = read_state(|s| s.ed25519_public_key.clone()) {
+        return public_key;
+    }
+    let public_key =
+        get_ed25519_public_key(read_state(|s| s.ed25519_key_name()), &Default::default()).await;
+    mutate_state(|s| s.ed25519_public_key = Some(public_key.clone()));
+    public_key
+}
@@ -0,0 +1,105 @@
+use crate::ed25519::Ed25519ExtendedPublicKey;
+use crate::state::{read_state, restore_state, State, StateV1, StoredState};
+use crate::{Ed25519KeyName, InitArg, SolanaNetwork};
+use candid::Principal;
+use ic_ed25519::PublicKey;
+use ic_stable_structures::Storable;
+use sol_rpc_types::CommitmentLevel;
+
+const IMERA_SPL_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+
+fn init_arg() -> InitArg {
+    InitArg {
+        sol_rpc_canister_id: Some(Principal::from_slice(&[1; 10])),
+        solana_network: Some(SolanaNetwork::Mainnet),
+        ed25519_key_name: Some(Ed25519KeyName::MainnetProdKey1),
+        solana_commitment_level: Some(CommitmentLevel::Finalized),
+        bikera_token_canister_id: Some(Principal::from_slice(&[2; 10])),
+        imera_spl_mint: Some(IMERA_SPL_MINT.to_string()),
+    }
+}
+
+fn cached_public_key() -> Ed25519ExtendedPublicKey {
+    // Encoding of the ed25519 base point
+    let mut base_point = [0x66; 32];
+    base_point[0] = 0x58;
+    Ed25519ExtendedPublicKey {
+        public_key: PublicKey::deserialize_raw(&base_point).unwrap(),
+        chain_code: [7; 32],
+    }
+}
+
+#[test]
+fn should_keep_unset_fields_on_upgrade() {
+    let mut state = State::from(init_arg());
+    state.ed25519_public_key = Some(cached_public_key());
+    let before = StateV1::from(&state);
+
+    state.apply_upgrade(InitArg::default());
+
+    assert_eq!(StateV1::from(&state), before);
+    assert_eq!(state.ed25519_public_key, Some(cached_public_key()));
+}
+
+#[test]
+fn should_replace_set_fields_on_upgrade() {
+    let mut state = State::from(init_arg());
+
+    state.apply_upgrade(InitArg {
+        sol_rpc_canister_id: Some(Principal::from_slice(&[3; 10])),
+        solana_network: Some(SolanaNetwork::Devnet),
+        ed25519_key_name: None,
+        solana_commitment_level: Some(CommitmentLevel::Confirmed),
+        bikera_token_canister_id: Some(Principal::from_slice(&[4; 10])),
+        imera_spl_mint: Some("CKfatsPMUf8SkiURsDXs7eK6GWb4Jsd6UDbs7twMCWxo".to_string()),
+    });
+
+    assert_eq!(
+        StateV1::from(&state),
+        StateV1 {
+            sol_rpc_canister_id: Some(Principal::from_slice(&[3; 10])),
+            solana_network: SolanaNetwork::Devnet,
+            solana_commitment_level: CommitmentLevel::Confirmed,
+            ed25519_key_name: Ed25519KeyName::MainnetProdKey1,
+            bikera_token_canister_id: Some(Principal::from_slice(&[4; 10])),
+            imera_spl_mint: Some("CKfatsPMUf8SkiURsDXs7eK6GWb4Jsd6UDbs7twMCWxo".to_string()),
+        }
+    );
+}
+
+#[test]
+fn should_clear_the_cached_key_when_the_key_name_changes() {
+    let mut state = State::from(init_arg());
+    state.ed25519_public_key = Some(cached_public_key());
+
+    // The same key name keeps the key
+    state.apply_upgrade(InitArg {
+        ed25519_key_name: Some(Ed25519KeyName::MainnetProdKey1),
+        ..InitArg::default()
+    });
+    assert_eq!(state.ed25519_public_key, Some(cached_public_key()));
+
+    state.apply_upgrade(InitArg {
+        ed25519_key_name: Some(Ed25519KeyName::MainnetTestKey1),
+        ..InitArg::default()
+    });
+    assert_eq!(state.ed25519_key_name(), Ed25519KeyName::MainnetTestKey1);
+    assert_eq!(state.ed25519_public_key, None);
+}
+
+#[test]
+fn should_start_from_defaults_without_a_stored_state() {
+    restore_state(None);
+
+    read_state(|state| assert_eq!(*state, State::default()));
+}
+
+#[test]
+fn should_round_trip_the_stored_state() {
+    let stored = StoredState::V1(StateV1::from(&State::from(init_arg())));
+
+    assert_eq!(StoredState::from_bytes(stored.to_bytes()), stored);
+    // Cells written before the schema envelope hold raw candid
+    let raw_candid = candid::encode_one(&stored).unwrap();
+    assert_eq!(StoredState::from_bytes(raw_candid.into()), stored);
+}
@@ -0,0 +1,131 @@
+//! Pure construction of the Solana messages sent by the canister.
+//!
+//! Every function takes the recent blockhash (or durable nonce) explicitly and returns an
+//! unsigned [`Message`], so the bytes that get signed can be checked without a Solana RPC.
+
+use crate::spl;
+use solana_hash::Hash;
+use solana_message::Message;
+use solana_pubkey::Pubkey;
+use solana_system_interface::instruction;
+
+#[cfg(test)]
+mod tests;
+
+/// Transfers `lamports` SOL from `payer` to `recipient`.
+pub fn transfer_sol_message(
+    payer: &Pubkey,
+    recipient: &Pubkey,
+    lamports: u64,
+    recent_blockhash: &Hash,
+) -> Message {
+    let instruction = instruction::transfer(payer, recipient, lamports);
+    Message::new_with_blockhash(&[instruction], Some(payer), recent_blockhash)
+}
+
+/// Transfers `lamports` SOL from `payer` to `recipient`, using the blockhash stored in
+/// `nonce_account` (whose authority is `payer`) instead of a recent blockhash.
+pub fn transfer_sol_with_durable_nonce_message(
+    payer: &Pubkey,
+    nonce_account: &Pubkey,
+    recipient: &Pubkey,
+    lamports: u64,
+    durable_nonce: &Hash,
+) -> Message {
+    let instructions = [
+        instruction::advance_nonce_account(nonce_account, payer),
+        instruction::transfer(payer, recipient, lamports),
+    ];
+    Message::new_with_blockhash(&instructions, Some(payer), durable_nonce)
+}
+
+/// Creates `nonce_account` funded with `lamports` by `payer`, who also becomes its authority.
+pub fn create_nonce_account_message(
+    payer: &Pubkey,
+    nonce_account: &Pubkey,
+    lamports: u64,
+    recent_blockhash: &Hash,
+) -> Message {
+    let instructions = instruction::create_nonce_account(payer, nonce_account, payer, lamports);
+    Message::new_with_blockhash(instructions.as_slice(), Some(payer), recent_blockhash)
+}
+
+/// Creates the associated token account of `wallet` for `mint`, paid by `payer`.
+/// Returns the address of the associated token account along with the message.
+pub fn create_associated_token_account_message(
+    payer: &Pubkey,
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    recent_blockhash: &Hash,
+) -> (Pubkey, Message) {
+    let (associated_token_account, instruction) =
+        spl::create_associated_token_account_instruction(payer, wallet, mint, token_program);
+    let message = Message::new_with_blockhash(&[instruction], Some(payer), recent_blockhash);
+    (associated_token_account, message)
+}
+
+/// Transfers `amount` base units of `mint` from the associated token account of `payer` to
+/// the associated token account of `recipient`. Both accounts must already exist.
+pub fn transfer_spl_token_message(
+    payer: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    recent_blockhash: &Hash,
+) -> Message {
+    let from = spl::get_associated_token_address(payer, mint, token_program);
+    let to = spl::get_associated_token_address(recipient, mint, token_program);
+    let instruction =
+        spl::transfer_instruction_with_program_id(&from, &to, payer, amount, token_program);
+    Message::new_with_blockhash(&[instruction], Some(payer), recent_blockhash)
+}
+
+/// Bridge release: transfers `amount` base units of `mint` (with `decimals` decimals) from
+/// the bridge `vault` to `recipient`, creating the recipient's associated token account first
+/// if needed. `memo` is recorded by the SPL Memo program so that the release can be traced
+/// back to the IC transaction from a Solana explorer.
+///
+/// Uses `TransferChecked`, which works for both legacy Token and Token-2022 mints.
+#[allow(clippy::too_many_arguments)]
+pub fn release_spl_token_message(
+    vault: &Pubkey,
+    recipient: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+    amount: u64,
+    decimals: u8,
+    memo: &str,
+    recent_blockhash: &Hash,
+) -> Message {
+    let from = spl::get_associated_token_address(vault, mint, token_program);
+    let (to, create_associated_token_account) =
+        spl::create_associated_token_account_idempotent_instruction(
+            vault,
+            recipient,
+            mint,
+            token_program,
+        );
+    let transfer = spl::transfer_checked_instruction_with_program_id(
+        &from,
+        mint,
+        &to,
+        vault,
+        amount,
+        decimals,
+        token_program,
+    );
+    let memo = spl::memo_instruction(memo, &[vault]);
+    Message::new_with_blockhash(
+        &[create_associated_token_account, transfer, memo],
+        Some(vault),
+        recent_blockhash,
+    )
+}
+
+/// Memo attached to a bridge release: the bridge transfer id and the index of the block in
+/// which the bikera ledger recorded the locked iMERA.
+pub fn bridge_release_memo(transfer_id: u64, ic_block_index: u64) -> String {
+    format!("bikera:bridge:{transfer_id}:ic_block:{ic_block_index}")
+}
@@ -0,0 +1,230 @@
+use crate::{
+    spl::{memo_program, token_2022_program, token_program},
+    transactions::*,
+};
+use solana_hash::Hash;
+use solana_message::Message;
+use solana_pubkey::{pubkey, Pubkey};
+
+// Golden vectors: hex of the serialized unsigned message (`Message::serialize`), i.e. the exact
+// bytes that get signed. Any change to how the canister builds transactions shows up here as a
+// diff; only update a vector when the change to the transaction is intended.
+
+const WALLET: Pubkey = pubkey!("AAAGuCgkmxYDTiBvzx1QT5XEjqXPRtQaiEXQo4gatD2o");
+const RECIPIENT: Pubkey = Pubkey::new_from_array([0x11; 32]);
+const NONCE_ACCOUNT: Pubkey = Pubkey::new_from_array([0x22; 32]);
+// USDC token which uses the legacy Token Program
+const USDC_MINT: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+// BonkEarn token which uses the Token 2022 Program
+const BERN_MINT: Pubkey = pubkey!("CKfatsPMUf8SkiURsDXs7eK6GWb4Jsd6UDbs7twMCWxo");
+const BLOCKHASH: Hash = Hash::new_from_array([0x07; 32]);
+
+fn to_hex(message: &Message) -> String {
+    message
+        .serialize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[test]
+fn should_build_transfer_sol_message() {
+    let message = transfer_sol_message(&WALLET, &RECIPIENT, 1_000_000, &BLOCKHASH);
+
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "010001038811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb34011111111111111111111111111111111111111111111111111111111",
+            "1111111100000000000000000000000000000000000000000000000000000000",
+            "0000000007070707070707070707070707070707070707070707070707070707",
+            "0707070701020200010c0200000040420f0000000000",
+        )
+    );
+}
+
+#[test]
+fn should_build_transfer_sol_with_durable_nonce_message() {
+    let message = transfer_sol_with_durable_nonce_message(
+        &WALLET,
+        &NONCE_ACCOUNT,
+        &RECIPIENT,
+        1_000_000,
+        &BLOCKHASH,
+    );
+
+    // The nonce must be advanced by the first instruction of the transaction
+    assert_eq!(message.instructions[0].data, vec![4, 0, 0, 0]);
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "010002058811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb34011111111111111111111111111111111111111111111111111111111",
+            "1111111122222222222222222222222222222222222222222222222222222222",
+            "2222222200000000000000000000000000000000000000000000000000000000",
+            "0000000006a7d517192c568ee08a845f73d29788cf035c3145b21ab344d8062e",
+            "a940000007070707070707070707070707070707070707070707070707070707",
+            "070707070203030204000404000000030200010c0200000040420f0000000000",
+        )
+    );
+}
+
+#[test]
+fn should_build_create_nonce_account_message() {
+    let message = create_nonce_account_message(&WALLET, &NONCE_ACCOUNT, 1_500_000, &BLOCKHASH);
+
+    // Both the payer and the new nonce account sign
+    assert_eq!(message.header.num_required_signatures, 2);
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "020003058811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb34022222222222222222222222222222222222222222222222222222222",
+            "2222222200000000000000000000000000000000000000000000000000000000",
+            "0000000006a7d517192c568ee08a845f73d29788cf035c3145b21ab344d8062e",
+            "a940000006a7d517192c5c51218cc94c3d4af17f58daee089ba1fd44e3dbd98a",
+            "0000000007070707070707070707070707070707070707070707070707070707",
+            "070707070202020001340000000060e316000000000050000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000203",
+            "01030424060000008811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b4",
+            "74d60e6170ebb340",
+        )
+    );
+}
+
+#[test]
+fn should_build_create_associated_token_account_message() {
+    let (associated_token_account, message) = create_associated_token_account_message(
+        &WALLET,
+        &WALLET,
+        &USDC_MINT,
+        &token_program::id(),
+        &BLOCKHASH,
+    );
+
+    // Same address as in `spl::tests::should_compute_ata_with_legacy_token_program`
+    assert_eq!(
+        associated_token_account,
+        pubkey!("Cra8woRQhnHsGAmFWcCN1m7A9J44ykNfGpehi6dMBuKR")
+    );
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "010004068811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb340b0237be34e669bb805ad44dae1261d7f0583427cbc4c204aaf304538",
+            "a65d108c00000000000000000000000000000000000000000000000000000000",
+            "0000000006ddf6e1d765a193d9cbe146ceeb79ac1cb485ed5f5b37913a8cf585",
+            "7eff00a98c97258f4e2489f1bb3d1029148e0d830b5a1399daff1084048e7bd8",
+            "dbe9f859c6fa7af3bedbad3a3d65f36aabc97431b1bbe4c2d2f6e0e47ca60203",
+            "452f5d6107070707070707070707070707070707070707070707070707070707",
+            "070707070104060001000502030100",
+        )
+    );
+}
+
+#[test]
+fn should_build_transfer_spl_token_message() {
+    let message = transfer_spl_token_message(
+        &WALLET,
+        &RECIPIENT,
+        &USDC_MINT,
+        &token_program::id(),
+        1_234_567,
+        &BLOCKHASH,
+    );
+
+    // SPL Token "transfer": tag 3 followed by the little-endian amount
+    assert_eq!(
+        message.instructions[0].data,
+        [vec![3], 1_234_567u64.to_le_bytes().to_vec()].concat()
+    );
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "010001048811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb3404880743f949d5ca877081f3528f37cad77ff3ae3da5d25541b08340d",
+            "0292f7aab0237be34e669bb805ad44dae1261d7f0583427cbc4c204aaf304538",
+            "a65d108c06ddf6e1d765a193d9cbe146ceeb79ac1cb485ed5f5b37913a8cf585",
+            "7eff00a907070707070707070707070707070707070707070707070707070707",
+            "07070707010303020100090387d6120000000000",
+        )
+    );
+}
+
+#[test]
+fn should_build_release_spl_token_message() {
+    let message = release_spl_token_message(
+        &WALLET,
+        &RECIPIENT,
+        &USDC_MINT,
+        &token_program::id(),
+        1_234_567,
+        6,
+        &bridge_release_memo(7, 42),
+        &BLOCKHASH,
+    );
+
+    // Create the recipient's token account idempotently, transfer, then record the memo
+    assert_eq!(message.instructions.len(), 3);
+    assert_eq!(message.instructions[0].data, vec![1]);
+    assert_eq!(
+        message.instructions[1].data,
+        [vec![12], 1_234_567u64.to_le_bytes().to_vec(), vec![6]].concat()
+    );
+    assert_eq!(message.program_id(2), Some(&memo_program::id()));
+    assert_eq!(
+        message.instructions[2].data,
+        b"bikera:bridge:7:ic_block:42".to_vec()
+    );
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "010006098811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb3404880743f949d5ca877081f3528f37cad77ff3ae3da5d25541b08340d",
+            "0292f7aab0237be34e669bb805ad44dae1261d7f0583427cbc4c204aaf304538",
+            "a65d108c00000000000000000000000000000000000000000000000000000000",
+            "00000000054a535a992921064d24e87160da387c7c35b5ddbc92bb81e41fa840",
+            "4105448d06ddf6e1d765a193d9cbe146ceeb79ac1cb485ed5f5b37913a8cf585",
+            "7eff00a911111111111111111111111111111111111111111111111111111111",
+            "111111118c97258f4e2489f1bb3d1029148e0d830b5a1399daff1084048e7bd8",
+            "dbe9f859c6fa7af3bedbad3a3d65f36aabc97431b1bbe4c2d2f6e0e47ca60203",
+            "452f5d6107070707070707070707070707070707070707070707070707070707",
+            "0707070703070600010608030501010504020801000a0c87d612000000000006",
+            "0401001b62696b6572613a6272696467653a373a69635f626c6f636b3a3432",
+        )
+    );
+}
+
+#[test]
+fn should_build_release_spl_token_2022_message() {
+    let message = release_spl_token_message(
+        &WALLET,
+        &RECIPIENT,
+        &BERN_MINT,
+        &token_2022_program::id(),
+        1_234_567,
+        5,
+        &bridge_release_memo(7, 42),
+        &BLOCKHASH,
+    );
+
+    // Both the token accounts and the transfer go through the Token-2022 program
+    assert_eq!(message.program_id(1), Some(&token_2022_program::id()));
+    assert_eq!(
+        to_hex(&message),
+        concat!(
+            "010006098811e55b0c45c8b405e3bdf8799f2ae7ab6a8aaf4c0602b474d60e61",
+            "70ebb340c9cfa8d25740b7e0adf0d1519aa52f839fe939d5b873e8ca6a872f41",
+            "716d96cee4bbfa494cca0899d3175afd6263c4917a972ac9c62afe2e98331ee1",
+            "6d87b40200000000000000000000000000000000000000000000000000000000",
+            "00000000054a535a992921064d24e87160da387c7c35b5ddbc92bb81e41fa840",
+            "4105448d06ddf6e1ee758fde18425dbce46ccddab61afc4d83b90d27febdf928",
+            "d8a18bfc11111111111111111111111111111111111111111111111111111111",
+            "111111118c97258f4e2489f1bb3d1029148e0d830b5a1399daff1084048e7bd8",
+            "dbe9f859a838a364b85c2ae54b54198be957bd4bd0ef494ee49f55ed9029ceb6",
+            "70d71a3007070707070707070707070707070707070707070707070707070707",
+            "0707070703070600010608030501010504020801000a0c87d612000000000005",
+            "0401001b62696b6572613a6272696467653a373a69635f626c6f636b3a3432",
+        )
+    );
+}
@@ -0,0 +1,82 @@
+// Caller, clock, certified data, global timer and traps of the current message
+//
+// The IC system API traps outside a canister, so unit tests swap these for values
+// they set through `env::testing`.
+
+#[cfg(not(test))]
+pub fn caller() -> candid::Principal {
+    ic_cdk::api::msg_caller()
+}
+
+#[cfg(not(test))]
+pub fn time() -> u64 {
+    ic_cdk::api::time()
+}
+
+#[cfg(not(test))]
+pub fn set_certified_data(root: [u8; 32]) {
+    ic_cdk::api::certified_data_set(root);
+}
+
+#[cfg(not(test))]
+pub fn set_global_timer(timestamp: u64) {
+    ic_cdk::api::global_timer_set(timestamp);
+}
+
+#[cfg(not(test))]
+pub fn trap(message: String) -> ! {
+    ic_cdk::trap(message)
+}
+
+#[cfg(test)]
+pub use testing::{caller, set_certified_data, set_global_timer, time, trap};
+
+#[cfg(test)]
+pub mod testing {
+    use candid::Principal;
+    use std::cell::Cell;
+
+    thread_local! {
+        static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+        static TIME: Cell<u64> = const { Cell::new(0) };
+        static CERTIFIED_DATA: Cell<[u8; 32]> = const { Cell::new([0; 32]) };
+        static GLOBAL_TIMER: Cell<Option<u64>> = const { Cell::new(None) };
+    }
+
+    pub fn caller() -> Principal {
+        CALLER.with(Cell::get)
+    }
+
+    pub fn time() -> u64 {
+        TIME.with(Cell::get)
+    }
+
+    pub fn set_certified_data(root: [u8; 32]) {
+        CERTIFIED_DATA.with(|data| data.set(root));
+    }
+
+    pub fn set_global_timer(timestamp: u64) {
+        GLOBAL_TIMER.with(|timer| timer.set(Some(timestamp)));
+    }
+
+    pub fn trap(message: String) -> ! {
+        panic!("{}", message)
+    }
+
+    pub fn set_caller(caller: Principal) {
+        CALLER.with(|c| c.set(caller));
+    }
+
+    pub fn set_time(time: u64) {
+        TIME.with(|t| t.set(time));
+    }
+
+    pub fn certified_data() -> [u8; 32] {
+        CERTIFIED_DATA.with(Cell::get)
+    }
+
+    // Takes the timestamp the canister last armed the global timer with
+    pub fn take_global_timer() -> Option<u64> {
+        GLOBAL_TIMER.with(Cell::take)
+    }
+}
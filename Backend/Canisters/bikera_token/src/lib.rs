@@ -1,14 +1,24 @@
 // src/lib.rs - Fixed Bikera ICRC-1 Token Canister
-use candid::{CandidType, Deserialize, Nat, Principal};
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
+use certified_client::ic_certification::{self, RbTree};
+use certified_client::{account_key, balance_leaf, BALANCES_LABEL};
 use ic_cdk_macros::*;
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, StableBTreeMap, Storable,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
 };
+use stable_schema::{rewrite_btree_map, rewrite_cell, SchemaRegistry, Versioned};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use serde_bytes::ByteBuf;
 use std::borrow::Cow;
+use std::ops::Bound;
+
+mod env;
+#[cfg(test)]
+mod tests;
+
+use env::{caller, time};
 
 // Type aliases
 type Memory = VirtualMemory<DefaultMemoryImpl>;
@@ -42,6 +52,25 @@ pub enum TransferError {
     GenericError { error_code: Nat, message: String },
 }
 
+#[derive(CandidType, Deserialize, Clone)]
+pub enum MetadataValue {
+    Nat(Nat),
+    Int(Int),
+    Text(String),
+    Blob(ByteBuf),
+}
+
+// Generic value used for structured status responses
+#[derive(CandidType, Deserialize, Clone)]
+pub enum Value {
+    Blob(ByteBuf),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct StandardRecord {
     pub name: String,
@@ -57,6 +86,109 @@ pub struct TokenInitArgs {
     pub minting_account: Option<Account>,
     pub initial_balances: Vec<(Account, Nat)>,
     pub max_supply: Option<Nat>,
+    pub fee_collector: Option<Account>,
+    pub controller: Option<Principal>,
+    pub logo: Option<String>,
+    pub vesting: Option<Vec<VestingGrant>>,
+}
+
+// Token Vesting Types
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq)]
+pub enum VestingKind {
+    // Nothing unlocks before `cliff_time`, then the schedule vests linearly until `end_time`
+    Linear,
+    // Everything unlocks at `cliff_time`
+    Cliff,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct VestingGrant {
+    pub account: Account,
+    pub amount: Nat,
+    pub kind: VestingKind,
+    pub start_time: u64,
+    pub cliff_time: u64,
+    pub end_time: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct VestingSchedule {
+    pub kind: VestingKind,
+    pub total_amount: Nat,
+    pub released_amount: Nat,
+    pub start_time: u64,
+    pub cliff_time: u64,
+    pub end_time: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct VestingStatus {
+    pub schedule: VestingSchedule,
+    pub vested_amount: Nat,
+    pub releasable_amount: Nat,
+    pub locked_amount: Nat,
+}
+
+// ICRC-2 Standard Types
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ApproveArgs {
+    pub from_subaccount: Option<[u8; 32]>,
+    pub spender: Account,
+    pub amount: Nat,
+    pub expected_allowance: Option<Nat>,
+    pub expires_at: Option<u64>,
+    pub fee: Option<Nat>,
+    pub memo: Option<ByteBuf>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum ApproveError {
+    BadFee { expected_fee: Nat },
+    InsufficientFunds { balance: Nat },
+    AllowanceChanged { current_allowance: Nat },
+    Expired { ledger_time: u64 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<[u8; 32]>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<ByteBuf>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AllowanceArgs {
+    pub account: Account,
+    pub spender: Account,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Allowance {
+    pub allowance: Nat,
+    pub expires_at: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -67,11 +199,83 @@ pub struct MintRequest {
     pub created_at_time: Option<u64>,
 }
 
-// Storable implementations
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq)]
+pub enum BatchMintMode {
+    // Every entry is checked up front; either all are minted or none are
+    Atomic,
+    // Entries that fail their checks are skipped and reported individually
+    BestEffort,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct BatchMintArgs {
+    pub mode: BatchMintMode,
+    pub mints: Vec<MintRequest>,
+    pub memo: Option<ByteBuf>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct BatchMintResult {
+    pub batch_id: u64,
+    pub results: Vec<Result<Nat, String>>,
+    pub total_minted: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MintBatch {
+    pub batch_id: u64,
+    pub minter: Principal,
+    pub mode: BatchMintMode,
+    pub memo: Option<ByteBuf>,
+    pub entries: u32,
+    pub minted: u32,
+    pub total_minted: Nat,
+    pub timestamp: u64,
+}
+
+// Transaction log
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Transaction {
+    pub operation: Operation,
+    pub timestamp: u64,
+    pub memo: Option<ByteBuf>,
+    pub batch_id: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum Operation {
+    Transfer {
+        from: Account,
+        to: Account,
+        amount: Nat,
+        fee: Option<Nat>,
+    },
+    Mint {
+        to: Account,
+        amount: Nat,
+    },
+    Burn {
+        from: Account,
+        amount: Nat,
+    },
+    Approve {
+        from: Account,
+        spender: Account,
+        amount: Nat,
+        expires_at: Option<u64>,
+    },
+}
+
+// Storable implementations. Keys stay raw candid since their bytes define the map order;
+// values go through the versioned `stable_schema` envelope.
+//
+// A candid `Account` with a subaccount is 90 bytes, so the former 64-byte bound trapped on
+// every subaccount key. Maps keyed by `Account` were created with unbounded values and do not
+// check the key bound against their layout, so raising it is safe in place.
 impl Storable for Account {
     const BOUND: ic_stable_structures::storable::Bound = 
         ic_stable_structures::storable::Bound::Bounded {
-            max_size: 64,
+            max_size: 128,
             is_fixed_size: false,
         };
 
@@ -90,20 +294,11 @@ impl Storable for Account {
 #[derive(CandidType, Deserialize, Clone)]
 pub struct StorableNat(pub Nat);
 
-impl Storable for StorableNat {
-    const BOUND: ic_stable_structures::storable::Bound = 
-        ic_stable_structures::storable::Bound::Unbounded;
-
-    fn to_bytes(&self) -> Cow<'_, [u8]> {
-        use candid::Encode;
-        Cow::Owned(Encode!(self).unwrap())
-    }
-
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        use candid::Decode;
-        Decode!(bytes.as_ref(), Self).unwrap()
-    }
-}
+stable_schema::versioned_since_raw_candid!(StorableNat, "bikera_token::StorableNat");
+stable_schema::versioned_since_raw_candid!(Transaction, "bikera_token::Transaction");
+stable_schema::versioned_since_raw_candid!(VestingSchedule, "bikera_token::VestingSchedule");
+stable_schema::versioned_since_raw_candid!(MintBatch, "bikera_token::MintBatch");
+stable_schema::versioned_since_raw_candid!(Allowance, "bikera_token::Allowance");
 
 // Token metadata
 #[derive(CandidType, Deserialize, Clone)]
@@ -115,11 +310,149 @@ pub struct TokenMetadata {
     pub total_supply: Nat,
     pub minting_account: Option<Account>,
     pub max_supply: Option<Nat>,
+    pub fee_collector: Option<Account>,
+    pub logo: Option<String>,
+}
+
+stable_schema::versioned_since_raw_candid!(TokenMetadata, "bikera_token::TokenMetadata");
+
+impl Default for TokenMetadata {
+    fn default() -> Self {
+        TokenMetadata {
+            name: "Bikera".to_string(),
+            symbol: "iMERA".to_string(),
+            decimals: 6,
+            fee: Nat::from(1_000u64),
+            total_supply: Nat::from(0u64),
+            minting_account: None,
+            max_supply: Some(Nat::from(100_000_000_000_000u64)), // 100M tokens with 6 decimals
+            fee_collector: None,
+            logo: None,
+        }
+    }
+}
+
+// Upgrade arguments; every field left as `None` keeps its current value
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TokenUpgradeArgs {
+    pub fee: Option<Nat>,
+    pub minting_account: Option<Account>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SubaccountBalance {
+    pub subaccount: [u8; 32],
+    pub balance: Nat,
+}
+
+// Balance with the subnet certificate and a witness of its entry (or absence) in
+// `CERTIFIED_BALANCES`; verify with `certified_client::Verifier::verify_balance`
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CertifiedBalance {
+    pub balance: Nat,
+    pub certificate: ByteBuf,
+    pub witness: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SupplyInvariant {
+    pub total_supply: Nat,
+    pub sum_of_balances: Nat,
+    pub holder_count: u64,
+    pub is_consistent: bool,
+}
+
+// Minter governance
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MinterConfig {
+    pub daily_quota: Nat,
+    pub per_call_max: Nat,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct GovernanceConfig {
+    pub controller: Option<Principal>,
+    pub admins: Vec<Principal>,
+    pub threshold: u8,
+    pub minting_paused: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum GovernanceAction {
+    SetMinter { minter: Principal, config: MinterConfig },
+    RemoveMinter { minter: Principal },
+    SetMintingPaused(bool),
+    SetAdmins { admins: Vec<Principal>, threshold: u8 },
+    SetController(Option<Principal>),
+    // Credits a new locked grant, e.g. for a hire after launch
+    AddVestingGrant(VestingGrant),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct GovernanceProposal {
+    pub id: u64,
+    pub action: GovernanceAction,
+    pub proposer: Principal,
+    pub approvals: Vec<Principal>,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct GovernanceRecord {
+    pub id: u64,
+    pub action: GovernanceAction,
+    pub approved_by: Vec<Principal>,
+    pub executed_at: u64,
+}
+
+stable_schema::versioned_since_raw_candid!(MinterConfig, "bikera_token::MinterConfig");
+stable_schema::versioned_since_raw_candid!(GovernanceConfig, "bikera_token::GovernanceConfig");
+stable_schema::versioned_since_raw_candid!(GovernanceProposal, "bikera_token::GovernanceProposal");
+stable_schema::versioned_since_raw_candid!(GovernanceRecord, "bikera_token::GovernanceRecord");
+
+impl Default for GovernanceConfig {
+    fn default() -> Self {
+        GovernanceConfig {
+            controller: None,
+            admins: Vec::new(),
+            threshold: 1,
+            minting_paused: false,
+        }
+    }
 }
 
 // Memory management
 const BALANCES_MEMORY_ID: u8 = 0;
 const METADATA_MEMORY_ID: u8 = 1;
+const ALLOWANCES_MEMORY_ID: u8 = 2;
+const DEDUP_MEMORY_ID: u8 = 3;
+const DEDUP_EXPIRY_MEMORY_ID: u8 = 4;
+const TX_COUNTER_MEMORY_ID: u8 = 5;
+const MINTERS_MEMORY_ID: u8 = 6;
+const MINT_USAGE_MEMORY_ID: u8 = 7;
+const GOVERNANCE_MEMORY_ID: u8 = 8;
+const PROPOSALS_MEMORY_ID: u8 = 9;
+const GOVERNANCE_LOG_MEMORY_ID: u8 = 10;
+const TRANSACTIONS_MEMORY_ID: u8 = 11;
+const MINT_BATCHES_MEMORY_ID: u8 = 12;
+const SUBACCOUNTS_MEMORY_ID: u8 = 13;
+const VESTING_MEMORY_ID: u8 = 14;
+const SCHEMA_REGISTRY_MEMORY_ID: u8 = 15;
+const PROPOSAL_COUNTER_MEMORY_ID: u8 = 16;
+
+const MAX_BATCH_MINT_SIZE: usize = 500;
+const MAX_MEMO_LENGTH: usize = 32;
+
+// Minting limits applied to the minting account when it is first added to the allowlist
+const DEFAULT_DAILY_MINT_QUOTA: u64 = 1_000_000_000_000; // 1M iMERA
+const DEFAULT_PER_CALL_MINT_MAX: u64 = 100_000_000_000; // 100K iMERA
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const PROPOSAL_TTL_NANOS: u64 = 7 * NANOS_PER_DAY;
+
+// ICRC-1 deduplication window
+const TRANSACTION_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const PERMITTED_DRIFT_NANOS: u64 = 60 * 1_000_000_000;
+const MAX_DEDUP_PRUNE_PER_CALL: usize = 100;
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -131,31 +464,140 @@ thread_local! {
         )
     );
 
-    static METADATA: RefCell<TokenMetadata> = RefCell::new(TokenMetadata {
-        name: "Bikera".to_string(),
-        symbol: "iMERA".to_string(),
-        decimals: 6,
-        fee: Nat::from(1_000u64),
-        total_supply: Nat::from(0u64),
-        minting_account: None,
-        max_supply: Some(Nat::from(100_000_000_000_000u64)), // 100M tokens with 8 decimals
-    });
+    // ICRC-2 allowances keyed by (account, spender)
+    static ALLOWANCES: RefCell<StableBTreeMap<(Account, Account), Allowance, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(ALLOWANCES_MEMORY_ID)))
+        )
+    );
+
+    // Hash of (caller, args) -> block index, for transactions that set `created_at_time`
+    static TX_DEDUP: RefCell<StableBTreeMap<[u8; 32], StorableNat, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(DEDUP_MEMORY_ID)))
+        )
+    );
+
+    // (created_at_time, hash) index used to prune TX_DEDUP once entries leave the window
+    static TX_DEDUP_EXPIRY: RefCell<StableBTreeMap<(u64, [u8; 32]), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(DEDUP_EXPIRY_MEMORY_ID)))
+        )
+    );
+
+    static METADATA: RefCell<StableCell<TokenMetadata, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(METADATA_MEMORY_ID))),
+            TokenMetadata::default(),
+        ).expect("failed to initialize the metadata cell")
+    );
+
+    static TX_COUNTER: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(TX_COUNTER_MEMORY_ID))),
+            0,
+        ).expect("failed to initialize the transaction counter cell")
+    );
+
+    static MINTERS: RefCell<StableBTreeMap<Principal, MinterConfig, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(MINTERS_MEMORY_ID)))
+        )
+    );
+
+    // Amount minted per (minter, day number)
+    static MINT_USAGE: RefCell<StableBTreeMap<(Principal, u64), StorableNat, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(MINT_USAGE_MEMORY_ID)))
+        )
+    );
+
+    static GOVERNANCE: RefCell<StableCell<GovernanceConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(GOVERNANCE_MEMORY_ID))),
+            GovernanceConfig::default(),
+        ).expect("failed to initialize the governance cell")
+    );
+
+    static PROPOSALS: RefCell<StableBTreeMap<u64, GovernanceProposal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(PROPOSALS_MEMORY_ID)))
+        )
+    );
+
+    static GOVERNANCE_LOG: RefCell<StableBTreeMap<u64, GovernanceRecord, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(GOVERNANCE_LOG_MEMORY_ID)))
+        )
+    );
+
+    // Next governance proposal id; never decreases, so expired ids are not handed out again
+    static PROPOSAL_COUNTER: RefCell<StableCell<u64, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(PROPOSAL_COUNTER_MEMORY_ID))),
+            0,
+        ).expect("failed to initialize the proposal counter cell")
+    );
+
+    static TRANSACTIONS: RefCell<StableBTreeMap<u64, Transaction, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(TRANSACTIONS_MEMORY_ID)))
+        )
+    );
+
+    static MINT_BATCHES: RefCell<StableBTreeMap<u64, MintBatch, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(MINT_BATCHES_MEMORY_ID)))
+        )
+    );
+
+    // (owner, subaccount) for every non-empty subaccount balance
+    static SUBACCOUNTS: RefCell<StableBTreeMap<(Principal, [u8; 32]), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(SUBACCOUNTS_MEMORY_ID)))
+        )
+    );
+
+    static VESTING: RefCell<StableBTreeMap<Account, VestingSchedule, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(VESTING_MEMORY_ID)))
+        )
+    );
+
+    // Schema version each collection was last rewritten with
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> = RefCell::new(
+        SchemaRegistry::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(SCHEMA_REGISTRY_MEMORY_ID)))
+        )
+    );
+
+    // Non-zero balances keyed by `certified_client::account_key`; its root is the canister's
+    // certified data. Kept on the heap and rebuilt from BALANCES after upgrades.
+    static CERTIFIED_BALANCES: RefCell<RbTree<Vec<u8>, Vec<u8>>> = const { RefCell::new(RbTree::new()) };
+
+    // Progress of refilling CERTIFIED_BALANCES after an upgrade
+    static CERTIFICATION_REBUILD: RefCell<CertificationRebuild> = const { RefCell::new(CertificationRebuild::Complete) };
 
-    static TX_COUNTER: RefCell<u64> = RefCell::new(0);
     static TRANSFER_LOCKS: RefCell<HashMap<Principal, bool>> = RefCell::new(HashMap::new());
 }
 
 #[init]
 fn init(args: TokenInitArgs) {
-    METADATA.with(|m| {
-        let mut metadata = m.borrow_mut();
+    mutate_metadata(|metadata| {
         metadata.name = args.name;
         metadata.symbol = args.symbol;
         metadata.decimals = args.decimals;
         metadata.fee = args.fee;
         metadata.minting_account = args.minting_account;
         metadata.max_supply = args.max_supply;
+        metadata.fee_collector = args.fee_collector;
+        metadata.logo = args.logo;
+    });
+
+    mutate_governance(|governance| {
+        governance.controller = Some(args.controller.unwrap_or_else(caller));
     });
+    seed_default_minter(None);
 
     // Set initial balances
     if !args.initial_balances.is_empty() {
@@ -164,59 +606,170 @@ fn init(args: TokenInitArgs) {
             let mut total = Nat::from(0u64);
             
             for (account, amount) in args.initial_balances {
-                balances.insert(account, StorableNat(amount.clone()));
+                store_balance(&mut balances, &account, amount.clone());
                 total += amount;
             }
             
-            METADATA.with(|m| {
-                m.borrow_mut().total_supply = total;
+            mutate_metadata(|metadata| metadata.total_supply = total);
+        });
+    }
+
+    // Vesting grants are credited up front and stay locked until released
+    for grant in args.vesting.unwrap_or_default() {
+        if let Err(message) = add_vesting_grant(&grant) {
+            env::trap(message);
+        }
+    }
+
+    update_certified_data();
+}
+
+#[post_upgrade]
+fn post_upgrade(args: Option<TokenUpgradeArgs>) {
+    // Balances, metadata and counters are already in stable memory
+    migrate_stable_schema();
+
+    // Metadata cells created by this upgrade start with a zero supply; derive it from the balances
+    if icrc1_total_supply() == 0u64 && !BALANCES.with(|balances| balances.borrow().is_empty()) {
+        let total_supply = sum_of_balances();
+        mutate_metadata(|metadata| metadata.total_supply = total_supply);
+    }
+
+    let previous_minting_account = icrc1_minting_account();
+    if let Some(args) = args {
+        mutate_metadata(|metadata| {
+            if let Some(fee) = args.fee {
+                metadata.fee = fee;
+            }
+            if let Some(minting_account) = args.minting_account {
+                metadata.minting_account = Some(minting_account);
+            }
+        });
+    }
+
+    // Canisters upgraded from before the allowlist keep their existing minter, and a new
+    // minting account takes over the limits of the previous one
+    seed_default_minter(previous_minting_account);
+
+    start_certification_rebuild(CERTIFICATION_REBUILD_CHUNK);
+
+    // Build the subaccount index for balances that predate it
+    if SUBACCOUNTS.with(|index| index.borrow().is_empty()) {
+        BALANCES.with(|balances| {
+            SUBACCOUNTS.with(|index| {
+                let mut index = index.borrow_mut();
+                for (account, _) in balances.borrow().iter() {
+                    if let Some(subaccount) = account.subaccount {
+                        index.insert((account.owner, subaccount), ());
+                    }
+                }
             });
         });
     }
 }
 
+// Only the certified-tree rebuild arms the global timer; there is no timers library to share
+// it with
+#[unsafe(export_name = "canister_global_timer")]
+fn canister_global_timer() {
+    ic_cdk::futures::in_executor_context(|| {
+        continue_certification_rebuild(CERTIFICATION_REBUILD_CHUNK)
+    });
+}
+
+// Rewrites every collection whose value type changed schema version since the last upgrade.
+// BALANCES and TRANSACTIONS grow with usage and could not be rewritten within the instruction
+// limit of a single upgrade; their values are migrated when they are read instead.
+fn migrate_stable_schema() {
+    fn migrate<T: Versioned>(collection: &str, rewrite: impl FnOnce() -> u64) {
+        let migrated = SCHEMA_REGISTRY.with(|registry| {
+            registry.borrow_mut().migrate::<T>(collection, rewrite)
+        });
+        if let Some(count) = migrated {
+            ic_cdk::println!("Migrated {} {} to schema v{}", count, collection, T::VERSION);
+        }
+    }
+
+    migrate::<Allowance>("allowances", || {
+        ALLOWANCES.with(|a| rewrite_btree_map(&mut a.borrow_mut()))
+    });
+    migrate::<StorableNat>("tx_dedup", || {
+        TX_DEDUP.with(|d| rewrite_btree_map(&mut d.borrow_mut()))
+    });
+    migrate::<TokenMetadata>("metadata", || {
+        METADATA.with(|m| rewrite_cell(&mut m.borrow_mut()))
+    });
+    migrate::<MinterConfig>("minters", || {
+        MINTERS.with(|m| rewrite_btree_map(&mut m.borrow_mut()))
+    });
+    migrate::<StorableNat>("mint_usage", || {
+        MINT_USAGE.with(|u| rewrite_btree_map(&mut u.borrow_mut()))
+    });
+    migrate::<GovernanceConfig>("governance", || {
+        GOVERNANCE.with(|g| rewrite_cell(&mut g.borrow_mut()))
+    });
+    migrate::<GovernanceProposal>("proposals", || {
+        PROPOSALS.with(|p| rewrite_btree_map(&mut p.borrow_mut()))
+    });
+    migrate::<GovernanceRecord>("governance_log", || {
+        GOVERNANCE_LOG.with(|l| rewrite_btree_map(&mut l.borrow_mut()))
+    });
+    migrate::<MintBatch>("mint_batches", || {
+        MINT_BATCHES.with(|b| rewrite_btree_map(&mut b.borrow_mut()))
+    });
+    migrate::<VestingSchedule>("vesting", || {
+        VESTING.with(|v| rewrite_btree_map(&mut v.borrow_mut()))
+    });
+}
+
 // ICRC-1 Standard Query Methods
 #[query]
 fn icrc1_name() -> String {
-    METADATA.with(|m| m.borrow().name.clone())
+    METADATA.with(|m| m.borrow().get().name.clone())
 }
 
 #[query]
 fn icrc1_symbol() -> String {
-    METADATA.with(|m| m.borrow().symbol.clone())
+    METADATA.with(|m| m.borrow().get().symbol.clone())
 }
 
 #[query]
 fn icrc1_decimals() -> u8 {
-    METADATA.with(|m| m.borrow().decimals)
+    METADATA.with(|m| m.borrow().get().decimals)
 }
 
 #[query]
 fn icrc1_fee() -> Nat {
-    METADATA.with(|m| m.borrow().fee.clone())
+    METADATA.with(|m| m.borrow().get().fee.clone())
 }
 
 #[query]
-fn icrc1_metadata() -> Vec<(String, String)> {
+fn icrc1_metadata() -> Vec<(String, MetadataValue)> {
     METADATA.with(|m| {
-        let metadata = m.borrow();
-        vec![
-            ("icrc1:name".to_string(), metadata.name.clone()),
-            ("icrc1:symbol".to_string(), metadata.symbol.clone()),
-            ("icrc1:decimals".to_string(), metadata.decimals.to_string()),
-            ("icrc1:fee".to_string(), metadata.fee.to_string()),
-        ]
+        let cell = m.borrow();
+        let metadata = cell.get();
+        let mut entries = vec![
+            ("icrc1:name".to_string(), MetadataValue::Text(metadata.name.clone())),
+            ("icrc1:symbol".to_string(), MetadataValue::Text(metadata.symbol.clone())),
+            ("icrc1:decimals".to_string(), MetadataValue::Nat(Nat::from(metadata.decimals))),
+            ("icrc1:fee".to_string(), MetadataValue::Nat(metadata.fee.clone())),
+            ("icrc1:max_memo_length".to_string(), MetadataValue::Nat(Nat::from(MAX_MEMO_LENGTH))),
+        ];
+        if let Some(logo) = &metadata.logo {
+            entries.push(("icrc1:logo".to_string(), MetadataValue::Text(logo.clone())));
+        }
+        entries
     })
 }
 
 #[query]
 fn icrc1_total_supply() -> Nat {
-    METADATA.with(|m| m.borrow().total_supply.clone())
+    METADATA.with(|m| m.borrow().get().total_supply.clone())
 }
 
 #[query]
 fn icrc1_minting_account() -> Option<Account> {
-    METADATA.with(|m| m.borrow().minting_account.clone())
+    METADATA.with(|m| m.borrow().get().minting_account.clone())
 }
 
 #[query]
@@ -228,20 +781,55 @@ fn icrc1_balance_of(account: Account) -> Nat {
     })
 }
 
+// Same balance as `icrc1_balance_of`, with a witness against the certified data. Must be
+// called as a query: replicated calls have no certificate. Fails until the certified tree is
+// rebuilt after an upgrade.
+#[query]
+fn get_certified_balance(account: Account) -> Result<CertifiedBalance, String> {
+    if certification_pending() {
+        return Err("Certified balances are being rebuilt after an upgrade; retry shortly".to_string());
+    }
+    let certificate = ic_cdk::api::data_certificate()
+        .ok_or("Certificates are only available in query calls")?;
+    let key = account_key(&account.owner, account.subaccount.as_ref());
+    let witness = CERTIFIED_BALANCES.with(|tree| {
+        ic_certification::labeled(BALANCES_LABEL, tree.borrow().witness(&key))
+    });
+
+    Ok(CertifiedBalance {
+        balance: icrc1_balance_of(account),
+        certificate: ByteBuf::from(certificate),
+        witness: ByteBuf::from(certified_client::encode_witness(&witness)),
+    })
+}
+
 #[query]
 fn icrc1_supported_standards() -> Vec<StandardRecord> {
     vec![
         StandardRecord {
             name: "ICRC-1".to_string(),
             url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-1".to_string(),
-        }
+        },
+        StandardRecord {
+            name: "ICRC-2".to_string(),
+            url: "https://github.com/dfinity/ICRC-1/tree/main/standards/ICRC-2".to_string(),
+        },
+        StandardRecord {
+            name: "ICRC-10".to_string(),
+            url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-10/ICRC-10.md".to_string(),
+        },
     ]
 }
 
+#[query]
+fn icrc10_supported_standards() -> Vec<StandardRecord> {
+    icrc1_supported_standards()
+}
+
 // ICRC-1 Standard Update Methods
 #[update]
 fn icrc1_transfer(args: TransferArg) -> Result<Nat, TransferError> {
-    let caller = ic_cdk::api::msg_caller();
+    let caller = caller();
     
     // Prevent anonymous callers
     if caller == Principal::anonymous() {
@@ -269,7 +857,7 @@ fn perform_transfer(caller: Principal, args: TransferArg) -> Result<Nat, Transfe
     };
 
     // Validate transfer parameters
-    if args.amount == Nat::from(0u64) {
+    if args.amount == 0u64 {
         return Err(TransferError::GenericError {
             error_code: Nat::from(400u32),
             message: "Transfer amount must be greater than zero".to_string(),
@@ -284,170 +872,1204 @@ fn perform_transfer(caller: Principal, args: TransferArg) -> Result<Nat, Transfe
         });
     }
 
+    // Check timestamp and deduplicate retried transfers
+    let now = time();
+    validate_memo(args.memo.as_ref())?;
+    validate_created_at_time(args.created_at_time, now)?;
+    let dedup_hash = args.created_at_time.map(|_| transaction_hash("icrc1_transfer", caller, &args));
+    if let Some(duplicate_of) = dedup_hash.as_ref().and_then(find_duplicate) {
+        return Err(TransferError::Duplicate { duplicate_of });
+    }
+
+    // Transfers to the minting account are burns and carry no fee
+    let is_burn = is_minting_account(&args.to);
+
     // Get fee
-    let fee = args.fee.unwrap_or_else(|| METADATA.with(|m| m.borrow().fee.clone()));
-    let expected_fee = METADATA.with(|m| m.borrow().fee.clone());
+    let expected_fee = if is_burn {
+        Nat::from(0u64)
+    } else {
+        METADATA.with(|m| m.borrow().get().fee.clone())
+    };
+    let fee = args.fee.clone().unwrap_or_else(|| expected_fee.clone());
     
     if fee != expected_fee {
         return Err(TransferError::BadFee { expected_fee });
     }
 
-    // Perform balance transfer
+    let tx_id = if is_burn {
+        burn_balance(&from_account, &args.amount, args.memo.clone())?
+    } else {
+        move_balance(&from_account, &args.to, &args.amount, &fee, args.memo.clone())?
+    };
+
+    if let (Some(hash), Some(created_at_time)) = (dedup_hash, args.created_at_time) {
+        record_dedup(hash, created_at_time, &tx_id, now);
+    }
+
+    Ok(tx_id)
+}
+
+// Debits `amount + fee` from `from` and credits `amount` to `to`
+fn move_balance(
+    from: &Account,
+    to: &Account,
+    amount: &Nat,
+    fee: &Nat,
+    memo: Option<ByteBuf>,
+) -> Result<Nat, TransferError> {
     BALANCES.with(|balances| {
         let mut balances = balances.borrow_mut();
         
-        let from_balance = balances.get(&from_account)
+        let from_balance = balances.get(from)
             .map(|storable| storable.0.clone())
             .unwrap_or(Nat::from(0u64));
         
-        let total_deduction = args.amount.clone() + fee.clone();
+        let total_deduction = amount.clone() + fee.clone();
         
-        if from_balance < total_deduction {
-            return Err(TransferError::InsufficientFunds { balance: from_balance });
+        // Unreleased vesting stays locked in the account
+        let locked = locked_balance(from);
+        if from_balance < total_deduction.clone() + locked.clone() {
+            return Err(TransferError::InsufficientFunds { balance: spendable(&from_balance, &locked) });
         }
 
-        // Update balances
-        let new_from_balance = from_balance - total_deduction;
-        if new_from_balance == Nat::from(0u64) {
-            balances.remove(&from_account);
+        // Settle the fee first so a failure leaves every balance untouched
+        collect_fee(&mut balances, fee)?;
+
+        // Update balances, re-reading `from` in case it is the fee collector
+        let from_balance = balances.get(from)
+            .map(|storable| storable.0.clone())
+            .unwrap_or(Nat::from(0u64));
+        store_balance(&mut balances, from, from_balance - total_deduction);
+
+        let to_balance = balances.get(to)
+            .map(|storable| storable.0.clone())
+            .unwrap_or(Nat::from(0u64));
+        store_balance(&mut balances, to, to_balance + amount.clone());
+
+        // Record transaction
+        let tx_id = record_transaction(Operation::Transfer {
+            from: from.clone(),
+            to: to.clone(),
+            amount: amount.clone(),
+            fee: Some(fee.clone()),
+        }, memo, None);
+
+        Ok(tx_id)
+    })
+}
+
+// Debits `amount` from `from` and removes it from the total supply
+fn burn_balance(from: &Account, amount: &Nat, memo: Option<ByteBuf>) -> Result<Nat, TransferError> {
+    let min_burn_amount = METADATA.with(|m| m.borrow().get().fee.clone());
+    if *amount < min_burn_amount {
+        return Err(TransferError::BadBurn { min_burn_amount });
+    }
+
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+
+        let from_balance = balances.get(from)
+            .map(|storable| storable.0.clone())
+            .unwrap_or(Nat::from(0u64));
+
+        let locked = locked_balance(from);
+        if from_balance < amount.clone() + locked.clone() {
+            return Err(TransferError::InsufficientFunds { balance: spendable(&from_balance, &locked) });
+        }
+
+        reduce_total_supply(amount)?;
+
+        let new_from_balance = from_balance - amount.clone();
+        store_balance(&mut balances, from, new_from_balance);
+
+        let tx_id = record_transaction(Operation::Burn {
+            from: from.clone(),
+            amount: amount.clone(),
+        }, memo, None);
+
+        Ok(tx_id)
+    })
+}
+
+// Writes a balance, dropping empty accounts and keeping the subaccount index in sync
+fn store_balance(balances: &mut StableBTreeMap<Account, StorableNat, Memory>, account: &Account, balance: Nat) {
+    let is_empty = balance == 0u64;
+    if is_empty {
+        balances.remove(account);
+    } else {
+        balances.insert(account.clone(), StorableNat(balance.clone()));
+    }
+
+    if let Some(subaccount) = account.subaccount {
+        SUBACCOUNTS.with(|index| {
+            let mut index = index.borrow_mut();
+            if is_empty {
+                index.remove(&(account.owner, subaccount));
+            } else {
+                index.insert((account.owner, subaccount), ());
+            }
+        });
+    }
+
+    let key = account_key(&account.owner, account.subaccount.as_ref());
+    CERTIFIED_BALANCES.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        if is_empty {
+            tree.delete(&key);
         } else {
-            balances.insert(from_account.clone(), StorableNat(new_from_balance));
+            tree.insert(key, balance_leaf(&balance));
         }
+    });
+    update_certified_data();
+}
+
+// Balances re-inserted into CERTIFIED_BALANCES per message after an upgrade. Refilling the
+// whole tree in `post_upgrade` grows with the number of holders and would eventually exceed
+// the upgrade instruction limit, so the rest continues from the global timer.
+const CERTIFICATION_REBUILD_CHUNK: usize = 10_000;
+
+enum CertificationRebuild {
+    Complete,
+    // Accounts up to and including `after` are back in the tree
+    Pending { after: Option<Account> },
+}
+
+fn certification_pending() -> bool {
+    CERTIFICATION_REBUILD.with(|rebuild| {
+        matches!(*rebuild.borrow(), CertificationRebuild::Pending { .. })
+    })
+}
+
+fn start_certification_rebuild(limit: usize) {
+    CERTIFIED_BALANCES.with(|tree| *tree.borrow_mut() = RbTree::new());
+    CERTIFICATION_REBUILD.with(|rebuild| {
+        *rebuild.borrow_mut() = CertificationRebuild::Pending { after: None }
+    });
+    continue_certification_rebuild(limit);
+}
+
+// Re-inserts up to `limit` balances and certifies the tree once every balance is back;
+// otherwise arms the global timer for the next chunk. Transfers during the rebuild update
+// the tree as usual, so accounts behind the cursor stay current.
+fn continue_certification_rebuild(limit: usize) {
+    let after = CERTIFICATION_REBUILD.with(|rebuild| match &*rebuild.borrow() {
+        CertificationRebuild::Pending { after } => Some(after.clone()),
+        CertificationRebuild::Complete => None,
+    });
+    let Some(after) = after else { return };
+
+    let (last, count) = BALANCES.with(|balances| {
+        CERTIFIED_BALANCES.with(|tree| {
+            let balances = balances.borrow();
+            let mut tree = tree.borrow_mut();
+            let range = match after {
+                Some(after) => balances.range((Bound::Excluded(after), Bound::Unbounded)),
+                None => balances.range(..),
+            };
+            let (mut last, mut count) = (None, 0);
+            for (account, balance) in range.take(limit) {
+                tree.insert(
+                    account_key(&account.owner, account.subaccount.as_ref()),
+                    balance_leaf(&balance.0),
+                );
+                last = Some(account);
+                count += 1;
+            }
+            (last, count)
+        })
+    });
+
+    // A short chunk reached the end of BALANCES
+    let complete = count < limit;
+    CERTIFICATION_REBUILD.with(|rebuild| {
+        *rebuild.borrow_mut() = if complete {
+            CertificationRebuild::Complete
+        } else {
+            CertificationRebuild::Pending { after: last }
+        }
+    });
+
+    if complete {
+        update_certified_data();
+    } else {
+        env::set_global_timer(env::time());
+    }
+}
+
+fn update_certified_data() {
+    // A partial tree would certify missing balances as absent
+    if certification_pending() {
+        return;
+    }
+    let root = CERTIFIED_BALANCES.with(|tree| {
+        certified_client::labeled_root_hash(BALANCES_LABEL, &tree.borrow())
+    });
+    env::set_certified_data(root);
+}
+
+// Part of a balance that is not locked by a vesting schedule
+fn spendable(balance: &Nat, locked: &Nat) -> Nat {
+    if balance > locked {
+        balance.clone() - locked.clone()
+    } else {
+        Nat::from(0u64)
+    }
+}
+
+// Credits the fee to the fee collector, or burns it when none is configured
+fn collect_fee(balances: &mut StableBTreeMap<Account, StorableNat, Memory>, fee: &Nat) -> Result<(), TransferError> {
+    if *fee == 0u64 {
+        return Ok(());
+    }
+
+    match METADATA.with(|m| m.borrow().get().fee_collector.clone()) {
+        Some(collector) => {
+            let collector_balance = balances.get(&collector)
+                .map(|storable| storable.0.clone())
+                .unwrap_or(Nat::from(0u64));
+            store_balance(balances, &collector, collector_balance + fee.clone());
+            Ok(())
+        }
+        None => reduce_total_supply(fee),
+    }
+}
+
+// Removes burned tokens from the total supply, refusing to let it underflow
+fn reduce_total_supply(amount: &Nat) -> Result<(), TransferError> {
+    mutate_metadata(|metadata| {
+        metadata.total_supply = checked_sub(&metadata.total_supply, amount).ok_or_else(|| {
+            TransferError::GenericError {
+                error_code: Nat::from(500u32),
+                message: format!("Burning {} exceeds the total supply of {}", amount, metadata.total_supply),
+            }
+        })?;
+        Ok(())
+    })
+}
+
+fn checked_sub(lhs: &Nat, rhs: &Nat) -> Option<Nat> {
+    (lhs >= rhs).then(|| lhs.clone() - rhs.clone())
+}
+
+fn is_minting_account(account: &Account) -> bool {
+    METADATA.with(|m| m.borrow().get().minting_account.as_ref() == Some(account))
+}
+
+// ICRC-2 Standard Methods
+#[update]
+fn icrc2_approve(args: ApproveArgs) -> Result<Nat, ApproveError> {
+    let caller = caller();
+
+    if caller == Principal::anonymous() {
+        return Err(ApproveError::GenericError {
+            error_code: Nat::from(403u32),
+            message: "Anonymous approvals not allowed".to_string(),
+        });
+    }
+
+    acquire_lock(caller).map_err(|_| ApproveError::TemporarilyUnavailable)?;
+
+    let result = perform_approve(caller, args);
+
+    release_lock(caller);
+
+    result
+}
+
+fn perform_approve(caller: Principal, args: ApproveArgs) -> Result<Nat, ApproveError> {
+    let from_account = Account {
+        owner: caller,
+        subaccount: args.from_subaccount,
+    };
+
+    if from_account == args.spender {
+        return Err(ApproveError::GenericError {
+            error_code: Nat::from(400u32),
+            message: "Cannot approve own account as spender".to_string(),
+        });
+    }
+
+    let now = time();
+    validate_memo(args.memo.as_ref()).map_err(to_approve_error)?;
+    validate_created_at_time(args.created_at_time, now).map_err(to_approve_error)?;
+    let dedup_hash = args.created_at_time.map(|_| transaction_hash("icrc2_approve", caller, &args));
+    if let Some(duplicate_of) = dedup_hash.as_ref().and_then(find_duplicate) {
+        return Err(ApproveError::Duplicate { duplicate_of });
+    }
+
+    if let Some(expires_at) = args.expires_at {
+        if expires_at <= now {
+            return Err(ApproveError::Expired { ledger_time: now });
+        }
+    }
+
+    let expected_fee = METADATA.with(|m| m.borrow().get().fee.clone());
+    let fee = args.fee.unwrap_or_else(|| expected_fee.clone());
+    if fee != expected_fee {
+        return Err(ApproveError::BadFee { expected_fee });
+    }
 
-        let to_balance = balances.get(&args.to)
+    if let Some(expected_allowance) = &args.expected_allowance {
+        let current_allowance = allowance_of(&from_account, &args.spender, now).allowance;
+        if current_allowance != *expected_allowance {
+            return Err(ApproveError::AllowanceChanged { current_allowance });
+        }
+    }
+
+    // The approver pays the fee for the approval itself
+    let tx_id = BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+
+        let balance = balances.get(&from_account)
             .map(|storable| storable.0.clone())
             .unwrap_or(Nat::from(0u64));
-        balances.insert(args.to.clone(), StorableNat(to_balance + args.amount.clone()));
 
-        // Generate transaction ID
-        let tx_id = TX_COUNTER.with(|counter| {
-            let mut counter = counter.borrow_mut();
-            *counter += 1;
-            Nat::from(*counter)
-        });
+        let locked = locked_balance(&from_account);
+        if balance < fee.clone() + locked.clone() {
+            return Err(ApproveError::InsufficientFunds { balance: spendable(&balance, &locked) });
+        }
+
+        collect_fee(&mut balances, &fee).map_err(to_approve_error)?;
+
+        let balance = balances.get(&from_account)
+            .map(|storable| storable.0.clone())
+            .unwrap_or(Nat::from(0u64));
+        store_balance(&mut balances, &from_account, balance - fee.clone());
+
+        let tx_id = record_transaction(Operation::Approve {
+            from: from_account.clone(),
+            spender: args.spender.clone(),
+            amount: args.amount.clone(),
+            expires_at: args.expires_at,
+        }, args.memo.clone(), None);
 
         Ok(tx_id)
+    })?;
+
+    ALLOWANCES.with(|allowances| {
+        let mut allowances = allowances.borrow_mut();
+        let key = (from_account, args.spender);
+        if args.amount == 0u64 {
+            allowances.remove(&key);
+        } else {
+            allowances.insert(key, Allowance {
+                allowance: args.amount,
+                expires_at: args.expires_at,
+            });
+        }
+    });
+
+    if let (Some(hash), Some(created_at_time)) = (dedup_hash, args.created_at_time) {
+        record_dedup(hash, created_at_time, &tx_id, now);
+    }
+
+    Ok(tx_id)
+}
+
+#[update]
+fn icrc2_transfer_from(args: TransferFromArgs) -> Result<Nat, TransferFromError> {
+    let caller = caller();
+
+    if caller == Principal::anonymous() {
+        return Err(TransferFromError::GenericError {
+            error_code: Nat::from(403u32),
+            message: "Anonymous transfers not allowed".to_string(),
+        });
+    }
+
+    // Spending from the spender's own account does not need an allowance; other
+    // subaccounts of the same owner do, as for any other spender
+    if caller == args.from.owner && args.spender_subaccount == args.from.subaccount {
+        return icrc1_transfer(TransferArg {
+            from_subaccount: args.from.subaccount,
+            to: args.to,
+            amount: args.amount,
+            fee: args.fee,
+            memo: args.memo,
+            created_at_time: args.created_at_time,
+        })
+        .map_err(to_transfer_from_error);
+    }
+
+    acquire_lock(caller).map_err(to_transfer_from_error)?;
+
+    let result = perform_transfer_from(caller, args);
+
+    release_lock(caller);
+
+    result
+}
+
+fn perform_transfer_from(caller: Principal, args: TransferFromArgs) -> Result<Nat, TransferFromError> {
+    let spender = Account {
+        owner: caller,
+        subaccount: args.spender_subaccount,
+    };
+
+    if args.amount == 0u64 {
+        return Err(TransferFromError::GenericError {
+            error_code: Nat::from(400u32),
+            message: "Transfer amount must be greater than zero".to_string(),
+        });
+    }
+
+    if args.from == args.to {
+        return Err(TransferFromError::GenericError {
+            error_code: Nat::from(400u32),
+            message: "Cannot transfer to self".to_string(),
+        });
+    }
+
+    let now = time();
+    validate_memo(args.memo.as_ref()).map_err(to_transfer_from_error)?;
+    validate_created_at_time(args.created_at_time, now).map_err(to_transfer_from_error)?;
+    let dedup_hash = args.created_at_time.map(|_| transaction_hash("icrc2_transfer_from", caller, &args));
+    if let Some(duplicate_of) = dedup_hash.as_ref().and_then(find_duplicate) {
+        return Err(TransferFromError::Duplicate { duplicate_of });
+    }
+
+    let is_burn = is_minting_account(&args.to);
+    let expected_fee = if is_burn {
+        Nat::from(0u64)
+    } else {
+        METADATA.with(|m| m.borrow().get().fee.clone())
+    };
+    let fee = args.fee.clone().unwrap_or_else(|| expected_fee.clone());
+    if fee != expected_fee {
+        return Err(TransferFromError::BadFee { expected_fee });
+    }
+
+    // The spender's allowance covers both the amount and the fee
+    let current = allowance_of(&args.from, &spender, now);
+    let total_deduction = args.amount.clone() + fee.clone();
+    if current.allowance < total_deduction {
+        return Err(TransferFromError::InsufficientAllowance { allowance: current.allowance });
+    }
+
+    let tx_id = if is_burn {
+        burn_balance(&args.from, &args.amount, args.memo.clone())
+    } else {
+        move_balance(&args.from, &args.to, &args.amount, &fee, args.memo.clone())
+    }
+    .map_err(to_transfer_from_error)?;
+
+    ALLOWANCES.with(|allowances| {
+        let mut allowances = allowances.borrow_mut();
+        let key = (args.from.clone(), spender);
+        let remaining = current.allowance - total_deduction;
+        if remaining == 0u64 {
+            allowances.remove(&key);
+        } else {
+            allowances.insert(key, Allowance {
+                allowance: remaining,
+                expires_at: current.expires_at,
+            });
+        }
+    });
+
+    if let (Some(hash), Some(created_at_time)) = (dedup_hash, args.created_at_time) {
+        record_dedup(hash, created_at_time, &tx_id, now);
+    }
+
+    Ok(tx_id)
+}
+
+#[query]
+fn icrc2_allowance(args: AllowanceArgs) -> Allowance {
+    allowance_of(&args.account, &args.spender, time())
+}
+
+// Returns the current allowance, treating expired approvals as zero
+fn allowance_of(account: &Account, spender: &Account, now: u64) -> Allowance {
+    ALLOWANCES.with(|allowances| {
+        match allowances.borrow().get(&(account.clone(), spender.clone())) {
+            Some(allowance) if allowance.expires_at.is_none_or(|expires_at| expires_at > now) => allowance,
+            _ => Allowance {
+                allowance: Nat::from(0u64),
+                expires_at: None,
+            },
+        }
     })
 }
 
+fn to_approve_error(err: TransferError) -> ApproveError {
+    match err {
+        TransferError::BadFee { expected_fee } => ApproveError::BadFee { expected_fee },
+        TransferError::InsufficientFunds { balance } => ApproveError::InsufficientFunds { balance },
+        TransferError::TooOld => ApproveError::TooOld,
+        TransferError::CreatedInFuture { ledger_time } => ApproveError::CreatedInFuture { ledger_time },
+        TransferError::Duplicate { duplicate_of } => ApproveError::Duplicate { duplicate_of },
+        TransferError::TemporarilyUnavailable => ApproveError::TemporarilyUnavailable,
+        TransferError::GenericError { error_code, message } => ApproveError::GenericError { error_code, message },
+        TransferError::BadBurn { .. } => ApproveError::GenericError {
+            error_code: Nat::from(400u32),
+            message: "Burn is not a valid approval".to_string(),
+        },
+    }
+}
+
+fn to_transfer_from_error(err: TransferError) -> TransferFromError {
+    match err {
+        TransferError::BadFee { expected_fee } => TransferFromError::BadFee { expected_fee },
+        TransferError::BadBurn { min_burn_amount } => TransferFromError::BadBurn { min_burn_amount },
+        TransferError::InsufficientFunds { balance } => TransferFromError::InsufficientFunds { balance },
+        TransferError::TooOld => TransferFromError::TooOld,
+        TransferError::CreatedInFuture { ledger_time } => TransferFromError::CreatedInFuture { ledger_time },
+        TransferError::Duplicate { duplicate_of } => TransferFromError::Duplicate { duplicate_of },
+        TransferError::TemporarilyUnavailable => TransferFromError::TemporarilyUnavailable,
+        TransferError::GenericError { error_code, message } => TransferFromError::GenericError { error_code, message },
+    }
+}
+
 // Minting functions for rewards canister
 #[update]
 fn mint_rewards(request: MintRequest) -> Result<Nat, String> {
-    let caller = ic_cdk::api::msg_caller();
+    let caller = caller();
     
-    // Only minting account can mint
-    let is_authorized = METADATA.with(|m| {
-        m.borrow().minting_account
-            .as_ref()
-            .map(|account| account.owner == caller)
-            .unwrap_or(false)
-    });
+    // Only allowlisted minters can mint, within their quotas
+    authorize_mint(caller, &request.amount)?;
+    check_mint(&request, &Nat::from(0u64))?;
+
+    let tx_id = credit_mint(&request, None);
+    record_mint_usage(caller, &request.amount);
+
+    Ok(tx_id)
+}
+
+#[update]
+fn batch_mint_rewards(recipients: Vec<MintRequest>) -> Vec<Result<Nat, String>> {
+    let entries = recipients.len();
+    match batch_mint(BatchMintArgs {
+        mode: BatchMintMode::BestEffort,
+        mints: recipients,
+        memo: None,
+    }) {
+        Ok(result) => result.results,
+        Err(e) => vec![Err(e); entries],
+    }
+}
+
+#[update]
+fn batch_mint(args: BatchMintArgs) -> Result<BatchMintResult, String> {
+    let caller = caller();
+
+    if args.mints.is_empty() {
+        return Err("Batch is empty".to_string());
+    }
+    if args.mints.len() > MAX_BATCH_MINT_SIZE {
+        return Err(format!("Batch size exceeds maximum ({})", MAX_BATCH_MINT_SIZE));
+    }
+    check_mint_memo(args.memo.as_ref())?;
     
-    if !is_authorized {
-        return Err("Unauthorized: Only rewards canister can mint".to_string());
+    // The daily quota covers the whole batch, the per-call maximum each entry
+    let config = minter_config(caller)?;
+    let requested = args.mints.iter()
+        .fold(Nat::from(0u64), |acc, request| acc + request.amount.clone());
+    check_daily_quota(caller, &config, &requested)?;
+    let check_entry = |request: &MintRequest, pending: &Nat| {
+        check_per_call_max(&config, &request.amount)?;
+        check_mint(request, pending)
+    };
+
+    // In atomic mode every entry has to pass before anything is minted
+    if args.mode == BatchMintMode::Atomic {
+        let mut pending = Nat::from(0u64);
+        for (index, request) in args.mints.iter().enumerate() {
+            check_entry(request, &pending)
+                .map_err(|e| format!("Entry {}: {}", index, e))?;
+            pending += request.amount.clone();
+        }
+    }
+
+    let batch_id = MINT_BATCHES.with(|b| b.borrow().last_key_value().map(|(id, _)| id + 1).unwrap_or(0));
+
+    let mut results = Vec::new();
+    let mut total_minted = Nat::from(0u64);
+
+    for request in &args.mints {
+        if let Err(e) = check_entry(request, &Nat::from(0u64)) {
+            results.push(Err(e));
+            continue;
+        }
+
+        let memo = request.memo.clone().or_else(|| args.memo.clone());
+        let tx_id = credit_mint(&MintRequest { memo, ..request.clone() }, Some(batch_id));
+        total_minted += request.amount.clone();
+        results.push(Ok(tx_id));
+    }
+
+    record_mint_usage(caller, &total_minted);
+
+    MINT_BATCHES.with(|b| {
+        b.borrow_mut().insert(batch_id, MintBatch {
+            batch_id,
+            minter: caller,
+            mode: args.mode,
+            memo: args.memo,
+            entries: args.mints.len() as u32,
+            minted: results.iter().filter(|r| r.is_ok()).count() as u32,
+            total_minted: total_minted.clone(),
+            timestamp: time(),
+        });
+    });
+
+    Ok(BatchMintResult {
+        batch_id,
+        results,
+        total_minted,
+    })
+}
+
+// Checks a single mint and its memo against the max supply, counting `pending` as already minted
+fn check_mint(request: &MintRequest, pending: &Nat) -> Result<(), String> {
+    if request.amount == 0u64 {
+        return Err("Mint amount must be greater than zero".to_string());
+    }
+    if is_minting_account(&request.to) {
+        return Err("Cannot mint to the minting account".to_string());
     }
+    check_mint_memo(request.memo.as_ref())?;
 
-    // Check max supply if set
     METADATA.with(|m| {
-        let metadata = m.borrow();
+        let cell = m.borrow();
+        let metadata = cell.get();
         if let Some(max_supply) = &metadata.max_supply {
-            if metadata.total_supply.clone() + request.amount.clone() > *max_supply {
-                return Err("Cannot mint: would exceed max supply".to_string());
+            if metadata.total_supply.clone() + pending.clone() + request.amount.clone() > *max_supply {
+                return Err("Would exceed max supply".to_string());
             }
         }
         Ok(())
-    })?;
+    })
+}
 
-    // Mint tokens
+fn check_mint_memo(memo: Option<&ByteBuf>) -> Result<(), String> {
+    validate_memo(memo).map_err(|_| format!("Memo exceeds maximum length of {} bytes", MAX_MEMO_LENGTH))
+}
+
+// Credits a mint to its recipient, bumps the total supply and logs it
+fn credit_mint(request: &MintRequest, batch_id: Option<u64>) -> Nat {
     BALANCES.with(|balances| {
         let mut balances = balances.borrow_mut();
-        let current_balance = balances.get(&request.to)
+        let current = balances.get(&request.to)
             .map(|storable| storable.0.clone())
             .unwrap_or(Nat::from(0u64));
-        balances.insert(request.to.clone(), StorableNat(current_balance + request.amount.clone()));
+        store_balance(&mut balances, &request.to, current + request.amount.clone());
     });
 
-    // Update total supply
+    mutate_metadata(|metadata| metadata.total_supply += request.amount.clone());
+
+    record_transaction(Operation::Mint {
+        to: request.to.clone(),
+        amount: request.amount.clone(),
+    }, request.memo.clone(), batch_id)
+}
+
+// Token Vesting
+
+// Credits a grant and locks it under its schedule. Grants count against the max supply like
+// mints; callers outside `init` also log the mint.
+fn add_vesting_grant(grant: &VestingGrant) -> Result<(), String> {
+    if grant.cliff_time < grant.start_time || grant.end_time < grant.cliff_time {
+        return Err(format!("Invalid vesting schedule for {}", grant.account.owner));
+    }
+    // An account holds a single schedule; a second grant would replace the first one's lock
+    if VESTING.with(|vesting| vesting.borrow().contains_key(&grant.account)) {
+        return Err(format!("Duplicate vesting grant for {}", grant.account.owner));
+    }
     METADATA.with(|m| {
-        let mut metadata = m.borrow_mut();
-        metadata.total_supply += request.amount.clone();
+        let cell = m.borrow();
+        let metadata = cell.get();
+        match &metadata.max_supply {
+            Some(max_supply) if metadata.total_supply.clone() + grant.amount.clone() > *max_supply => {
+                Err(format!("Vesting grant for {} would exceed max supply", grant.account.owner))
+            }
+            _ => Ok(()),
+        }
+    })?;
+
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        let current = balances.get(&grant.account)
+            .map(|storable| storable.0.clone())
+            .unwrap_or(Nat::from(0u64));
+        store_balance(&mut balances, &grant.account, current + grant.amount.clone());
+    });
+    mutate_metadata(|metadata| metadata.total_supply += grant.amount.clone());
+
+    VESTING.with(|vesting| {
+        vesting.borrow_mut().insert(grant.account.clone(), VestingSchedule {
+            kind: grant.kind.clone(),
+            total_amount: grant.amount.clone(),
+            released_amount: Nat::from(0u64),
+            start_time: grant.start_time,
+            cliff_time: grant.cliff_time,
+            end_time: grant.end_time,
+        });
     });
+    Ok(())
+}
 
-    // Generate transaction ID
-    let tx_id = TX_COUNTER.with(|counter| {
-        let mut counter = counter.borrow_mut();
-        *counter += 1;
-        Nat::from(*counter)
+#[update]
+fn release_vested(subaccount: Option<[u8; 32]>) -> Result<Nat, String> {
+    let account = Account {
+        owner: caller(),
+        subaccount,
+    };
+    let now = time();
+
+    let mut schedule = VESTING.with(|vesting| vesting.borrow().get(&account))
+        .ok_or_else(|| "No vesting schedule for this account".to_string())?;
+
+    let releasable = spendable(&vested_amount(&schedule, now), &schedule.released_amount);
+    if releasable == 0u64 {
+        return Err("Nothing to release yet".to_string());
+    }
+
+    schedule.released_amount += releasable.clone();
+    VESTING.with(|vesting| {
+        let mut vesting = vesting.borrow_mut();
+        if schedule.released_amount >= schedule.total_amount {
+            vesting.remove(&account);
+        } else {
+            vesting.insert(account, schedule);
+        }
     });
 
-    Ok(tx_id)
+    Ok(releasable)
+}
+
+#[query]
+fn vesting_schedule_of(account: Account) -> Option<VestingStatus> {
+    let now = time();
+    VESTING.with(|vesting| vesting.borrow().get(&account)).map(|schedule| {
+        let vested = vested_amount(&schedule, now);
+        VestingStatus {
+            releasable_amount: spendable(&vested, &schedule.released_amount),
+            locked_amount: spendable(&schedule.total_amount, &schedule.released_amount),
+            vested_amount: vested,
+            schedule,
+        }
+    })
+}
+
+#[query]
+fn transferable_balance_of(account: Account) -> Nat {
+    let locked = locked_balance(&account);
+    spendable(&icrc1_balance_of(account), &locked)
+}
+
+// Amount of the schedule that has vested by `now`, released or not
+fn vested_amount(schedule: &VestingSchedule, now: u64) -> Nat {
+    if now < schedule.cliff_time {
+        return Nat::from(0u64);
+    }
+    if schedule.kind == VestingKind::Cliff || now >= schedule.end_time || schedule.end_time <= schedule.start_time {
+        return schedule.total_amount.clone();
+    }
+
+    let elapsed = now - schedule.start_time;
+    let duration = schedule.end_time - schedule.start_time;
+    schedule.total_amount.clone() * Nat::from(elapsed) / Nat::from(duration)
+}
+
+// Amount still locked; it only shrinks when the owner calls `release_vested`
+fn locked_balance(account: &Account) -> Nat {
+    VESTING.with(|vesting| {
+        vesting.borrow().get(account)
+            .map(|schedule| spendable(&schedule.total_amount, &schedule.released_amount))
+            .unwrap_or(Nat::from(0u64))
+    })
 }
 
+// Minter allowlist and rate limits
+fn authorize_mint(minter: Principal, amount: &Nat) -> Result<(), String> {
+    let config = minter_config(minter)?;
+    check_per_call_max(&config, amount)?;
+    check_daily_quota(minter, &config, amount)
+}
+
+fn minter_config(minter: Principal) -> Result<MinterConfig, String> {
+    if GOVERNANCE.with(|g| g.borrow().get().minting_paused) {
+        return Err("Minting is paused".to_string());
+    }
+
+    MINTERS.with(|m| m.borrow().get(&minter))
+        .ok_or_else(|| "Unauthorized: caller is not an allowlisted minter".to_string())
+}
+
+fn check_per_call_max(config: &MinterConfig, amount: &Nat) -> Result<(), String> {
+    if *amount > config.per_call_max {
+        return Err(format!("Mint of {} exceeds per-call maximum of {}", amount, config.per_call_max));
+    }
+    Ok(())
+}
+
+fn check_daily_quota(minter: Principal, config: &MinterConfig, amount: &Nat) -> Result<(), String> {
+    let minted_today = minted_today(minter);
+    if minted_today.clone() + amount.clone() > config.daily_quota {
+        return Err(format!(
+            "Mint would exceed daily quota: {} of {} already minted today",
+            minted_today, config.daily_quota
+        ));
+    }
+
+    Ok(())
+}
+
+fn minted_today(minter: Principal) -> Nat {
+    let day = time() / NANOS_PER_DAY;
+    MINT_USAGE.with(|usage| {
+        usage.borrow().get(&(minter, day))
+            .map(|storable| storable.0)
+            .unwrap_or(Nat::from(0u64))
+    })
+}
+
+fn record_mint_usage(minter: Principal, amount: &Nat) {
+    let day = time() / NANOS_PER_DAY;
+    let total = minted_today(minter) + amount.clone();
+    MINT_USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        // Usage from previous days is no longer needed
+        let stale: Vec<(Principal, u64)> = usage.iter()
+            .map(|(key, _)| key)
+            .filter(|(_, d)| *d < day)
+            .collect();
+        for key in stale {
+            usage.remove(&key);
+        }
+        usage.insert((minter, day), StorableNat(total));
+    });
+}
+
+// Adds the minting account owner to an empty allowlist with the default limits, or hands
+// the allowlist entry of the previous minting account owner over to the new one
+fn seed_default_minter(previous_minting_account: Option<Account>) {
+    let minter = match METADATA.with(|m| m.borrow().get().minting_account.clone()) {
+        Some(account) => account.owner,
+        None => return,
+    };
+
+    MINTERS.with(|m| {
+        let mut minters = m.borrow_mut();
+        if minters.is_empty() {
+            minters.insert(minter, MinterConfig {
+                daily_quota: Nat::from(DEFAULT_DAILY_MINT_QUOTA),
+                per_call_max: Nat::from(DEFAULT_PER_CALL_MINT_MAX),
+            });
+            return;
+        }
+
+        let previous_minter = match previous_minting_account {
+            Some(account) if account.owner != minter => account.owner,
+            _ => return,
+        };
+        if let Some(config) = minters.remove(&previous_minter) {
+            if !minters.contains_key(&minter) {
+                minters.insert(minter, config);
+            }
+        }
+    });
+}
+
+// Governance: the controller acts alone, admins act once `threshold` of them approve
 #[update]
-fn batch_mint_rewards(recipients: Vec<MintRequest>) -> Vec<Result<Nat, String>> {
-    let caller = ic_cdk::api::msg_caller();
-    
-    // Check authorization once
-    let is_authorized = METADATA.with(|m| {
-        m.borrow().minting_account
-            .as_ref()
-            .map(|account| account.owner == caller)
-            .unwrap_or(false)
+fn propose_governance_action(action: GovernanceAction) -> Result<u64, String> {
+    let caller = caller();
+    let governance = GOVERNANCE.with(|g| g.borrow().get().clone());
+
+    if governance.controller == Some(caller) {
+        let id = next_proposal_id();
+        execute_governance_action(id, action, vec![caller])?;
+        return Ok(id);
+    }
+
+    if !governance.admins.contains(&caller) {
+        return Err("Unauthorized: only the controller or an admin can propose changes".to_string());
+    }
+
+    let id = next_proposal_id();
+    if governance.threshold <= 1 {
+        execute_governance_action(id, action, vec![caller])?;
+        return Ok(id);
+    }
+
+    prune_expired_proposals();
+    PROPOSALS.with(|p| {
+        p.borrow_mut().insert(id, GovernanceProposal {
+            id,
+            action,
+            proposer: caller,
+            approvals: vec![caller],
+            created_at: time(),
+        });
     });
-    
-    if !is_authorized {
-        return vec![Err("Unauthorized".to_string()); recipients.len()];
+
+    Ok(id)
+}
+
+#[update]
+fn approve_governance_action(id: u64) -> Result<String, String> {
+    let caller = caller();
+    let governance = GOVERNANCE.with(|g| g.borrow().get().clone());
+
+    if !governance.admins.contains(&caller) && governance.controller != Some(caller) {
+        return Err("Unauthorized: only the controller or an admin can approve changes".to_string());
     }
 
-    let mut results = Vec::new();
-    let mut total_minted = Nat::from(0u64);
+    let mut proposal = PROPOSALS.with(|p| p.borrow().get(&id))
+        .ok_or_else(|| format!("Proposal {} not found", id))?;
 
-    for request in recipients {
-        // Check max supply
-        let can_mint = METADATA.with(|m| {
-            let metadata = m.borrow();
-            if let Some(max_supply) = &metadata.max_supply {
-                metadata.total_supply.clone() + total_minted.clone() + request.amount.clone() <= *max_supply
-            } else {
-                true
+    if is_expired(&proposal) {
+        PROPOSALS.with(|p| p.borrow_mut().remove(&id));
+        return Err(format!("Proposal {} has expired", id));
+    }
+
+    if proposal.approvals.contains(&caller) {
+        return Err("Proposal already approved by caller".to_string());
+    }
+    proposal.approvals.push(caller);
+
+    // The controller's approval is always sufficient
+    let admin_approvals = proposal.approvals.iter()
+        .filter(|approver| governance.admins.contains(approver))
+        .count();
+    if governance.controller == Some(caller) || admin_approvals >= governance.threshold as usize {
+        // A failed action leaves the proposal pending as it was stored
+        execute_governance_action(id, proposal.action, proposal.approvals)?;
+        PROPOSALS.with(|p| p.borrow_mut().remove(&id));
+        return Ok(format!("Proposal {} executed", id));
+    }
+
+    let approvals = proposal.approvals.len();
+    PROPOSALS.with(|p| p.borrow_mut().insert(id, proposal));
+    Ok(format!("Proposal {} has {} of {} approvals", id, approvals, governance.threshold))
+}
+
+fn execute_governance_action(id: u64, action: GovernanceAction, approved_by: Vec<Principal>) -> Result<(), String> {
+    match action.clone() {
+        GovernanceAction::SetMinter { minter, config } => {
+            MINTERS.with(|m| m.borrow_mut().insert(minter, config));
+        }
+        GovernanceAction::RemoveMinter { minter } => {
+            MINTERS.with(|m| m.borrow_mut().remove(&minter));
+        }
+        GovernanceAction::SetMintingPaused(paused) => {
+            mutate_governance(|governance| governance.minting_paused = paused);
+        }
+        GovernanceAction::SetAdmins { admins, threshold } => {
+            if threshold == 0 || threshold as usize > admins.len().max(1) {
+                return Err("Threshold must be between 1 and the number of admins".to_string());
             }
+            mutate_governance(|governance| {
+                governance.admins = admins;
+                governance.threshold = threshold;
+            });
+        }
+        GovernanceAction::SetController(controller) => {
+            mutate_governance(|governance| governance.controller = controller);
+        }
+        GovernanceAction::AddVestingGrant(grant) => {
+            add_vesting_grant(&grant)?;
+            record_transaction(Operation::Mint {
+                to: grant.account,
+                amount: grant.amount,
+            }, None, None);
+        }
+    }
+
+    GOVERNANCE_LOG.with(|log| {
+        log.borrow_mut().insert(id, GovernanceRecord {
+            id,
+            action,
+            approved_by,
+            executed_at: time(),
         });
+    });
 
-        if !can_mint {
-            results.push(Err("Would exceed max supply".to_string()));
-            continue;
+    Ok(())
+}
+
+fn is_expired(proposal: &GovernanceProposal) -> bool {
+    proposal.created_at + PROPOSAL_TTL_NANOS < time()
+}
+
+// Expired proposals can no longer be approved; drop them when new ones are stored
+fn prune_expired_proposals() {
+    PROPOSALS.with(|p| {
+        let mut proposals = p.borrow_mut();
+        let expired: Vec<u64> = proposals.iter()
+            .filter(|(_, proposal)| is_expired(proposal))
+            .map(|(id, _)| id)
+            .collect();
+        for id in expired {
+            proposals.remove(&id);
         }
+    });
+}
 
-        // Mint tokens
-        BALANCES.with(|balances| {
-            let mut balances = balances.borrow_mut();
-            let current = balances.get(&request.to)
-                .map(|storable| storable.0.clone())
-                .unwrap_or(Nat::from(0u64));
-            balances.insert(request.to.clone(), StorableNat(current + request.amount.clone()));
-        });
+fn next_proposal_id() -> u64 {
+    // Counters created by an upgrade start after the ids already in use
+    let last_proposal = PROPOSALS.with(|p| p.borrow().last_key_value().map(|(id, _)| id));
+    let last_executed = GOVERNANCE_LOG.with(|log| log.borrow().last_key_value().map(|(id, _)| id));
+    let first_unused = last_proposal.max(last_executed).map(|id| id + 1).unwrap_or(0);
 
-        total_minted += request.amount.clone();
+    PROPOSAL_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let id = (*counter.get()).max(first_unused);
+        counter.set(id + 1).expect("failed to persist proposal counter");
+        id
+    })
+}
+
+fn mutate_governance<R>(f: impl FnOnce(&mut GovernanceConfig) -> R) -> R {
+    GOVERNANCE.with(|g| {
+        let mut cell = g.borrow_mut();
+        let mut governance = cell.get().clone();
+        let result = f(&mut governance);
+        cell.set(governance).expect("failed to persist governance config");
+        result
+    })
+}
 
-        // Generate transaction ID
-        let tx_id = TX_COUNTER.with(|counter| {
-            let mut counter = counter.borrow_mut();
-            *counter += 1;
-            Nat::from(*counter)
+#[query]
+fn get_minters() -> Vec<(Principal, MinterConfig)> {
+    MINTERS.with(|m| m.borrow().iter().collect())
+}
+
+#[query]
+fn get_minter_usage(minter: Principal) -> Nat {
+    minted_today(minter)
+}
+
+#[query]
+fn get_governance_config() -> GovernanceConfig {
+    GOVERNANCE.with(|g| g.borrow().get().clone())
+}
+
+#[query]
+fn get_pending_proposals() -> Vec<GovernanceProposal> {
+    PROPOSALS.with(|p| {
+        p.borrow().iter()
+            .map(|(_, proposal)| proposal)
+            .filter(|proposal| !is_expired(proposal))
+            .collect()
+    })
+}
+
+#[query]
+fn get_governance_log(start: u64, limit: u32) -> Vec<GovernanceRecord> {
+    GOVERNANCE_LOG.with(|log| {
+        log.borrow()
+            .range(start..)
+            .take(limit.min(100) as usize)
+            .map(|(_, record)| record)
+            .collect()
+    })
+}
+
+// Utility functions
+fn mutate_metadata<R>(f: impl FnOnce(&mut TokenMetadata) -> R) -> R {
+    METADATA.with(|m| {
+        let mut cell = m.borrow_mut();
+        let mut metadata = cell.get().clone();
+        let result = f(&mut metadata);
+        cell.set(metadata).expect("failed to persist token metadata");
+        result
+    })
+}
+
+// Assigns the next transaction ID and appends the transaction to the log
+fn record_transaction(operation: Operation, memo: Option<ByteBuf>, batch_id: Option<u64>) -> Nat {
+    let tx_id = TX_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next = *counter.get() + 1;
+        counter.set(next).expect("failed to persist transaction counter");
+        next
+    });
+
+    TRANSACTIONS.with(|transactions| {
+        transactions.borrow_mut().insert(tx_id, Transaction {
+            operation,
+            timestamp: time(),
+            memo,
+            batch_id,
         });
+    });
 
-        results.push(Ok(tx_id));
+    Nat::from(tx_id)
+}
+
+// Makes sure `created_at_time` falls inside the deduplication window
+fn validate_created_at_time(created_at_time: Option<u64>, now: u64) -> Result<(), TransferError> {
+    if let Some(created_at_time) = created_at_time {
+        if created_at_time > now.saturating_add(PERMITTED_DRIFT_NANOS) {
+            return Err(TransferError::CreatedInFuture { ledger_time: now });
+        }
+        if created_at_time.saturating_add(TRANSACTION_WINDOW_NANOS + PERMITTED_DRIFT_NANOS) < now {
+            return Err(TransferError::TooOld);
+        }
     }
+    Ok(())
+}
 
-    // Update total supply
-    METADATA.with(|m| {
-        m.borrow_mut().total_supply += total_minted;
+fn validate_memo(memo: Option<&ByteBuf>) -> Result<(), TransferError> {
+    if let Some(memo) = memo {
+        if memo.len() > MAX_MEMO_LENGTH {
+            return Err(TransferError::GenericError {
+                error_code: Nat::from(400u32),
+                message: format!("Memo exceeds maximum length of {} bytes", MAX_MEMO_LENGTH),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Hash of the calling method, the caller and the candid-encoded arguments
+fn transaction_hash<T: CandidType>(method: &str, caller: Principal, args: &T) -> [u8; 32] {
+    use candid::Encode;
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(caller.as_slice());
+    hasher.update(Encode!(args).unwrap());
+    hasher.finalize().into()
+}
+
+fn find_duplicate(hash: &[u8; 32]) -> Option<Nat> {
+    TX_DEDUP.with(|dedup| dedup.borrow().get(hash).map(|storable| storable.0))
+}
+
+// Remembers a deduplicated transaction and prunes entries that left the window
+fn record_dedup(hash: [u8; 32], created_at_time: u64, tx_id: &Nat, now: u64) {
+    let cutoff = now.saturating_sub(TRANSACTION_WINDOW_NANOS + PERMITTED_DRIFT_NANOS);
+
+    let expired: Vec<(u64, [u8; 32])> = TX_DEDUP_EXPIRY.with(|expiry| {
+        expiry.borrow()
+            .range(..(cutoff, [0u8; 32]))
+            .take(MAX_DEDUP_PRUNE_PER_CALL)
+            .map(|(key, _)| key)
+            .collect()
     });
 
-    results
+    TX_DEDUP_EXPIRY.with(|expiry| {
+        let mut expiry = expiry.borrow_mut();
+        for key in &expired {
+            expiry.remove(key);
+        }
+        expiry.insert((created_at_time, hash), ());
+    });
+
+    TX_DEDUP.with(|dedup| {
+        let mut dedup = dedup.borrow_mut();
+        for (_, expired_hash) in &expired {
+            dedup.remove(expired_hash);
+        }
+        dedup.insert(hash, StorableNat(tx_id.clone()));
+    });
 }
 
-// Utility functions
 fn acquire_lock(principal: Principal) -> Result<(), TransferError> {
     TRANSFER_LOCKS.with(|locks| {
         let mut locks = locks.borrow_mut();
@@ -467,31 +2089,136 @@ fn release_lock(principal: Principal) {
 }
 
 // Additional utility methods
+#[query]
+fn get_transaction(tx_id: Nat) -> Option<Transaction> {
+    let tx_id = u64::try_from(&tx_id.0).ok()?;
+    TRANSACTIONS.with(|transactions| transactions.borrow().get(&tx_id))
+}
+
+#[query]
+fn get_mint_batch(batch_id: u64) -> Option<MintBatch> {
+    MINT_BATCHES.with(|b| b.borrow().get(&batch_id))
+}
+
 #[query]
 fn get_holder_count() -> u64 {
     BALANCES.with(|balances| balances.borrow().len())
 }
 
+// Lists an owner's non-empty subaccounts (the default account is not included)
 #[query]
-fn canister_status() -> String {
+fn list_subaccounts(owner: Principal) -> Vec<SubaccountBalance> {
+    let subaccounts: Vec<[u8; 32]> = SUBACCOUNTS.with(|index| {
+        index.borrow()
+            .range((owner, [0u8; 32])..=(owner, [u8::MAX; 32]))
+            .map(|((_, subaccount), _)| subaccount)
+            .collect()
+    });
+
+    subaccounts.into_iter()
+        .map(|subaccount| SubaccountBalance {
+            subaccount,
+            balance: icrc1_balance_of(Account {
+                owner,
+                subaccount: Some(subaccount),
+            }),
+        })
+        .collect()
+}
+
+#[query]
+fn get_fee_collector() -> Option<Account> {
+    METADATA.with(|m| m.borrow().get().fee_collector.clone())
+}
+
+fn sum_of_balances() -> Nat {
+    BALANCES.with(|balances| {
+        balances.borrow().iter()
+            .fold(Nat::from(0u64), |acc, (_, storable)| acc + storable.0)
+    })
+}
+
+// Sums every balance and compares it with the recorded total supply
+#[query]
+fn check_supply_invariant() -> SupplyInvariant {
+    let sum_of_balances = sum_of_balances();
+    let holder_count = BALANCES.with(|balances| balances.borrow().len());
+    let total_supply = icrc1_total_supply();
+
+    SupplyInvariant {
+        is_consistent: sum_of_balances == total_supply,
+        total_supply,
+        sum_of_balances,
+        holder_count,
+    }
+}
+
+#[query]
+fn canister_status() -> Value {
     let cycles = ic_cdk::api::canister_cycle_balance();
-    let memory_size = ic_cdk::api::stable::stable_size();
+    let memory_size = ic_cdk::stable::stable_size();
     
-    format!("cycles: {}, memory_size: {}, holders: {}, total_supply: {}", 
-            cycles, memory_size, get_holder_count(), icrc1_total_supply())
+    Value::Map(vec![
+        ("cycles".to_string(), Value::Nat(Nat::from(cycles))),
+        ("memory_size".to_string(), Value::Nat(Nat::from(memory_size))),
+        ("holder_count".to_string(), Value::Nat(Nat::from(get_holder_count()))),
+        ("total_supply".to_string(), Value::Nat(icrc1_total_supply())),
+    ])
 }
 
 // Security: Inspect message to reject malicious requests
 #[inspect_message]
 fn inspect_message() {
     let method_name = ic_cdk::api::msg_method_name();
-    let caller = ic_cdk::api::msg_caller();
+    let caller = caller();
     
     // Reject anonymous callers for state-changing operations
-    let state_changing_methods = vec!["icrc1_transfer", "mint_rewards", "batch_mint_rewards"];
+    let state_changing_methods = vec![
+        "icrc1_transfer",
+        "icrc2_approve",
+        "icrc2_transfer_from",
+        "mint_rewards",
+        "batch_mint_rewards",
+        "batch_mint",
+        "release_vested",
+        "propose_governance_action",
+        "approve_governance_action",
+    ];
     if caller == Principal::anonymous() && state_changing_methods.contains(&method_name.as_str()) {
         return;
     }
     
     ic_cdk::api::accept_message();
-}
\ No newline at end of file
+}
+
+ic_cdk::export_candid!();
+
+#[test]
+fn check_candid_interface_compatibility() {
+    use candid_parser::utils::{service_equal, CandidSource};
+
+    let new_interface = __export_service();
+
+    // check the public interface against the actual one
+    let old_interface = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("bikera_token.did");
+
+    service_equal(
+        CandidSource::Text(&new_interface),
+        CandidSource::File(old_interface.as_path()),
+    )
+    .unwrap();
+}
+
+#[test]
+fn should_fit_accounts_with_subaccounts_in_the_key_bound() {
+    let account = Account {
+        owner: Principal::from_slice(&[7; 29]),
+        subaccount: Some([1; 32]),
+    };
+
+    let ic_stable_structures::storable::Bound::Bounded { max_size, .. } = Account::BOUND else {
+        panic!("Account keys must be bounded");
+    };
+    assert!(account.to_bytes().len() <= max_size as usize);
+}
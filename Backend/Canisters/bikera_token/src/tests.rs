@@ -0,0 +1,862 @@
+use crate::env::testing::{certified_data, set_caller, set_time, take_global_timer};
+use crate::*;
+
+const CONTROLLER: Principal = Principal::from_slice(&[1; 29]);
+const MINTER: Principal = Principal::from_slice(&[2; 29]);
+const ALICE: Principal = Principal::from_slice(&[10; 29]);
+const BOB: Principal = Principal::from_slice(&[11; 29]);
+const CAROL: Principal = Principal::from_slice(&[12; 29]);
+
+const FEE: u64 = 1_000;
+// 2026-10-18T00:00:00Z
+const NOW: u64 = 1_792_281_600_000_000_000;
+
+fn nat(amount: u64) -> Nat {
+    Nat::from(amount)
+}
+
+fn account(owner: Principal) -> Account {
+    Account {
+        owner,
+        subaccount: None,
+    }
+}
+
+fn subaccount(owner: Principal, id: u8) -> Account {
+    Account {
+        owner,
+        subaccount: Some([id; 32]),
+    }
+}
+
+fn balance(account: Account) -> Nat {
+    icrc1_balance_of(account)
+}
+
+fn ok<T, E>(result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(_) => panic!("expected the call to succeed"),
+    }
+}
+
+fn init_args() -> TokenInitArgs {
+    TokenInitArgs {
+        name: "Bikera".to_string(),
+        symbol: "iMERA".to_string(),
+        decimals: 6,
+        fee: nat(FEE),
+        minting_account: Some(account(MINTER)),
+        initial_balances: vec![(account(ALICE), nat(1_000_000))],
+        max_supply: None,
+        fee_collector: None,
+        controller: None,
+        logo: None,
+        vesting: None,
+    }
+}
+
+// Installed by CONTROLLER at NOW with ALICE holding 1_000_000
+fn setup() {
+    setup_with(init_args());
+}
+
+fn setup_with(args: TokenInitArgs) {
+    set_time(NOW);
+    set_caller(CONTROLLER);
+    init(args);
+}
+
+fn transfer_arg(to: Account, amount: u64) -> TransferArg {
+    TransferArg {
+        from_subaccount: None,
+        to,
+        amount: nat(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn approve_arg(spender: Account, amount: u64) -> ApproveArgs {
+    ApproveArgs {
+        from_subaccount: None,
+        spender,
+        amount: nat(amount),
+        expected_allowance: None,
+        expires_at: None,
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn transfer_from_arg(from: Account, to: Account, amount: u64) -> TransferFromArgs {
+    TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount: nat(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn allowance(account: Account, spender: Account) -> Nat {
+    icrc2_allowance(AllowanceArgs { account, spender }).allowance
+}
+
+#[test]
+fn should_spend_allowances_with_transfer_from() {
+    setup();
+    set_caller(ALICE);
+    ok(icrc2_approve(approve_arg(account(BOB), 50_000)));
+    assert_eq!(balance(account(ALICE)), nat(1_000_000 - FEE));
+
+    set_caller(BOB);
+    ok(icrc2_transfer_from(transfer_from_arg(account(ALICE), account(CAROL), 20_000)));
+
+    assert_eq!(balance(account(CAROL)), nat(20_000));
+    assert_eq!(balance(account(ALICE)), nat(1_000_000 - 20_000 - 2 * FEE));
+    // The allowance covers the amount and the fee
+    assert_eq!(allowance(account(ALICE), account(BOB)), nat(50_000 - 20_000 - FEE));
+
+    let result = icrc2_transfer_from(transfer_from_arg(account(ALICE), account(CAROL), 29_000));
+    assert!(matches!(result, Err(TransferFromError::InsufficientAllowance { .. })));
+}
+
+#[test]
+fn should_check_expected_allowances_and_expiry() {
+    setup();
+    set_caller(ALICE);
+    ok(icrc2_approve(ApproveArgs {
+        expires_at: Some(NOW + 1_000),
+        ..approve_arg(account(BOB), 50_000)
+    }));
+
+    let result = icrc2_approve(ApproveArgs {
+        expected_allowance: Some(nat(10_000)),
+        ..approve_arg(account(BOB), 60_000)
+    });
+    assert!(matches!(result, Err(ApproveError::AllowanceChanged { current_allowance }) if current_allowance == 50_000u64));
+
+    set_time(NOW + 1_000);
+    assert_eq!(allowance(account(ALICE), account(BOB)), nat(0));
+    let result = icrc2_approve(ApproveArgs {
+        expires_at: Some(NOW),
+        ..approve_arg(account(BOB), 60_000)
+    });
+    assert!(matches!(result, Err(ApproveError::Expired { .. })));
+}
+
+#[test]
+fn should_compare_full_accounts_when_approving() {
+    setup();
+    set_caller(ALICE);
+
+    let result = icrc2_approve(approve_arg(account(ALICE), 10_000));
+    assert!(matches!(result, Err(ApproveError::GenericError { .. })));
+
+    // Another subaccount of the same owner is a different spender
+    ok(icrc2_approve(approve_arg(subaccount(ALICE, 1), 10_000)));
+    assert_eq!(allowance(account(ALICE), subaccount(ALICE, 1)), nat(10_000));
+}
+
+#[test]
+fn should_require_an_allowance_for_other_subaccounts_of_the_owner() {
+    setup();
+    set_caller(ALICE);
+    let from_savings = TransferFromArgs {
+        spender_subaccount: Some([1; 32]),
+        ..transfer_from_arg(account(ALICE), account(BOB), 5_000)
+    };
+
+    let result = icrc2_transfer_from(from_savings.clone());
+    assert!(matches!(result, Err(TransferFromError::InsufficientAllowance { .. })));
+
+    ok(icrc2_approve(approve_arg(subaccount(ALICE, 1), 10_000)));
+    ok(icrc2_transfer_from(from_savings));
+    assert_eq!(balance(account(BOB)), nat(5_000));
+    assert_eq!(allowance(account(ALICE), subaccount(ALICE, 1)), nat(10_000 - 5_000 - FEE));
+
+    // Spending from the spender's own account is a plain transfer
+    ok(icrc2_transfer_from(transfer_from_arg(account(ALICE), account(BOB), 5_000)));
+    assert_eq!(balance(account(BOB)), nat(10_000));
+}
+
+#[test]
+fn should_certify_balances_after_a_transfer() {
+    setup();
+    let root = certified_data();
+    set_caller(ALICE);
+    ok(icrc1_transfer(transfer_arg(account(BOB), 10_000)));
+
+    assert_eq!(balance(account(BOB)), nat(10_000));
+    assert_eq!(balance(account(ALICE)), nat(1_000_000 - 10_000 - FEE));
+    assert_ne!(certified_data(), root);
+}
+
+#[test]
+fn should_deduplicate_retried_transfers() {
+    setup();
+    set_caller(ALICE);
+    let retried = TransferArg {
+        created_at_time: Some(NOW),
+        ..transfer_arg(account(BOB), 10_000)
+    };
+
+    let tx_id = ok(icrc1_transfer(retried.clone()));
+    let result = icrc1_transfer(retried.clone());
+    assert!(matches!(result, Err(TransferError::Duplicate { duplicate_of }) if duplicate_of == tx_id));
+    assert_eq!(balance(account(BOB)), nat(10_000));
+
+    // Other arguments, other callers and transfers without created_at_time are not duplicates
+    ok(icrc1_transfer(TransferArg {
+        memo: Some(ByteBuf::from(vec![1])),
+        ..retried.clone()
+    }));
+    ok(icrc1_transfer(transfer_arg(account(BOB), 10_000)));
+    ok(icrc1_transfer(transfer_arg(account(BOB), 10_000)));
+    set_caller(BOB);
+    ok(icrc1_transfer(TransferArg {
+        to: account(ALICE),
+        ..retried
+    }));
+}
+
+#[test]
+fn should_reject_created_at_time_outside_the_window() {
+    setup();
+    set_caller(ALICE);
+
+    let result = icrc1_transfer(TransferArg {
+        created_at_time: Some(NOW + PERMITTED_DRIFT_NANOS + 1),
+        ..transfer_arg(account(BOB), 10_000)
+    });
+    assert!(matches!(result, Err(TransferError::CreatedInFuture { ledger_time }) if ledger_time == NOW));
+
+    let result = icrc1_transfer(TransferArg {
+        created_at_time: Some(NOW - TRANSACTION_WINDOW_NANOS - PERMITTED_DRIFT_NANOS - 1),
+        ..transfer_arg(account(BOB), 10_000)
+    });
+    assert!(matches!(result, Err(TransferError::TooOld)));
+
+    // Both edges of the window are accepted
+    ok(icrc1_transfer(TransferArg {
+        created_at_time: Some(NOW + PERMITTED_DRIFT_NANOS),
+        ..transfer_arg(account(BOB), 10_000)
+    }));
+    ok(icrc1_transfer(TransferArg {
+        created_at_time: Some(NOW - TRANSACTION_WINDOW_NANOS - PERMITTED_DRIFT_NANOS),
+        ..transfer_arg(account(BOB), 10_000)
+    }));
+}
+
+#[test]
+fn should_prune_dedup_entries_that_left_the_window() {
+    setup();
+    set_caller(ALICE);
+    let first = TransferArg {
+        created_at_time: Some(NOW),
+        ..transfer_arg(account(BOB), 10_000)
+    };
+    ok(icrc1_transfer(first));
+    assert_eq!(TX_DEDUP.with(|d| d.borrow().len()), 1);
+
+    let later = NOW + TRANSACTION_WINDOW_NANOS + PERMITTED_DRIFT_NANOS + 1;
+    set_time(later);
+    ok(icrc1_transfer(TransferArg {
+        created_at_time: Some(later),
+        ..transfer_arg(account(BOB), 10_000)
+    }));
+
+    assert_eq!(TX_DEDUP.with(|d| d.borrow().len()), 1);
+    assert_eq!(TX_DEDUP_EXPIRY.with(|e| e.borrow().len()), 1);
+    assert!(TX_DEDUP_EXPIRY.with(|e| e.borrow().iter().all(|((created_at_time, _), _)| created_at_time == later)));
+}
+
+#[test]
+fn should_burn_transfer_fees_without_a_fee_collector() {
+    setup();
+    set_caller(ALICE);
+
+    let result = icrc1_transfer(TransferArg {
+        fee: Some(nat(FEE - 1)),
+        ..transfer_arg(account(BOB), 10_000)
+    });
+    assert!(matches!(result, Err(TransferError::BadFee { expected_fee }) if expected_fee == FEE));
+
+    ok(icrc1_transfer(TransferArg {
+        fee: Some(nat(FEE)),
+        ..transfer_arg(account(BOB), 10_000)
+    }));
+    assert_eq!(icrc1_total_supply(), nat(1_000_000 - FEE));
+
+    let result = icrc1_transfer(transfer_arg(account(BOB), 1_000_000));
+    assert!(matches!(result, Err(TransferError::InsufficientFunds { balance }) if balance == 1_000_000 - 10_000 - FEE));
+}
+
+#[test]
+fn should_credit_transfer_fees_to_the_fee_collector() {
+    setup_with(TokenInitArgs {
+        fee_collector: Some(account(CAROL)),
+        ..init_args()
+    });
+    set_caller(ALICE);
+
+    ok(icrc1_transfer(transfer_arg(account(BOB), 10_000)));
+    ok(icrc2_approve(approve_arg(account(BOB), 10_000)));
+
+    assert_eq!(balance(account(CAROL)), nat(2 * FEE));
+    assert_eq!(icrc1_total_supply(), nat(1_000_000));
+    assert!(get_fee_collector() == Some(account(CAROL)));
+}
+
+#[test]
+fn should_burn_transfers_to_the_minting_account() {
+    setup();
+    set_caller(ALICE);
+
+    let result = icrc1_transfer(transfer_arg(account(MINTER), FEE - 1));
+    assert!(matches!(result, Err(TransferError::BadBurn { min_burn_amount }) if min_burn_amount == FEE));
+    let result = icrc1_transfer(TransferArg {
+        fee: Some(nat(FEE)),
+        ..transfer_arg(account(MINTER), 10_000)
+    });
+    assert!(matches!(result, Err(TransferError::BadFee { expected_fee }) if expected_fee == 0u64));
+
+    // Burns carry no fee
+    ok(icrc1_transfer(transfer_arg(account(MINTER), 10_000)));
+    assert_eq!(balance(account(ALICE)), nat(1_000_000 - 10_000));
+    assert_eq!(balance(account(MINTER)), nat(0));
+    assert_eq!(icrc1_total_supply(), nat(1_000_000 - 10_000));
+}
+
+#[test]
+fn should_derive_a_fresh_total_supply_from_balances_on_upgrade() {
+    setup();
+    set_caller(ALICE);
+    ok(icrc1_transfer(transfer_arg(account(BOB), 10_000)));
+    // A metadata cell created by the upgrade itself
+    mutate_metadata(|metadata| metadata.total_supply = nat(0));
+
+    set_caller(CONTROLLER);
+    post_upgrade(None);
+
+    assert_eq!(icrc1_total_supply(), nat(1_000_000 - FEE));
+    assert!(check_supply_invariant().is_consistent);
+}
+
+#[test]
+fn should_keep_an_existing_total_supply_on_upgrade() {
+    setup();
+    mutate_metadata(|metadata| metadata.total_supply = nat(2_000_000));
+
+    post_upgrade(None);
+
+    assert_eq!(icrc1_total_supply(), nat(2_000_000));
+}
+
+// ALICE, BOB, CAROL and two of CAROL's subaccounts hold balances
+fn setup_five_holders() {
+    let mut args = init_args();
+    args.initial_balances = vec![
+        (account(ALICE), nat(1_000_000)),
+        (account(BOB), nat(200_000)),
+        (account(CAROL), nat(300_000)),
+        (subaccount(CAROL, 1), nat(40_000)),
+        (subaccount(CAROL, 2), nat(50_000)),
+    ];
+    setup_with(args);
+}
+
+#[test]
+fn should_rebuild_the_certified_tree_in_chunks_after_upgrade() {
+    setup_five_holders();
+    let root = certified_data();
+    take_global_timer();
+
+    start_certification_rebuild(2);
+    let mut chunks = 1;
+    while take_global_timer().is_some() {
+        assert_eq!(
+            get_certified_balance(account(ALICE)).err().as_deref(),
+            Some("Certified balances are being rebuilt after an upgrade; retry shortly")
+        );
+        continue_certification_rebuild(2);
+        chunks += 1;
+    }
+
+    assert_eq!(chunks, 3);
+    assert!(!certification_pending());
+    assert_eq!(certified_data(), root);
+}
+
+#[test]
+fn should_keep_transfers_made_during_the_rebuild() {
+    setup_five_holders();
+    let root = certified_data();
+
+    start_certification_rebuild(2);
+    set_caller(ALICE);
+    ok(icrc1_transfer(transfer_arg(subaccount(BOB, 3), 10_000)));
+    ok(icrc1_transfer(transfer_arg(subaccount(CAROL, 2), 10_000)));
+    // Nothing is certified until every balance is back in the tree
+    assert_eq!(certified_data(), root);
+    while certification_pending() {
+        continue_certification_rebuild(2);
+    }
+    let rebuilt = certified_data();
+
+    start_certification_rebuild(usize::MAX);
+    assert_ne!(rebuilt, root);
+    assert_eq!(certified_data(), rebuilt);
+}
+
+#[test]
+fn should_refuse_burns_that_exceed_the_total_supply() {
+    setup();
+    mutate_metadata(|metadata| metadata.total_supply = nat(5_000));
+    let tx_count = TX_COUNTER.with(|counter| *counter.borrow().get());
+    set_caller(ALICE);
+
+    let result = icrc1_transfer(transfer_arg(account(MINTER), 10_000));
+    assert!(matches!(result, Err(TransferError::GenericError { error_code, .. }) if error_code == 500u64));
+    mutate_metadata(|metadata| metadata.total_supply = nat(FEE - 1));
+    let result = icrc1_transfer(transfer_arg(account(BOB), 10_000));
+    assert!(matches!(result, Err(TransferError::GenericError { error_code, .. }) if error_code == 500u64));
+    let result = icrc2_approve(approve_arg(account(BOB), 10_000));
+    assert!(matches!(result, Err(ApproveError::GenericError { error_code, .. }) if error_code == 500u64));
+
+    // Nothing moved
+    assert_eq!(balance(account(ALICE)), nat(1_000_000));
+    assert_eq!(balance(account(BOB)), nat(0));
+    assert_eq!(icrc1_total_supply(), nat(FEE - 1));
+    assert_eq!(TX_COUNTER.with(|counter| *counter.borrow().get()), tx_count);
+}
+
+fn mint_request(to: Account, amount: u64) -> MintRequest {
+    MintRequest {
+        to,
+        amount: nat(amount),
+        memo: None,
+        created_at_time: None,
+    }
+}
+
+fn minter_config(daily_quota: u64, per_call_max: u64) -> MinterConfig {
+    MinterConfig {
+        daily_quota: nat(daily_quota),
+        per_call_max: nat(per_call_max),
+    }
+}
+
+fn minters() -> Vec<(Principal, Nat, Nat)> {
+    get_minters()
+        .into_iter()
+        .map(|(minter, config)| (minter, config.daily_quota, config.per_call_max))
+        .collect()
+}
+
+#[test]
+fn should_hand_the_minter_over_to_a_new_minting_account() {
+    setup();
+    ok(propose_governance_action(GovernanceAction::SetMinter {
+        minter: MINTER,
+        config: minter_config(50_000, 20_000),
+    }));
+
+    post_upgrade(Some(TokenUpgradeArgs {
+        fee: None,
+        minting_account: Some(account(CAROL)),
+    }));
+
+    assert_eq!(minters(), vec![(CAROL, nat(50_000), nat(20_000))]);
+    set_caller(MINTER);
+    assert!(mint_rewards(mint_request(account(BOB), 10_000)).is_err());
+    set_caller(CAROL);
+    ok(mint_rewards(mint_request(account(BOB), 10_000)));
+
+    // Upgrades that keep the minting account leave the allowlist alone
+    post_upgrade(None);
+    assert_eq!(minters(), vec![(CAROL, nat(50_000), nat(20_000))]);
+}
+
+#[test]
+fn should_apply_the_per_call_maximum_to_each_batch_entry() {
+    setup();
+    ok(propose_governance_action(GovernanceAction::SetMinter {
+        minter: MINTER,
+        config: minter_config(40_000, 10_000),
+    }));
+    set_caller(MINTER);
+
+    // Entries under the cap may add up to more than it
+    let result = ok(batch_mint(BatchMintArgs {
+        mode: BatchMintMode::Atomic,
+        mints: vec![mint_request(account(ALICE), 8_000), mint_request(account(BOB), 8_000)],
+        memo: None,
+    }));
+    assert_eq!(result.total_minted, nat(16_000));
+
+    let result = batch_mint(BatchMintArgs {
+        mode: BatchMintMode::Atomic,
+        mints: vec![mint_request(account(ALICE), 8_000), mint_request(account(BOB), 12_000)],
+        memo: None,
+    });
+    assert!(matches!(result, Err(message) if message.starts_with("Entry 1:")));
+
+    let result = ok(batch_mint(BatchMintArgs {
+        mode: BatchMintMode::BestEffort,
+        mints: vec![mint_request(account(ALICE), 8_000), mint_request(account(BOB), 12_000)],
+        memo: None,
+    }));
+    assert!(result.results[0].is_ok() && result.results[1].is_err());
+    assert_eq!(balance(account(BOB)), nat(8_000));
+
+    // The daily quota still covers the batch as a whole
+    let result = batch_mint(BatchMintArgs {
+        mode: BatchMintMode::BestEffort,
+        mints: vec![mint_request(account(ALICE), 9_000), mint_request(account(BOB), 9_000)],
+        memo: None,
+    });
+    assert!(matches!(result, Err(message) if message.contains("daily quota")));
+}
+
+#[test]
+fn should_require_the_admin_threshold_for_proposals() {
+    setup();
+    assert_eq!(ok(propose_governance_action(GovernanceAction::SetAdmins {
+        admins: vec![ALICE, BOB, CAROL],
+        threshold: 2,
+    })), 0);
+
+    set_caller(ALICE);
+    let id = ok(propose_governance_action(GovernanceAction::SetMintingPaused(true)));
+    assert_eq!(get_pending_proposals().len(), 1);
+    assert!(approve_governance_action(id).is_err());
+    assert!(!get_governance_config().minting_paused);
+
+    set_caller(BOB);
+    ok(approve_governance_action(id));
+    assert!(get_governance_config().minting_paused);
+    assert!(get_pending_proposals().is_empty());
+    assert_eq!(get_governance_log(0, 10).iter().map(|record| record.id).collect::<Vec<_>>(), vec![0, id]);
+
+    set_caller(MINTER);
+    assert!(propose_governance_action(GovernanceAction::SetMintingPaused(false)).is_err());
+}
+
+#[test]
+fn should_not_reuse_the_ids_of_expired_proposals() {
+    setup();
+    ok(propose_governance_action(GovernanceAction::SetAdmins {
+        admins: vec![ALICE, BOB],
+        threshold: 2,
+    }));
+    set_caller(ALICE);
+    let expired = ok(propose_governance_action(GovernanceAction::SetMintingPaused(true)));
+
+    set_time(NOW + PROPOSAL_TTL_NANOS + 1);
+    set_caller(BOB);
+    assert!(approve_governance_action(expired).is_err());
+    assert!(get_pending_proposals().is_empty());
+
+    let id = ok(propose_governance_action(GovernanceAction::SetMintingPaused(true)));
+    assert!(id > expired);
+}
+
+#[test]
+fn should_hide_and_prune_expired_proposals() {
+    setup();
+    ok(propose_governance_action(GovernanceAction::SetAdmins {
+        admins: vec![ALICE, BOB],
+        threshold: 2,
+    }));
+    set_caller(ALICE);
+    let expired = ok(propose_governance_action(GovernanceAction::SetMintingPaused(true)));
+
+    set_time(NOW + PROPOSAL_TTL_NANOS + 1);
+    assert!(get_pending_proposals().is_empty());
+
+    let id = ok(propose_governance_action(GovernanceAction::SetMintingPaused(true)));
+    let stored: Vec<u64> = PROPOSALS.with(|p| p.borrow().iter().map(|(id, _)| id).collect());
+    assert_eq!(stored, vec![id]);
+    assert!(id > expired);
+}
+
+#[test]
+fn should_keep_proposals_whose_action_fails() {
+    setup();
+    ok(propose_governance_action(GovernanceAction::SetAdmins {
+        admins: vec![ALICE, BOB],
+        threshold: 2,
+    }));
+    set_caller(ALICE);
+    // No threshold of 2 fits an empty admin list
+    let id = ok(propose_governance_action(GovernanceAction::SetAdmins {
+        admins: vec![],
+        threshold: 2,
+    }));
+
+    set_caller(BOB);
+    assert!(approve_governance_action(id).is_err());
+
+    let pending = get_pending_proposals();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].approvals, vec![ALICE]);
+    assert_eq!(get_governance_config().admins, vec![ALICE, BOB]);
+    assert_eq!(get_governance_log(0, 10).len(), 1);
+}
+
+#[test]
+fn should_check_single_mints_like_batch_entries() {
+    setup_with(TokenInitArgs {
+        max_supply: Some(nat(1_050_000)),
+        ..init_args()
+    });
+    set_caller(MINTER);
+
+    assert!(mint_rewards(mint_request(account(BOB), 0)).is_err());
+    assert!(mint_rewards(mint_request(account(MINTER), 10_000)).is_err());
+    assert!(mint_rewards(mint_request(account(BOB), 60_000)).is_err());
+    let result = mint_rewards(MintRequest {
+        memo: Some(ByteBuf::from(vec![0; MAX_MEMO_LENGTH + 1])),
+        ..mint_request(account(BOB), 10_000)
+    });
+    assert!(matches!(result, Err(message) if message.starts_with("Memo exceeds")));
+
+    ok(mint_rewards(MintRequest {
+        memo: Some(ByteBuf::from(vec![0; MAX_MEMO_LENGTH])),
+        ..mint_request(account(BOB), 50_000)
+    }));
+    assert_eq!(balance(account(BOB)), nat(50_000));
+    assert_eq!(get_minter_usage(MINTER), nat(50_000));
+
+    set_caller(BOB);
+    assert!(mint_rewards(mint_request(account(BOB), 10_000)).is_err());
+}
+
+#[test]
+fn should_validate_batch_and_entry_memos() {
+    setup();
+    set_caller(MINTER);
+    let long_memo = Some(ByteBuf::from(vec![0; MAX_MEMO_LENGTH + 1]));
+
+    let result = batch_mint(BatchMintArgs {
+        mode: BatchMintMode::BestEffort,
+        mints: vec![mint_request(account(BOB), 10_000)],
+        memo: long_memo.clone(),
+    });
+    assert!(result.is_err());
+
+    let result = ok(batch_mint(BatchMintArgs {
+        mode: BatchMintMode::BestEffort,
+        mints: vec![
+            MintRequest { memo: long_memo, ..mint_request(account(ALICE), 10_000) },
+            mint_request(account(BOB), 10_000),
+        ],
+        memo: None,
+    }));
+    assert!(result.results[0].is_err() && result.results[1].is_ok());
+    assert_eq!(result.total_minted, nat(10_000));
+}
+
+#[test]
+fn should_accept_the_memos_of_the_rewards_canister() {
+    setup();
+    set_caller(MINTER);
+    // Longer than the memo cap on its own, as validation ids usually are
+    let validation_id = format!("validation-{}-{}", NOW, "f".repeat(64));
+    let reward_memo = |owner: Principal| {
+        let memo = canonical_hash::reward_mint_memo(&validation_id, &owner.to_text());
+        Some(ByteBuf::from(memo.to_vec()))
+    };
+
+    ok(mint_rewards(MintRequest {
+        memo: reward_memo(ALICE),
+        ..mint_request(account(ALICE), 10_000)
+    }));
+    let results = batch_mint_rewards(vec![
+        MintRequest { memo: reward_memo(BOB), ..mint_request(account(BOB), 10_000) },
+        MintRequest { memo: reward_memo(CAROL), ..mint_request(account(CAROL), 10_000) },
+    ]);
+
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert_eq!(balance(account(BOB)), nat(10_000));
+    assert_eq!(balance(account(CAROL)), nat(10_000));
+}
+
+fn subaccount_ids(owner: Principal) -> Vec<(u8, Nat)> {
+    list_subaccounts(owner)
+        .into_iter()
+        .map(|entry| (entry.subaccount[0], entry.balance))
+        .collect()
+}
+
+#[test]
+fn should_list_the_non_empty_subaccounts_of_an_owner() {
+    setup();
+    set_caller(ALICE);
+    ok(icrc1_transfer(transfer_arg(subaccount(ALICE, 2), 20_000)));
+    ok(icrc1_transfer(transfer_arg(subaccount(ALICE, 1), 10_000)));
+    ok(icrc1_transfer(transfer_arg(subaccount(BOB, 1), 30_000)));
+
+    // Sorted by subaccount, without the default account or other owners
+    assert_eq!(subaccount_ids(ALICE), vec![(1, nat(10_000)), (2, nat(20_000))]);
+    assert_eq!(subaccount_ids(BOB), vec![(1, nat(30_000))]);
+    assert!(subaccount_ids(CAROL).is_empty());
+}
+
+#[test]
+fn should_drop_emptied_subaccounts_from_the_index() {
+    setup();
+    set_caller(ALICE);
+    ok(icrc1_transfer(transfer_arg(subaccount(ALICE, 1), 10_000)));
+    ok(icrc1_transfer(transfer_arg(subaccount(ALICE, 2), 20_000)));
+
+    ok(icrc1_transfer(TransferArg {
+        from_subaccount: Some([1; 32]),
+        ..transfer_arg(account(BOB), 10_000 - FEE)
+    }));
+
+    assert_eq!(balance(subaccount(ALICE, 1)), nat(0));
+    assert_eq!(subaccount_ids(ALICE), vec![(2, nat(20_000))]);
+    assert!(SUBACCOUNTS.with(|index| !index.borrow().contains_key(&(ALICE, [1; 32]))));
+}
+
+const MONTH: u64 = 30 * NANOS_PER_DAY;
+
+fn vesting_grant(owner: Principal, kind: VestingKind) -> VestingGrant {
+    VestingGrant {
+        account: account(owner),
+        amount: nat(120_000),
+        kind,
+        start_time: NOW,
+        cliff_time: NOW + 3 * MONTH,
+        end_time: NOW + 12 * MONTH,
+    }
+}
+
+fn setup_vesting(grants: Vec<VestingGrant>) {
+    setup_with(TokenInitArgs {
+        vesting: Some(grants),
+        ..init_args()
+    });
+}
+
+#[test]
+#[should_panic(expected = "Duplicate vesting grant")]
+fn should_reject_duplicate_vesting_grants() {
+    setup_vesting(vec![
+        vesting_grant(BOB, VestingKind::Linear),
+        vesting_grant(BOB, VestingKind::Cliff),
+    ]);
+}
+
+#[test]
+#[should_panic(expected = "would exceed max supply")]
+fn should_reject_vesting_grants_above_the_max_supply() {
+    setup_with(TokenInitArgs {
+        max_supply: Some(nat(1_200_000)),
+        vesting: Some(vec![
+            vesting_grant(BOB, VestingKind::Linear),
+            vesting_grant(CAROL, VestingKind::Linear),
+        ]),
+        ..init_args()
+    });
+}
+
+#[test]
+fn should_add_vesting_grants_through_governance() {
+    setup_with(TokenInitArgs {
+        max_supply: Some(nat(1_200_000)),
+        ..init_args()
+    });
+
+    ok(propose_governance_action(GovernanceAction::AddVestingGrant(vesting_grant(BOB, VestingKind::Cliff))));
+    assert_eq!(balance(account(BOB)), nat(120_000));
+    assert_eq!(icrc1_total_supply(), nat(1_120_000));
+    assert!(vesting_schedule_of(account(BOB)).is_some());
+    assert!(check_supply_invariant().is_consistent);
+
+    // Over the max supply, and a second grant for the same account
+    assert!(propose_governance_action(GovernanceAction::AddVestingGrant(vesting_grant(CAROL, VestingKind::Cliff))).is_err());
+    assert!(propose_governance_action(GovernanceAction::AddVestingGrant(VestingGrant {
+        amount: nat(1_000),
+        ..vesting_grant(BOB, VestingKind::Cliff)
+    })).is_err());
+    assert_eq!(balance(account(CAROL)), nat(0));
+    assert_eq!(icrc1_total_supply(), nat(1_120_000));
+}
+
+#[test]
+fn should_vest_linearly_after_the_cliff() {
+    setup_vesting(vec![vesting_grant(BOB, VestingKind::Linear)]);
+    let schedule = VESTING.with(|vesting| vesting.borrow().get(&account(BOB))).unwrap();
+
+    assert_eq!(vested_amount(&schedule, NOW + 3 * MONTH - 1), nat(0));
+    assert_eq!(vested_amount(&schedule, NOW + 3 * MONTH), nat(30_000));
+    assert_eq!(vested_amount(&schedule, NOW + 6 * MONTH), nat(60_000));
+    assert_eq!(vested_amount(&schedule, NOW + 12 * MONTH), nat(120_000));
+    assert_eq!(vested_amount(&schedule, NOW + 24 * MONTH), nat(120_000));
+}
+
+#[test]
+fn should_vest_everything_at_the_cliff() {
+    setup_vesting(vec![vesting_grant(BOB, VestingKind::Cliff)]);
+    let schedule = VESTING.with(|vesting| vesting.borrow().get(&account(BOB))).unwrap();
+
+    assert_eq!(vested_amount(&schedule, NOW + 3 * MONTH - 1), nat(0));
+    assert_eq!(vested_amount(&schedule, NOW + 3 * MONTH), nat(120_000));
+}
+
+#[test]
+fn should_unlock_released_vesting() {
+    setup_vesting(vec![vesting_grant(BOB, VestingKind::Linear)]);
+    assert_eq!(icrc1_total_supply(), nat(1_000_000 + 120_000));
+    assert_eq!(balance(account(BOB)), nat(120_000));
+    set_caller(BOB);
+
+    // Nothing is spendable before the cliff
+    assert!(release_vested(None).is_err());
+    let result = icrc1_transfer(transfer_arg(account(CAROL), 10_000));
+    assert!(matches!(result, Err(TransferError::InsufficientFunds { balance }) if balance == 0u64));
+
+    set_time(NOW + 6 * MONTH);
+    assert_eq!(ok(release_vested(None)), nat(60_000));
+    assert!(release_vested(None).is_err());
+    assert_eq!(transferable_balance_of(account(BOB)), nat(60_000));
+    let status = vesting_schedule_of(account(BOB)).unwrap();
+    assert_eq!(status.locked_amount, nat(60_000));
+    assert_eq!(status.releasable_amount, nat(0));
+
+    ok(icrc1_transfer(transfer_arg(account(CAROL), 50_000)));
+    let result = icrc1_transfer(transfer_arg(account(CAROL), 10_000));
+    assert!(matches!(result, Err(TransferError::InsufficientFunds { balance }) if balance == 10_000u64 - FEE));
+
+    // A fully vested schedule is removed once released
+    set_time(NOW + 12 * MONTH);
+    assert_eq!(ok(release_vested(None)), nat(60_000));
+    assert!(vesting_schedule_of(account(BOB)).is_none());
+    assert_eq!(transferable_balance_of(account(BOB)), nat(120_000 - 50_000 - FEE));
+}
+
+#[test]
+fn should_leave_growing_collections_to_migrate_on_read() {
+    setup();
+    set_caller(ALICE);
+    ok(icrc1_transfer(transfer_arg(account(BOB), 10_000)));
+
+    set_caller(CONTROLLER);
+    post_upgrade(None);
+
+    let version_of = |collection: &str| SCHEMA_REGISTRY.with(|registry| registry.borrow().version_of(collection));
+    assert_eq!(version_of("balances"), None);
+    assert_eq!(version_of("transactions"), None);
+    assert_eq!(version_of("allowances"), Some(1));
+    assert_eq!(version_of("metadata"), Some(1));
+    assert_eq!(balance(account(BOB)), nat(10_000));
+    assert!(get_transaction(nat(1)).is_some());
+}
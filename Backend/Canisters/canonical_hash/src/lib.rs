@@ -0,0 +1,187 @@
+// canonical_hash/src/lib.rs - Canonical binary encoding behind every validator and consensus hash
+//
+// Hashes used to be computed over ad-hoc concatenations (`interval_id.to_string()` followed by
+// the merkle root, `uid.to_string()` next to big-endian coordinates), so different inputs
+// could produce the same preimage across field boundaries. Every hash is now SHA-256 over
+//
+//     version: u8 | domain | field | field | ...
+//
+// where, all integers being big-endian:
+//
+// - `version` is `ENCODING_VERSION` (1)
+// - `domain` is a text naming the hash (see `domain`), so no two hashes share a preimage
+// - u8 / u32 / u64 are fixed width, i32 is two's complement
+// - bytes and text (UTF-8) are a u32 length followed by the bytes
+// - lists are a u32 item count followed by the items
+//
+// `test_vectors.json` pins the encoding and the digest of every hash so the edge servers can
+// reproduce them bit for bit; the tests check the file against this crate.
+use sha2::{Digest, Sha256};
+
+#[cfg(test)]
+mod tests;
+
+pub const ENCODING_VERSION: u8 = 1;
+
+pub mod domain {
+    pub const BLOCK: &str = "bikera/consensus/block";
+    pub const CLUSTER_WINNERS: &str = "bikera/validator/cluster-winners";
+    pub const COMPETITION_COMMITMENT: &str = "bikera/validator/competition-commitment";
+    pub const COMPETITION_TARGET: &str = "bikera/validator/competition-target";
+    pub const REWARD_MINT_MEMO: &str = "bikera/rewards/mint-memo";
+}
+
+/// Builds the canonical encoding of one hash preimage, field by field.
+pub struct CanonicalEncoder {
+    bytes: Vec<u8>,
+}
+
+impl CanonicalEncoder {
+    pub fn new(domain: &str) -> Self {
+        let mut encoder = Self {
+            bytes: vec![ENCODING_VERSION],
+        };
+        encoder.text(domain);
+        encoder
+    }
+
+    pub fn u8(&mut self, value: u8) -> &mut Self {
+        self.bytes.push(value);
+        self
+    }
+
+    pub fn u32(&mut self, value: u32) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn u64(&mut self, value: u64) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn i32(&mut self, value: i32) -> &mut Self {
+        self.bytes.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    pub fn bytes(&mut self, value: &[u8]) -> &mut Self {
+        self.length_prefix(value.len());
+        self.bytes.extend_from_slice(value);
+        self
+    }
+
+    pub fn text(&mut self, value: &str) -> &mut Self {
+        self.bytes(value.as_bytes())
+    }
+
+    /// Starts a list of `count` items; the caller then encodes each item.
+    pub fn list(&mut self, count: usize) -> &mut Self {
+        self.length_prefix(count)
+    }
+
+    fn length_prefix(&mut self, len: usize) -> &mut Self {
+        let len = u32::try_from(len).expect("canonical fields are limited to u32::MAX items");
+        self.u32(len)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn digest(&self) -> [u8; 32] {
+        Sha256::digest(&self.bytes).into()
+    }
+
+    pub fn hex_digest(&self) -> String {
+        hex::encode(self.digest())
+    }
+}
+
+// ============= HASHES =============
+
+/// Fields of a consensus block covered by its hash (everything but the hash itself).
+pub struct BlockHeader<'a> {
+    pub index: u64,
+    pub interval_id: u64,
+    pub merkle_root: &'a str,
+    pub winner_count: u32,
+    pub timestamp: u64,
+}
+
+pub fn block_encoder(block: &BlockHeader) -> CanonicalEncoder {
+    let mut encoder = CanonicalEncoder::new(domain::BLOCK);
+    encoder
+        .u64(block.index)
+        .u64(block.interval_id)
+        .text(block.merkle_root)
+        .u32(block.winner_count)
+        .u64(block.timestamp);
+    encoder
+}
+
+/// Hex block hash stored in `Block::hash`.
+pub fn block_hash(block: &BlockHeader) -> String {
+    block_encoder(block).hex_digest()
+}
+
+/// A validator cluster winner as it enters the interval root.
+pub struct WinnerLeaf {
+    pub uid: u32,
+    /// Cluster center in microdegrees
+    pub lat: i32,
+    pub lon: i32,
+    pub participants: u8,
+}
+
+pub fn cluster_winners_encoder(winners: &[WinnerLeaf]) -> CanonicalEncoder {
+    let mut encoder = CanonicalEncoder::new(domain::CLUSTER_WINNERS);
+    encoder.list(winners.len());
+    for winner in winners {
+        encoder
+            .u32(winner.uid)
+            .i32(winner.lat)
+            .i32(winner.lon)
+            .u8(winner.participants);
+    }
+    encoder
+}
+
+/// Hex root over the winners of an interval (or a batch), in the order they were selected.
+pub fn cluster_winners_hash(winners: &[WinnerLeaf]) -> String {
+    cluster_winners_encoder(winners).hex_digest()
+}
+
+pub fn competition_commitment_encoder(interval_id: u64, seed: &[u8]) -> CanonicalEncoder {
+    let mut encoder = CanonicalEncoder::new(domain::COMPETITION_COMMITMENT);
+    encoder.u64(interval_id).bytes(seed);
+    encoder
+}
+
+/// Hex commitment published when a competition opens, before its seed is revealed.
+pub fn competition_commitment(interval_id: u64, seed: &[u8]) -> String {
+    competition_commitment_encoder(interval_id, seed).hex_digest()
+}
+
+pub fn competition_target_encoder(interval_id: u64, seed: &[u8]) -> CanonicalEncoder {
+    let mut encoder = CanonicalEncoder::new(domain::COMPETITION_TARGET);
+    encoder.u64(interval_id).bytes(seed);
+    encoder
+}
+
+/// Digest the target distance of a competition is drawn from.
+pub fn competition_target_digest(interval_id: u64, seed: &[u8]) -> [u8; 32] {
+    competition_target_encoder(interval_id, seed).digest()
+}
+
+pub fn reward_mint_memo_encoder(validation_id: &str, user_id: &str) -> CanonicalEncoder {
+    let mut encoder = CanonicalEncoder::new(domain::REWARD_MINT_MEMO);
+    encoder.text(validation_id).text(user_id);
+    encoder
+}
+
+/// Memo of a reward mint. Validation ids have no length limit, so the rewards canister sends
+/// this 32-byte digest instead of the ids themselves to stay within the token's memo cap.
+pub fn reward_mint_memo(validation_id: &str, user_id: &str) -> [u8; 32] {
+    reward_mint_memo_encoder(validation_id, user_id).digest()
+}
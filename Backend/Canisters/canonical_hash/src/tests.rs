@@ -0,0 +1,201 @@
+use crate::*;
+use serde_json::Value;
+
+const TEST_VECTORS: &str = include_str!("../test_vectors.json");
+
+fn vectors() -> Vec<Value> {
+    let file: Value = serde_json::from_str(TEST_VECTORS).unwrap();
+    assert_eq!(file["version"], u64::from(ENCODING_VERSION));
+    file["vectors"].as_array().unwrap().clone()
+}
+
+fn vector(name: &str) -> Value {
+    vectors()
+        .into_iter()
+        .find(|v| v["name"] == name)
+        .unwrap_or_else(|| panic!("no test vector named {}", name))
+}
+
+fn int<T: std::str::FromStr>(field: &Value) -> T
+where
+    T::Err: std::fmt::Debug,
+{
+    field["value"].as_str().unwrap().parse().unwrap()
+}
+
+fn encode_field(encoder: &mut CanonicalEncoder, field: &Value) {
+    match field["type"].as_str().unwrap() {
+        "u8" => encoder.u8(int(field)),
+        "u32" => encoder.u32(int(field)),
+        "u64" => encoder.u64(int(field)),
+        "i32" => encoder.i32(int(field)),
+        "bytes" => encoder.bytes(&hex::decode(field["value"].as_str().unwrap()).unwrap()),
+        "text" => encoder.text(field["value"].as_str().unwrap()),
+        "list" => {
+            let items = field["items"].as_array().unwrap();
+            encoder.list(items.len());
+            for item in items {
+                for field in item.as_array().unwrap() {
+                    encode_field(encoder, field);
+                }
+            }
+            encoder
+        }
+        other => panic!("unknown field type {}", other),
+    };
+}
+
+fn field<'a>(vector: &'a Value, name: &str) -> &'a Value {
+    vector["fields"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["name"] == name)
+        .unwrap()
+}
+
+fn block_header(vector: &Value) -> (u64, u64, String, u32, u64) {
+    (
+        int(field(vector, "index")),
+        int(field(vector, "interval_id")),
+        field(vector, "merkle_root")["value"]
+            .as_str()
+            .unwrap()
+            .to_string(),
+        int(field(vector, "winner_count")),
+        int(field(vector, "timestamp")),
+    )
+}
+
+fn hash_block(vector: &Value) -> String {
+    let (index, interval_id, merkle_root, winner_count, timestamp) = block_header(vector);
+    block_hash(&BlockHeader {
+        index,
+        interval_id,
+        merkle_root: &merkle_root,
+        winner_count,
+        timestamp,
+    })
+}
+
+#[test]
+fn should_match_every_test_vector() {
+    for vector in vectors() {
+        let mut encoder = CanonicalEncoder::new(vector["domain"].as_str().unwrap());
+        for field in vector["fields"].as_array().unwrap() {
+            encode_field(&mut encoder, field);
+        }
+
+        assert_eq!(
+            hex::encode(encoder.as_bytes()),
+            vector["encoding"],
+            "encoding of {}",
+            vector["name"]
+        );
+        assert_eq!(
+            encoder.hex_digest(),
+            vector["sha256"],
+            "hash of {}",
+            vector["name"]
+        );
+    }
+}
+
+#[test]
+fn should_hash_blocks_like_the_vectors() {
+    for name in ["block", "block_field_boundary_a", "block_max_values"] {
+        let vector = vector(name);
+        assert_eq!(hash_block(&vector), vector["sha256"], "{}", name);
+    }
+}
+
+#[test]
+fn should_separate_fields_that_used_to_run_together() {
+    let a = vector("block_field_boundary_a");
+    let b = vector("block_field_boundary_b");
+
+    assert_eq!(hash_block(&b), b["sha256"]);
+    assert_ne!(hash_block(&a), hash_block(&b));
+}
+
+#[test]
+fn should_hash_cluster_winners_like_the_vectors() {
+    let winners = [
+        WinnerLeaf {
+            uid: 42,
+            lat: 47_376_887,
+            lon: 8_541_694,
+            participants: 3,
+        },
+        WinnerLeaf {
+            uid: 7,
+            lat: -33_868_820,
+            lon: -151_209_295,
+            participants: 2,
+        },
+    ];
+
+    assert_eq!(
+        cluster_winners_hash(&winners),
+        vector("cluster_winners")["sha256"]
+    );
+    assert_eq!(
+        cluster_winners_hash(&[]),
+        vector("cluster_winners_empty")["sha256"]
+    );
+    // The block vector commits to this root
+    assert_eq!(
+        field(&vector("block"), "merkle_root")["value"],
+        cluster_winners_hash(&winners)
+    );
+}
+
+#[test]
+fn should_hash_competition_seeds_like_the_vectors() {
+    let seed: Vec<u8> = (0..32).collect();
+
+    assert_eq!(
+        competition_commitment(5_843_210, &seed),
+        vector("competition_commitment")["sha256"]
+    );
+    assert_eq!(
+        hex::encode(competition_target_digest(5_843_210, &seed)),
+        vector("competition_target")["sha256"]
+    );
+}
+
+#[test]
+fn should_hash_reward_mint_memos_like_the_vectors() {
+    assert_eq!(
+        hex::encode(reward_mint_memo(
+            "interval-5843210-batch-000000000000000000042",
+            "2vxsx-fae"
+        )),
+        vector("reward_mint_memo")["sha256"]
+    );
+    assert_ne!(
+        reward_mint_memo("a_b", "c"),
+        reward_mint_memo("a", "b_c")
+    );
+}
+
+#[test]
+fn should_separate_domains() {
+    let seed = [9u8; 32];
+
+    assert_ne!(
+        competition_commitment(1, &seed),
+        hex::encode(competition_target_digest(1, &seed))
+    );
+}
+
+#[test]
+fn should_prefix_variable_length_fields() {
+    let mut one = CanonicalEncoder::new("test");
+    one.text("ab").text("c");
+    let mut other = CanonicalEncoder::new("test");
+    other.text("a").text("bc");
+
+    assert_ne!(one.as_bytes(), other.as_bytes());
+    assert_eq!(&one.as_bytes()[..9], b"\x01\x00\x00\x00\x04test");
+}
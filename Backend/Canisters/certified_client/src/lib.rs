@@ -0,0 +1,75 @@
+// certified_client/src/lib.rs - Certified query data shared by the canisters and their clients
+//
+// Query calls are answered by a single replica and pass through a boundary node, so their
+// results can be forged unless they are certified. The consensus and token canisters keep a
+// hash tree of what they serve and set its root hash as their certified data:
+//
+//     consensus:    blocks / <index: u64 big-endian>  -> canonical block hash
+//     bikera_token: balances / <account key>          -> balance, big-endian, no leading zeros
+//
+// Certified queries return the values together with the subnet's certificate and a witness
+// (a pruned copy of the tree, CBOR-encoded). With the `verify` feature (the default) this
+// crate checks both against the IC root key; canisters depend on it without default
+// features, for the layout only.
+use candid::{Nat, Principal};
+use ic_certification::{labeled_hash, AsHashTree, Hash, HashTree, RbTree};
+use serde::Serialize;
+
+#[cfg(feature = "verify")]
+mod verify;
+#[cfg(feature = "verify")]
+pub use verify::*;
+
+#[cfg(all(test, feature = "verify"))]
+mod tests;
+
+pub use canonical_hash::BlockHeader;
+pub use ic_certification;
+
+pub const BLOCKS_LABEL: &[u8] = b"blocks";
+pub const BALANCES_LABEL: &[u8] = b"balances";
+
+pub fn block_key(index: u64) -> Vec<u8> {
+    index.to_be_bytes().to_vec()
+}
+
+/// Key of an account balance: the owner's length and bytes, then the subaccount if set.
+///
+/// `None` and an all-zero subaccount are kept apart, like the token's balance map does.
+pub fn account_key(owner: &Principal, subaccount: Option<&[u8; 32]>) -> Vec<u8> {
+    let owner = owner.as_slice();
+    let mut key = Vec::with_capacity(1 + owner.len() + 32);
+    key.push(owner.len() as u8);
+    key.extend_from_slice(owner);
+    if let Some(subaccount) = subaccount {
+        key.extend_from_slice(subaccount);
+    }
+    key
+}
+
+pub fn balance_leaf(balance: &Nat) -> Vec<u8> {
+    let bytes = balance.0.to_bytes_be();
+    // `to_bytes_be` renders zero as a single 0 byte
+    if bytes == [0] {
+        Vec::new()
+    } else {
+        bytes
+    }
+}
+
+/// Certified data of a canister whose tree holds a single labeled subtree.
+pub fn labeled_root_hash<V: AsHashTree + 'static>(label: &[u8], tree: &RbTree<Vec<u8>, V>) -> Hash {
+    labeled_hash(label, &tree.root_hash())
+}
+
+/// CBOR encoding of a witness, as returned by the certified queries.
+pub fn encode_witness(witness: &HashTree) -> Vec<u8> {
+    let mut serializer = serde_cbor::Serializer::new(Vec::new());
+    serializer
+        .self_describe()
+        .expect("failed to encode witness");
+    witness
+        .serialize(&mut serializer)
+        .expect("failed to encode witness");
+    serializer.into_inner()
+}
@@ -0,0 +1,387 @@
+use crate::*;
+use ic_certification::hash_tree::{fork, label, leaf};
+use ic_certification::{Certificate, Delegation};
+use ic_verify_bls_signature::PrivateKey;
+
+const NOW: u64 = 1_760_745_600_000_000_000;
+
+fn canister_id() -> Principal {
+    Principal::from_slice(&[0, 0, 0, 0, 0, 0x30, 0, 1, 1, 1])
+}
+
+fn secret_key(seed: u8) -> PrivateKey {
+    PrivateKey::deserialize(&[seed; 32]).unwrap()
+}
+
+fn der_key(key: &PrivateKey) -> Vec<u8> {
+    let mut der = IC_ROOT_KEY[..IC_ROOT_KEY.len() - 96].to_vec();
+    der.extend_from_slice(&key.public_key().serialize());
+    der
+}
+
+fn leb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn sign(tree: HashTree, key: &PrivateKey, delegation: Option<Delegation>) -> Certificate {
+    let mut message = b"\x0dic-state-root".to_vec();
+    message.extend_from_slice(&tree.digest());
+    Certificate {
+        tree,
+        signature: key.sign(&message).serialize().to_vec(),
+        delegation,
+    }
+}
+
+fn state_tree(certified_data: &[u8], time: u64) -> HashTree {
+    fork(
+        label(
+            "canister",
+            label(
+                canister_id().as_slice(),
+                label("certified_data", leaf(certified_data.to_vec())),
+            ),
+        ),
+        label("time", leaf(leb128(time))),
+    )
+}
+
+fn certificate(certified_data: &[u8], key: &PrivateKey, time: u64) -> Vec<u8> {
+    serde_cbor::to_vec(&sign(state_tree(certified_data, time), key, None)).unwrap()
+}
+
+fn blocks(count: u64) -> Vec<(u64, u64, String, u32, u64)> {
+    (0..count)
+        .map(|index| (index, 5_843_200 + index, format!("{:064x}", index), 3, NOW))
+        .collect()
+}
+
+fn headers(blocks: &[(u64, u64, String, u32, u64)]) -> Vec<BlockHeader<'_>> {
+    blocks
+        .iter()
+        .map(
+            |(index, interval_id, merkle_root, winner_count, timestamp)| BlockHeader {
+                index: *index,
+                interval_id: *interval_id,
+                merkle_root,
+                winner_count: *winner_count,
+                timestamp: *timestamp,
+            },
+        )
+        .collect()
+}
+
+fn block_tree(blocks: &[(u64, u64, String, u32, u64)]) -> RbTree<Vec<u8>, Hash> {
+    let mut tree = RbTree::new();
+    for header in headers(blocks) {
+        tree.insert(
+            block_key(header.index),
+            canonical_hash::block_encoder(&header).digest(),
+        );
+    }
+    tree
+}
+
+/// Certificate and witness for blocks `first..=last`, as `get_certified_latest_blocks` builds
+/// them
+fn certified_blocks(
+    tree: &RbTree<Vec<u8>, Hash>,
+    first: u64,
+    last: u64,
+    key: &PrivateKey,
+) -> (Vec<u8>, Vec<u8>) {
+    let witness = ic_certification::labeled(
+        BLOCKS_LABEL,
+        tree.value_range(&block_key(first), &block_key(last)),
+    );
+    let certified_data = labeled_root_hash(BLOCKS_LABEL, tree);
+    (
+        certificate(&certified_data, key, NOW),
+        encode_witness(&witness),
+    )
+}
+
+#[test]
+fn should_verify_latest_blocks() {
+    let blocks = blocks(5);
+    let tree = block_tree(&blocks);
+    let (certificate, witness) = certified_blocks(&tree, 2, 4, &secret_key(1));
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    assert_eq!(
+        verifier.verify_blocks(&headers(&blocks[2..]), &certificate, &witness, NOW),
+        Ok(())
+    );
+}
+
+#[test]
+fn should_reject_tampered_blocks() {
+    let blocks = blocks(3);
+    let tree = block_tree(&blocks);
+    let (certificate, witness) = certified_blocks(&tree, 0, 2, &secret_key(1));
+
+    let mut tampered = headers(&blocks);
+    tampered[1].winner_count += 1;
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    assert_eq!(
+        verifier.verify_blocks(&tampered, &certificate, &witness, NOW),
+        Err(VerifyError::ValueMismatch { key: block_key(1) })
+    );
+}
+
+#[test]
+fn should_reject_blocks_that_are_not_the_latest() {
+    let blocks = blocks(3);
+    let tree = block_tree(&blocks);
+    let (certificate, witness) = certified_blocks(&tree, 0, 1, &secret_key(1));
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    assert_eq!(
+        verifier.verify_blocks(&headers(&blocks[..2]), &certificate, &witness, NOW),
+        Err(VerifyError::NotLatest { index: 2 })
+    );
+}
+
+#[test]
+fn should_require_a_proof_of_absence_for_no_blocks() {
+    let certified = |tree: &RbTree<Vec<u8>, Hash>, witness: HashTree| {
+        let certified_data = labeled_root_hash(BLOCKS_LABEL, tree);
+        (
+            certificate(&certified_data, &secret_key(1), NOW),
+            encode_witness(&ic_certification::labeled(BLOCKS_LABEL, witness)),
+        )
+    };
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+
+    let empty = block_tree(&[]);
+    let (certificate, witness) = certified(&empty, empty.witness(&block_key(0)));
+    assert_eq!(
+        verifier.verify_blocks(&[], &certificate, &witness, NOW),
+        Ok(())
+    );
+
+    // A pruned tree hides the certified blocks
+    let tree = block_tree(&blocks(3));
+    let pruned = ic_certification::hash_tree::pruned(tree.root_hash());
+    let (certificate, witness) = certified(&tree, pruned);
+    assert_eq!(
+        verifier.verify_blocks(&[], &certificate, &witness, NOW),
+        Err(VerifyError::NotLatest { index: 0 })
+    );
+
+    let (certificate, witness) = certified(&tree, tree.witness(&block_key(0)));
+    assert_eq!(
+        verifier.verify_blocks(&[], &certificate, &witness, NOW),
+        Err(VerifyError::NotLatest { index: 0 })
+    );
+}
+
+#[test]
+fn should_reject_witnesses_of_other_data() {
+    let tree = block_tree(&blocks(3));
+    let (certificate, _) = certified_blocks(&tree, 0, 2, &secret_key(1));
+    let (_, other_witness) = certified_blocks(&block_tree(&blocks(4)), 0, 3, &secret_key(1));
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    assert_eq!(
+        verifier
+            .verify_witness(&certificate, &other_witness, NOW)
+            .unwrap_err(),
+        VerifyError::CertifiedDataMismatch
+    );
+}
+
+#[test]
+fn should_reject_certificates_signed_by_another_key() {
+    let tree = block_tree(&blocks(1));
+    let (certificate, witness) = certified_blocks(&tree, 0, 0, &secret_key(2));
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    assert_eq!(
+        verifier
+            .verify_witness(&certificate, &witness, NOW)
+            .unwrap_err(),
+        VerifyError::InvalidSignature
+    );
+}
+
+#[test]
+fn should_reject_stale_certificates() {
+    let tree = block_tree(&blocks(1));
+    let (certificate, witness) = certified_blocks(&tree, 0, 0, &secret_key(1));
+    let later = NOW + DEFAULT_MAX_CERTIFICATE_AGE_NANOS + 1;
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    assert_eq!(
+        verifier
+            .verify_witness(&certificate, &witness, later)
+            .unwrap_err(),
+        VerifyError::StaleCertificate {
+            time_nanos: NOW,
+            now_nanos: later
+        }
+    );
+}
+
+#[test]
+fn should_verify_balances_and_their_absence() {
+    let alice = Principal::from_slice(&[7; 29]);
+    let bob = Principal::from_slice(&[8; 29]);
+    let savings = [1u8; 32];
+
+    let mut tree: RbTree<Vec<u8>, Vec<u8>> = RbTree::new();
+    tree.insert(
+        account_key(&alice, None),
+        balance_leaf(&Nat::from(2_000u64)),
+    );
+    tree.insert(
+        account_key(&alice, Some(&savings)),
+        balance_leaf(&Nat::from(500u64)),
+    );
+    let certificate = certificate(
+        &labeled_root_hash(BALANCES_LABEL, &tree),
+        &secret_key(1),
+        NOW,
+    );
+    let witness_of = |key: Vec<u8>| {
+        encode_witness(&ic_certification::labeled(
+            BALANCES_LABEL,
+            tree.witness(&key),
+        ))
+    };
+
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+    let savings_witness = witness_of(account_key(&alice, Some(&savings)));
+    assert_eq!(
+        verifier.verify_balance(
+            &alice,
+            Some(&savings),
+            &Nat::from(500u64),
+            &certificate,
+            &savings_witness,
+            NOW
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        verifier.verify_balance(
+            &alice,
+            Some(&savings),
+            &Nat::from(501u64),
+            &certificate,
+            &savings_witness,
+            NOW
+        ),
+        Err(VerifyError::ValueMismatch {
+            key: account_key(&alice, Some(&savings))
+        })
+    );
+
+    let bob_witness = witness_of(account_key(&bob, None));
+    assert_eq!(
+        verifier.verify_balance(
+            &bob,
+            None,
+            &Nat::from(0u64),
+            &certificate,
+            &bob_witness,
+            NOW
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        verifier.verify_balance(
+            &bob,
+            None,
+            &Nat::from(1u64),
+            &certificate,
+            &bob_witness,
+            NOW
+        ),
+        Err(VerifyError::ValueMismatch {
+            key: account_key(&bob, None)
+        })
+    );
+}
+
+fn delegated_certificate(certified_data: &[u8], ranges: &[(Principal, Principal)]) -> Vec<u8> {
+    let subnet_id = Principal::from_slice(&[9; 29]);
+    let ranges: Vec<(serde_bytes::ByteBuf, serde_bytes::ByteBuf)> = ranges
+        .iter()
+        .map(|(low, high)| {
+            (
+                serde_bytes::ByteBuf::from(low.as_slice()),
+                serde_bytes::ByteBuf::from(high.as_slice()),
+            )
+        })
+        .collect();
+    let subnet_tree = label(
+        "subnet",
+        label(
+            subnet_id.as_slice(),
+            fork(
+                label(
+                    "canister_ranges",
+                    leaf(serde_cbor::to_vec(&ranges).unwrap()),
+                ),
+                label("public_key", leaf(der_key(&secret_key(3)))),
+            ),
+        ),
+    );
+    let delegation = Delegation {
+        subnet_id: subnet_id.as_slice().to_vec(),
+        certificate: serde_cbor::to_vec(&sign(subnet_tree, &secret_key(1), None)).unwrap(),
+    };
+
+    let certificate = sign(
+        state_tree(certified_data, NOW),
+        &secret_key(3),
+        Some(delegation),
+    );
+    serde_cbor::to_vec(&certificate).unwrap()
+}
+
+#[test]
+fn should_follow_subnet_delegations() {
+    let tree = block_tree(&blocks(2));
+    let certified_data = labeled_root_hash(BLOCKS_LABEL, &tree);
+    let witness = encode_witness(&ic_certification::labeled(
+        BLOCKS_LABEL,
+        tree.value_range(&block_key(0), &block_key(1)),
+    ));
+    let verifier = Verifier::new(canister_id(), &der_key(&secret_key(1)));
+
+    let hosting = delegated_certificate(&certified_data, &[(canister_id(), canister_id())]);
+    assert!(verifier.verify_witness(&hosting, &witness, NOW).is_ok());
+
+    let elsewhere = Principal::from_slice(&[0, 0, 0, 0, 0, 0x40, 0, 1, 1, 1]);
+    let other_subnet = delegated_certificate(&certified_data, &[(elsewhere, elsewhere)]);
+    assert_eq!(
+        verifier
+            .verify_witness(&other_subnet, &witness, NOW)
+            .unwrap_err(),
+        VerifyError::CanisterNotInDelegation
+    );
+}
+
+#[test]
+fn should_encode_keys_and_leaves() {
+    assert_eq!(block_key(258), [0, 0, 0, 0, 0, 0, 1, 2]);
+    assert_eq!(
+        account_key(&Principal::from_slice(&[7, 8]), Some(&[1; 32])),
+        [&[2u8, 7, 8][..], &[1; 32]].concat()
+    );
+    assert_eq!(balance_leaf(&Nat::from(0x0102u64)), [1, 2]);
+    assert!(balance_leaf(&Nat::from(0u64)).is_empty());
+    assert_eq!(IC_ROOT_KEY.len(), 133);
+}
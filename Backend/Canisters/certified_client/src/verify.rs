@@ -0,0 +1,343 @@
+// Verification of certified query responses
+//
+// A certificate is valid when its tree is signed by the IC root key, or by a subnet key
+// delegated by the root key to a subnet whose canister ranges contain the canister. The
+// canister's `certified_data` in that tree must then equal the root hash of the witness, and
+// the witness must hold the returned values.
+use crate::{account_key, balance_leaf, block_key, BALANCES_LABEL, BLOCKS_LABEL};
+use candid::{Nat, Principal};
+use canonical_hash::BlockHeader;
+use ic_certification::{Certificate, HashTree, LookupResult};
+use serde_bytes::ByteBuf;
+use std::fmt;
+
+/// DER-encoded public key of the IC root subnet (mainnet)
+pub const IC_ROOT_KEY: &[u8] = &[
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00, 0x81, 0x4c, 0x0e, 0x6e, 0xc7, 0x1f, 0xab, 0x58, 0x3b, 0x08, 0xbd,
+    0x81, 0x37, 0x3c, 0x25, 0x5c, 0x3c, 0x37, 0x1b, 0x2e, 0x84, 0x86, 0x3c, 0x98, 0xa4, 0xf1, 0xe0,
+    0x8b, 0x74, 0x23, 0x5d, 0x14, 0xfb, 0x5d, 0x9c, 0x0c, 0xd5, 0x46, 0xd9, 0x68, 0x5f, 0x91, 0x3a,
+    0x0c, 0x0b, 0x2c, 0xc5, 0x34, 0x15, 0x83, 0xbf, 0x4b, 0x43, 0x92, 0xe4, 0x67, 0xdb, 0x96, 0xd6,
+    0x5b, 0x9b, 0xb4, 0xcb, 0x71, 0x71, 0x12, 0xf8, 0x47, 0x2e, 0x0d, 0x5a, 0x4d, 0x14, 0x50, 0x5f,
+    0xfd, 0x74, 0x84, 0xb0, 0x12, 0x91, 0x09, 0x1c, 0x5f, 0x87, 0xb9, 0x88, 0x83, 0x46, 0x3f, 0x98,
+    0x09, 0x1a, 0x0b, 0xaa, 0xae,
+];
+
+/// DER prefix of a BLS12-381 G2 public key, followed by the 96-byte key
+const BLS_KEY_DER_PREFIX: &[u8] = &[
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05,
+    0x03, 0x01, 0x02, 0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03,
+    0x02, 0x01, 0x03, 0x61, 0x00,
+];
+const BLS_KEY_LEN: usize = 96;
+
+/// Domain separator of the signed state root: its length, then `ic-state-root`
+const STATE_ROOT_DOMAIN: &[u8] = b"\x0dic-state-root";
+
+/// Certificates older than this are rejected by default
+pub const DEFAULT_MAX_CERTIFICATE_AGE_NANOS: u64 = 5 * 60 * 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    MalformedCertificate(String),
+    MalformedWitness(String),
+    MalformedPublicKey,
+    InvalidSignature,
+    /// The delegated subnet is not allowed to certify this canister
+    CanisterNotInDelegation,
+    /// Certificate time is older than the maximum age, or in the future
+    StaleCertificate {
+        time_nanos: u64,
+        now_nanos: u64,
+    },
+    /// The witness is not what the canister certified
+    CertifiedDataMismatch,
+    /// The witness does not cover the value (or proves it absent)
+    MissingValue {
+        key: Vec<u8>,
+    },
+    ValueMismatch {
+        key: Vec<u8>,
+    },
+    /// A block newer than the latest returned one is certified
+    NotLatest {
+        index: u64,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::MalformedCertificate(e) => write!(f, "malformed certificate: {}", e),
+            VerifyError::MalformedWitness(e) => write!(f, "malformed witness: {}", e),
+            VerifyError::MalformedPublicKey => write!(f, "malformed BLS public key"),
+            VerifyError::InvalidSignature => write!(f, "invalid certificate signature"),
+            VerifyError::CanisterNotInDelegation => {
+                write!(f, "the delegated subnet does not host this canister")
+            }
+            VerifyError::StaleCertificate {
+                time_nanos,
+                now_nanos,
+            } => write!(
+                f,
+                "certificate time {} is too far from the current time {}",
+                time_nanos, now_nanos
+            ),
+            VerifyError::CertifiedDataMismatch => {
+                write!(f, "witness does not match the certified data")
+            }
+            VerifyError::MissingValue { key } => {
+                write!(f, "witness does not prove key {}", hex(key))
+            }
+            VerifyError::ValueMismatch { key } => {
+                write!(f, "certified value of key {} differs", hex(key))
+            }
+            VerifyError::NotLatest { index } => {
+                write!(f, "block {} is certified but was not returned", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Verifies certified responses of one canister.
+pub struct Verifier {
+    canister_id: Principal,
+    root_key: Vec<u8>,
+    max_certificate_age_nanos: u64,
+}
+
+impl Verifier {
+    /// `root_key` is the DER-encoded root key: [`IC_ROOT_KEY`] on mainnet, or the key a local
+    /// replica reports in its status.
+    pub fn new(canister_id: Principal, root_key: &[u8]) -> Self {
+        Self {
+            canister_id,
+            root_key: root_key.to_vec(),
+            max_certificate_age_nanos: DEFAULT_MAX_CERTIFICATE_AGE_NANOS,
+        }
+    }
+
+    pub fn mainnet(canister_id: Principal) -> Self {
+        Self::new(canister_id, IC_ROOT_KEY)
+    }
+
+    pub fn with_max_certificate_age(mut self, nanos: u64) -> Self {
+        self.max_certificate_age_nanos = nanos;
+        self
+    }
+
+    /// Checks the certificate and returns the witness once it is proven to be the canister's
+    /// certified tree.
+    pub fn verify_witness(
+        &self,
+        certificate: &[u8],
+        witness: &[u8],
+        now_nanos: u64,
+    ) -> Result<HashTree, VerifyError> {
+        let certificate = parse_certificate(certificate)?;
+        self.verify_certificate(&certificate, now_nanos)?;
+
+        let certified_data = match certificate.tree.lookup_path([
+            b"canister".as_slice(),
+            self.canister_id.as_slice(),
+            b"certified_data".as_slice(),
+        ]) {
+            LookupResult::Found(data) => data,
+            _ => {
+                return Err(VerifyError::MalformedCertificate(
+                    "no certified data for the canister".to_string(),
+                ))
+            }
+        };
+
+        let witness: HashTree = serde_cbor::from_slice(witness)
+            .map_err(|e| VerifyError::MalformedWitness(e.to_string()))?;
+        if witness.digest().as_slice() != certified_data {
+            return Err(VerifyError::CertifiedDataMismatch);
+        }
+        Ok(witness)
+    }
+
+    /// Checks blocks returned by `get_certified_latest_blocks`, in index order, and that no
+    /// newer block is certified. An empty list must come with a proof that block 0 is absent.
+    pub fn verify_blocks(
+        &self,
+        blocks: &[BlockHeader],
+        certificate: &[u8],
+        witness: &[u8],
+        now_nanos: u64,
+    ) -> Result<(), VerifyError> {
+        let witness = self.verify_witness(certificate, witness, now_nanos)?;
+
+        for block in blocks {
+            let expected = canonical_hash::block_encoder(block).digest();
+            expect_value(
+                &witness,
+                BLOCKS_LABEL,
+                &block_key(block.index),
+                Some(&expected),
+            )?;
+        }
+
+        let next = blocks.last().map_or(0, |last| last.index + 1);
+        if lookup(&witness, BLOCKS_LABEL, &block_key(next)) != LookupResult::Absent {
+            return Err(VerifyError::NotLatest { index: next });
+        }
+        Ok(())
+    }
+
+    /// Checks a balance returned by `get_certified_balance`; zero balances are proven
+    /// absent from the tree.
+    pub fn verify_balance(
+        &self,
+        owner: &Principal,
+        subaccount: Option<&[u8; 32]>,
+        balance: &Nat,
+        certificate: &[u8],
+        witness: &[u8],
+        now_nanos: u64,
+    ) -> Result<(), VerifyError> {
+        let witness = self.verify_witness(certificate, witness, now_nanos)?;
+
+        let key = account_key(owner, subaccount);
+        let leaf = balance_leaf(balance);
+        let expected = (!leaf.is_empty()).then_some(leaf.as_slice());
+        expect_value(&witness, BALANCES_LABEL, &key, expected)
+    }
+
+    fn verify_certificate(
+        &self,
+        certificate: &Certificate,
+        now_nanos: u64,
+    ) -> Result<(), VerifyError> {
+        let key = match &certificate.delegation {
+            None => self.root_key.clone(),
+            Some(delegation) => {
+                let delegated = parse_certificate(&delegation.certificate)?;
+                if delegated.delegation.is_some() {
+                    return Err(VerifyError::MalformedCertificate(
+                        "nested delegation".to_string(),
+                    ));
+                }
+                verify_signature(&delegated, &self.root_key)?;
+                self.delegated_key(&delegated, &delegation.subnet_id)?
+            }
+        };
+        verify_signature(certificate, &key)?;
+
+        let time_nanos = certificate_time(certificate)?;
+        if time_nanos > now_nanos.saturating_add(self.max_certificate_age_nanos)
+            || now_nanos.saturating_sub(time_nanos) > self.max_certificate_age_nanos
+        {
+            return Err(VerifyError::StaleCertificate {
+                time_nanos,
+                now_nanos,
+            });
+        }
+        Ok(())
+    }
+
+    /// Public key of the delegated subnet, if it hosts the canister.
+    fn delegated_key(
+        &self,
+        delegated: &Certificate,
+        subnet_id: &[u8],
+    ) -> Result<Vec<u8>, VerifyError> {
+        let ranges = match delegated.tree.lookup_path([
+            b"subnet".as_slice(),
+            subnet_id,
+            b"canister_ranges".as_slice(),
+        ]) {
+            LookupResult::Found(ranges) => ranges,
+            _ => return Err(VerifyError::CanisterNotInDelegation),
+        };
+        // CBOR list of inclusive (low, high) principal ranges, compared as bytes
+        let ranges: Vec<(ByteBuf, ByteBuf)> = serde_cbor::from_slice(ranges)
+            .map_err(|e| VerifyError::MalformedCertificate(e.to_string()))?;
+        let canister = self.canister_id.as_slice();
+        if !ranges
+            .iter()
+            .any(|(low, high)| low.as_slice() <= canister && canister <= high.as_slice())
+        {
+            return Err(VerifyError::CanisterNotInDelegation);
+        }
+
+        match delegated.tree.lookup_path([
+            b"subnet".as_slice(),
+            subnet_id,
+            b"public_key".as_slice(),
+        ]) {
+            LookupResult::Found(key) => Ok(key.to_vec()),
+            _ => Err(VerifyError::MalformedCertificate(
+                "no public key for the delegated subnet".to_string(),
+            )),
+        }
+    }
+}
+
+fn parse_certificate(bytes: &[u8]) -> Result<Certificate, VerifyError> {
+    serde_cbor::from_slice(bytes).map_err(|e| VerifyError::MalformedCertificate(e.to_string()))
+}
+
+fn verify_signature(certificate: &Certificate, der_key: &[u8]) -> Result<(), VerifyError> {
+    let key = der_key
+        .strip_prefix(BLS_KEY_DER_PREFIX)
+        .filter(|key| key.len() == BLS_KEY_LEN)
+        .ok_or(VerifyError::MalformedPublicKey)?;
+
+    let mut message = STATE_ROOT_DOMAIN.to_vec();
+    message.extend_from_slice(&certificate.tree.digest());
+    ic_verify_bls_signature::verify_bls_signature(&certificate.signature, &message, key)
+        .map_err(|_| VerifyError::InvalidSignature)
+}
+
+fn certificate_time(certificate: &Certificate) -> Result<u64, VerifyError> {
+    let LookupResult::Found(bytes) = certificate.tree.lookup_path([b"time".as_slice()]) else {
+        return Err(VerifyError::MalformedCertificate("no time".to_string()));
+    };
+    decode_leb128(bytes)
+        .ok_or_else(|| VerifyError::MalformedCertificate("invalid time".to_string()))
+}
+
+fn decode_leb128(bytes: &[u8]) -> Option<u64> {
+    let mut value: u64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        let shift = 7 * i as u32;
+        if shift >= 64 {
+            return None;
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return (i + 1 == bytes.len()).then_some(value);
+        }
+    }
+    None
+}
+
+fn lookup<'a>(witness: &'a HashTree, label: &[u8], key: &[u8]) -> LookupResult<'a> {
+    witness.lookup_path([label, key])
+}
+
+/// Checks that the witness holds `expected` at `label / key`, or proves it absent for `None`.
+fn expect_value(
+    witness: &HashTree,
+    label: &[u8],
+    key: &[u8],
+    expected: Option<&[u8]>,
+) -> Result<(), VerifyError> {
+    match (lookup(witness, label, key), expected) {
+        (LookupResult::Found(value), Some(expected)) if value == expected => Ok(()),
+        (LookupResult::Absent, None) => Ok(()),
+        (LookupResult::Found(_), _) | (LookupResult::Absent, Some(_)) => {
+            Err(VerifyError::ValueMismatch { key: key.to_vec() })
+        }
+        _ => Err(VerifyError::MissingValue { key: key.to_vec() }),
+    }
+}
@@ -1,9 +1,13 @@
 use candid::{CandidType, Deserialize, Principal};
+use certified_client::ic_certification::{self, Hash, RbTree};
+use certified_client::{block_key, BLOCKS_LABEL};
+use ic_cdk::call::Call;
 use ic_cdk_macros::*;
-use ic_stable_structures::{StableVec, memory_manager::*, Storable, DefaultMemoryImpl};
+use ic_stable_structures::{StableVec, memory_manager::*, DefaultMemoryImpl};
 use ic_stable_structures::memory_manager::VirtualMemory;
-use ic_stable_structures::storable::Bound;
-use std::cell::RefCell;
+use stable_schema::{SchemaError, SchemaRegistry, Versioned};
+use serde_bytes::ByteBuf;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
@@ -18,21 +22,22 @@ pub struct Block {
     pub hash: String,
 }
 
-// Implement Storable for Block
-impl Storable for Block {
-    const BOUND: Bound = Bound::Bounded { max_size: 1024, is_fixed_size: false };
-    
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        use candid::Encode;
-        std::borrow::Cow::Owned(Encode!(self).unwrap())
-    }
+impl Versioned for Block {
+    const NAME: &'static str = "consensus::Block";
+    const VERSION: u16 = 1;
 
-    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        use candid::Decode;
-        Decode!(bytes.as_ref(), Self).unwrap()
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, SchemaError> {
+        match version {
+            // Raw candid of the same record
+            0 => stable_schema::decode_candid(Self::NAME, version, payload),
+            _ => Err(SchemaError::UnsupportedVersion { type_name: Self::NAME, version }),
+        }
     }
 }
 
+// The StableVec was created with 1024-byte slots; the bound cannot change in place
+stable_schema::versioned_storable!(Block, max_size = 1024);
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct ClusterWinner {
     pub user_id: String,
@@ -48,6 +53,15 @@ pub struct ValidationResult {
     pub cluster_winners: Vec<ClusterWinner>,
 }
 
+// Blocks with the subnet certificate and the witness of their hashes in `CERTIFIED_BLOCKS`;
+// verify with `certified_client::Verifier::verify_blocks`
+#[derive(CandidType, Deserialize)]
+pub struct CertifiedBlocks {
+    pub blocks: Vec<Block>,
+    pub certificate: ByteBuf,
+    pub witness: ByteBuf,
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct ConsensusRequest {
     pub interval_id: u64,
@@ -70,13 +84,115 @@ thread_local! {
         RefCell::new(HashMap::new());
     
     static CONFIG: RefCell<Config> = RefCell::new(Config::default());
+
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> = RefCell::new(
+        SchemaRegistry::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
+    );
+
+    // Canonical hash of every block by index; its root is the canister's certified data.
+    // Kept on the heap and rebuilt from BLOCKCHAIN after upgrades.
+    static CERTIFIED_BLOCKS: RefCell<RbTree<Vec<u8>, Hash>> = const { RefCell::new(RbTree::new()) };
+
+    // Next block to re-insert into CERTIFIED_BLOCKS after an upgrade; None once the tree is complete
+    static CERTIFICATION_REBUILD: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+// Blocks re-inserted into CERTIFIED_BLOCKS per message after an upgrade. Hashing the whole
+// chain in `post_upgrade` grows with every interval and would eventually exceed the upgrade
+// instruction limit, so the rest continues from the global timer.
+const CERTIFICATION_REBUILD_CHUNK: u64 = 5_000;
+
+#[init]
+fn init() {
+    update_certified_data();
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Rewrite blocks in the current schema once per Block version bump
+    let migrated = SCHEMA_REGISTRY.with(|registry| {
+        registry.borrow_mut().migrate::<Block>("blocks", || {
+            BLOCKCHAIN.with(|b| stable_schema::rewrite_vec(&b.borrow()))
+        })
+    });
+    if let Some(count) = migrated {
+        ic_cdk::println!("Migrated {} blocks to schema v{}", count, Block::VERSION);
+    }
+
+    CERTIFIED_BLOCKS.with(|tree| *tree.borrow_mut() = RbTree::new());
+    CERTIFICATION_REBUILD.with(|next| next.set(Some(0)));
+    continue_certification_rebuild();
+}
+
+// Only the certified-tree rebuild arms the global timer; there is no timers library to share
+// it with
+#[unsafe(export_name = "canister_global_timer")]
+fn canister_global_timer() {
+    ic_cdk::futures::in_executor_context(continue_certification_rebuild);
+}
+
+fn continue_certification_rebuild() {
+    if rebuild_certified_blocks(CERTIFICATION_REBUILD_CHUNK) {
+        update_certified_data();
+    } else {
+        ic_cdk::api::global_timer_set(ic_cdk::api::time());
+    }
+}
+
+// Re-inserts up to `limit` blocks and returns whether the tree holds the whole chain. Blocks
+// appended during the rebuild are certified as usual and simply inserted again.
+fn rebuild_certified_blocks(limit: u64) -> bool {
+    let Some(start) = CERTIFICATION_REBUILD.with(Cell::get) else {
+        return true;
+    };
+
+    let end = BLOCKCHAIN.with(|b| {
+        let blockchain = b.borrow();
+        let end = blockchain.len().min(start.saturating_add(limit));
+        CERTIFIED_BLOCKS.with(|tree| {
+            let mut tree = tree.borrow_mut();
+            for block in (start..end).filter_map(|i| blockchain.get(i)) {
+                tree.insert(block_key(block.index), certified_block_hash(&block));
+            }
+        });
+        (end < blockchain.len()).then_some(end)
+    });
+    CERTIFICATION_REBUILD.with(|next| next.set(end));
+    end.is_none()
+}
+
+fn certification_pending() -> bool {
+    CERTIFICATION_REBUILD.with(Cell::get).is_some()
+}
+
+// Blocks stored before the canonical encoding keep their old `hash`, so the tree always holds
+// the canonical hash recomputed from the block's fields
+fn certified_block_hash(block: &Block) -> Hash {
+    canonical_hash::block_encoder(&block_header(block)).digest()
+}
+
+fn certify_block(block: &Block) {
+    CERTIFIED_BLOCKS.with(|tree| {
+        tree.borrow_mut()
+            .insert(block_key(block.index), certified_block_hash(block))
+    });
+    update_certified_data();
+}
+
+fn update_certified_data() {
+    // A partial tree would certify a shorter chain
+    if certification_pending() {
+        return;
+    }
+    let root = CERTIFIED_BLOCKS.with(|tree| {
+        certified_client::labeled_root_hash(BLOCKS_LABEL, &tree.borrow())
+    });
+    ic_cdk::api::certified_data_set(root);
 }
 
 #[derive(Default)]
 struct Config {
-    validator_canister: Option<Principal>,
     distributor_canister: Option<Principal>,
-    min_confirmations: u8,
 }
 
 #[update]
@@ -133,28 +249,28 @@ async fn finalize_consensus(interval_id: u64) -> Result<String, String> {
         .ok_or("No winning result found".to_string())?;
     
     // Create block
-    let block = Block {
+    let mut block = Block {
         index: BLOCKCHAIN.with(|b| b.borrow().len()),
         interval_id,
-        merkle_root: winning_merkle.clone(),
+        merkle_root: winning_merkle,
         winner_count: winning_result.cluster_winners.len() as u32,
         timestamp: ic_cdk::api::time(),
-        hash: calculate_block_hash(interval_id, &winning_merkle),
+        hash: String::new(),
     };
+    block.hash = calculate_block_hash(&block);
     
     // Store block
     BLOCKCHAIN.with(|b| {
         b.borrow_mut().push(&block).unwrap();
     });
+    certify_block(&block);
     
     // Notify reward distributor
     let distributor = CONFIG.with(|c| c.borrow().distributor_canister);
     if let Some(distributor_canister) = distributor {
-        let _: Result<(String,), _> = ic_cdk::call(
-            distributor_canister,
-            "distribute_rewards",
-            (interval_id, winning_result.cluster_winners),
-        ).await;
+        let _ = Call::unbounded_wait(distributor_canister, "distribute_rewards")
+            .with_args(&(interval_id, winning_result.cluster_winners))
+            .await;
     }
     
     Ok(format!("Block {} created", block.index))
@@ -165,7 +281,7 @@ pub fn get_latest_blocks(count: u32) -> Vec<Block> {
     BLOCKCHAIN.with(|b| {
         let blockchain = b.borrow();
         let len = blockchain.len();
-        let start = if len > count as u64 { len - count as u64 } else { 0 };
+        let start = len.saturating_sub(count as u64);
         
         (start..len)
             .filter_map(|i| blockchain.get(i))
@@ -173,11 +289,130 @@ pub fn get_latest_blocks(count: u32) -> Vec<Block> {
     })
 }
 
-fn calculate_block_hash(interval_id: u64, merkle_root: &str) -> String {
-    use sha2::{Sha256, Digest};
-    let mut hasher = Sha256::new();
-    hasher.update(interval_id.to_string());
-    hasher.update(merkle_root);
-    hasher.update(ic_cdk::api::time().to_string());
-    hex::encode(hasher.finalize())
+// Same blocks as `get_latest_blocks`, with a witness that they are the latest certified ones.
+// Must be called as a query: replicated calls have no certificate. Fails until the certified
+// tree is rebuilt after an upgrade.
+#[query]
+pub fn get_certified_latest_blocks(count: u32) -> Result<CertifiedBlocks, String> {
+    if certification_pending() {
+        return Err("Certified blocks are being rebuilt after an upgrade; retry shortly".to_string());
+    }
+    let certificate = ic_cdk::api::data_certificate()
+        .ok_or("Certificates are only available in query calls")?;
+    let blocks = get_latest_blocks(count);
+
+    let witness = CERTIFIED_BLOCKS.with(|tree| {
+        let tree = tree.borrow();
+        match (blocks.first(), blocks.last()) {
+            (Some(first), Some(last)) => {
+                tree.value_range(&block_key(first.index), &block_key(last.index))
+            }
+            // Proves that no block is certified yet
+            _ => tree.witness(&block_key(0)),
+        }
+    });
+
+    Ok(CertifiedBlocks {
+        blocks,
+        certificate: ByteBuf::from(certificate),
+        witness: ByteBuf::from(certified_client::encode_witness(&ic_certification::labeled(
+            BLOCKS_LABEL,
+            witness,
+        ))),
+    })
+}
+
+// Covers every other field of the block, including the stored timestamp, so anyone can
+// recompute it; see canonical_hash/test_vectors.json for the encoding
+fn calculate_block_hash(block: &Block) -> String {
+    canonical_hash::block_hash(&block_header(block))
+}
+
+fn block_header(block: &Block) -> canonical_hash::BlockHeader<'_> {
+    canonical_hash::BlockHeader {
+        index: block.index,
+        interval_id: block.interval_id,
+        merkle_root: &block.merkle_root,
+        winner_count: block.winner_count,
+        timestamp: block.timestamp,
+    }
+}
+
+#[test]
+fn should_decode_blocks_stored_before_the_envelope() {
+    let bytes = hex::decode(include_str!("../fixtures/block_v0.hex").trim()).unwrap();
+
+    let block: Block = stable_schema::decode(&bytes).unwrap();
+    assert_eq!(block.index, 7);
+    assert_eq!(block.interval_id, 5_843_210);
+    assert_eq!(block.winner_count, 3);
+    assert_eq!(block.merkle_root, "9f2c".repeat(16));
+
+    let upgraded: Block = stable_schema::decode(&stable_schema::encode(&block)).unwrap();
+    assert_eq!(upgraded.hash, block.hash);
+}
+
+#[test]
+fn should_hash_blocks_like_the_edge_servers() {
+    // The "block" vector of canonical_hash/test_vectors.json
+    let block = Block {
+        index: 7,
+        interval_id: 5_843_210,
+        merkle_root: "3bd7ee90d57100754d26e734101583a8ceb718514c0a0f82652c9d68c006f834".to_string(),
+        winner_count: 2,
+        timestamp: 1_760_745_600_000_000_000,
+        hash: String::new(),
+    };
+
+    assert_eq!(
+        calculate_block_hash(&block),
+        "e82c6442ddd1f9ede538d55e44babc82e3d3535ec4d9f1c8a49da3bc35401242"
+    );
+}
+
+#[cfg(test)]
+fn push_test_blocks(count: u64) {
+    BLOCKCHAIN.with(|b| {
+        let blockchain = b.borrow();
+        for index in 0..count {
+            let mut block = Block {
+                index,
+                interval_id: 5_843_210 + index,
+                merkle_root: "9f2c".repeat(16),
+                winner_count: 2,
+                timestamp: 1_760_745_600_000_000_000 + index,
+                hash: String::new(),
+            };
+            block.hash = calculate_block_hash(&block);
+            blockchain.push(&block).unwrap();
+        }
+    });
+}
+
+#[test]
+fn should_rebuild_the_certified_blocks_in_chunks() {
+    use ic_certification::AsHashTree;
+
+    push_test_blocks(5);
+    CERTIFICATION_REBUILD.with(|next| next.set(Some(0)));
+
+    let mut chunks = 1;
+    while !rebuild_certified_blocks(2) {
+        assert_eq!(
+            get_certified_latest_blocks(1).err().as_deref(),
+            Some("Certified blocks are being rebuilt after an upgrade; retry shortly")
+        );
+        chunks += 1;
+    }
+
+    assert_eq!(chunks, 3);
+    assert!(!certification_pending());
+    let mut expected = RbTree::<Vec<u8>, Hash>::new();
+    BLOCKCHAIN.with(|b| {
+        for block in b.borrow().iter() {
+            expected.insert(block_key(block.index), certified_block_hash(&block));
+        }
+    });
+    let rebuilt = CERTIFIED_BLOCKS.with(|tree| tree.borrow().root_hash());
+    assert_eq!(rebuilt, expected.root_hash());
 }
@@ -1,10 +1,11 @@
 // rewards/src/lib.rs - Fixed version with token integration
 use candid::{CandidType, Deserialize, Principal, Nat};
+use ic_cdk::call::Call;
 use ic_cdk_macros::*;
-use ic_stable_structures::{StableBTreeMap, memory_manager::*, Storable, DefaultMemoryImpl, VirtualMemory};
+use ic_stable_structures::{StableBTreeMap, memory_manager::*, DefaultMemoryImpl};
+use stable_schema::{SchemaError, SchemaRegistry, Versioned};
 use std::cell::RefCell;
 use serde_bytes::ByteBuf;
-use std::borrow::Cow;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
@@ -17,22 +18,21 @@ pub struct UserRewards {
     pub principal: Option<Principal>,
 }
 
-// Implement Storable for UserRewards
-impl Storable for UserRewards {
-    const BOUND: ic_stable_structures::storable::Bound = 
-        ic_stable_structures::storable::Bound::Unbounded;
-    
-    fn to_bytes(&self) -> Cow<'_, [u8]> {
-        use candid::Encode;
-        Cow::Owned(Encode!(self).unwrap())
-    }
+impl Versioned for UserRewards {
+    const NAME: &'static str = "rewards::UserRewards";
+    const VERSION: u16 = 1;
 
-    fn from_bytes(bytes: Cow<[u8]>) -> Self {
-        use candid::Decode;
-        Decode!(bytes.as_ref(), Self).unwrap()
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, SchemaError> {
+        match version {
+            // Raw candid of the same record
+            0 => stable_schema::decode_candid(Self::NAME, version, payload),
+            _ => Err(SchemaError::UnsupportedVersion { type_name: Self::NAME, version }),
+        }
     }
 }
 
+stable_schema::versioned_storable!(UserRewards);
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct ClusterWinner {
     pub user_id: String,
@@ -47,6 +47,14 @@ pub struct Account {
     pub subaccount: Option<[u8; 32]>,
 }
 
+// Purpose a reward is paid out for; each maps to its own subaccount
+#[derive(CandidType, Deserialize, Clone)]
+pub enum RewardPurpose {
+    Season(u32),
+    TeamPool(String),
+    VestingTranche(u32),
+}
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct MintRequest {
     pub to: Account,
@@ -65,7 +73,11 @@ thread_local! {
         ));
         
     // Store the token canister ID
-    static TOKEN_CANISTER_ID: RefCell<Option<Principal>> = RefCell::new(None);
+    static TOKEN_CANISTER_ID: RefCell<Option<Principal>> = const { RefCell::new(None) };
+
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> = RefCell::new(
+        SchemaRegistry::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
+    );
 }
 
 #[init]
@@ -77,6 +89,22 @@ fn init(token_canister_id: Option<Principal>) {
     }
 }
 
+#[post_upgrade]
+fn post_upgrade(token_canister_id: Option<Principal>) {
+    // The token canister ID lives on the heap; it is passed again on upgrade
+    init(token_canister_id);
+
+    // Rewrite user rewards in the current schema once per UserRewards version bump
+    let migrated = SCHEMA_REGISTRY.with(|registry| {
+        registry.borrow_mut().migrate::<UserRewards>("user_rewards", || {
+            USER_REWARDS.with(|rewards| stable_schema::rewrite_btree_map(&mut rewards.borrow_mut()))
+        })
+    });
+    if let Some(count) = migrated {
+        ic_cdk::println!("Migrated {} user rewards to schema v{}", count, UserRewards::VERSION);
+    }
+}
+
 #[update]
 pub fn set_token_canister(canister_id: Principal) -> String {
     TOKEN_CANISTER_ID.with(|token_id| {
@@ -160,7 +188,8 @@ pub async fn claim_rewards(user_id: String) -> Result<u64, String> {
 pub async fn mint_ride_rewards(
     user_id: String,
     amount: u64,
-    ride_validation_id: String
+    ride_validation_id: String,
+    purpose: Option<RewardPurpose>,
 ) -> Result<String, String> {
     let token_canister_id = TOKEN_CANISTER_ID.with(|token_id| {
         token_id.borrow().ok_or("Token canister not configured".to_string())
@@ -174,35 +203,44 @@ pub async fn mint_ride_rewards(
     let mint_request = MintRequest {
         to: Account {
             owner: user_principal,
-            subaccount: None,
+            subaccount: purpose.as_ref().map(derive_subaccount),
         },
         amount: Nat::from(amount),
-        memo: Some(ByteBuf::from(ride_validation_id.as_bytes().to_vec())),
+        memo: Some(ByteBuf::from(canonical_hash::reward_mint_memo(&ride_validation_id, &user_id).to_vec())),
         created_at_time: Some(ic_cdk::api::time()),
     };
     
     // Use the new call API
-    let result: Result<(Result<Nat, String>,), _> = ic_cdk::call(
-        token_canister_id,
-        "mint_rewards",
-        (mint_request,),
-    ).await;
+    let result = Call::unbounded_wait(token_canister_id, "mint_rewards")
+        .with_arg(mint_request)
+        .await
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|response| {
+            response
+                .candid::<Result<Nat, String>>()
+                .map_err(|e| format!("{:?}", e))
+        });
     
     match result {
-        Ok((Ok(block_index),)) => Ok(format!("Minted {} tokens, block: {}", amount, block_index)),
-        Ok((Err(e),)) => Err(format!("Mint failed: {}", e)),
-        Err(e) => Err(format!("Inter-canister call failed: {:?}", e)),
+        Ok(Ok(block_index)) => Ok(format!("Minted {} tokens, block: {}", amount, block_index)),
+        Ok(Err(e)) => Err(format!("Mint failed: {}", e)),
+        Err(e) => Err(format!("Inter-canister call failed: {}", e)),
     }
 }
 
 // Batch mint for multiple winners
 #[update]
-pub async fn batch_mint_ride_rewards(winners: Vec<ClusterWinner>, validation_id: String) -> Vec<Result<String, String>> {
-    let token_canister_id = match TOKEN_CANISTER_ID.with(|token_id| token_id.borrow().clone()) {
+pub async fn batch_mint_ride_rewards(
+    winners: Vec<ClusterWinner>,
+    validation_id: String,
+    purpose: Option<RewardPurpose>,
+) -> Vec<Result<String, String>> {
+    let token_canister_id = match TOKEN_CANISTER_ID.with(|token_id| *token_id.borrow()) {
         Some(id) => id,
         None => return vec![Err("Token canister not configured".to_string()); winners.len()],
     };
     
+    let subaccount = purpose.as_ref().map(derive_subaccount);
     let mut mint_requests = Vec::new();
     let mut results = Vec::new();
     
@@ -214,10 +252,10 @@ pub async fn batch_mint_ride_rewards(winners: Vec<ClusterWinner>, validation_id:
                 mint_requests.push(MintRequest {
                     to: Account {
                         owner: principal,
-                        subaccount: None,
+                        subaccount,
                     },
                     amount: Nat::from(reward_amount),
-                    memo: Some(ByteBuf::from(format!("{}_{}", validation_id, winner.user_id).as_bytes().to_vec())),
+                    memo: Some(ByteBuf::from(canonical_hash::reward_mint_memo(&validation_id, &winner.user_id).to_vec())),
                     created_at_time: Some(ic_cdk::api::time()),
                 });
             },
@@ -232,12 +270,17 @@ pub async fn batch_mint_ride_rewards(winners: Vec<ClusterWinner>, validation_id:
     }
     
     // Call token canister batch mint
-    match ic_cdk::call::<(Vec<MintRequest>,), (Vec<Result<Nat, String>>,)>(
-        token_canister_id,
-        "batch_mint_rewards",
-        (mint_requests,),
-    ).await {
-        Ok((mint_results,)) => {
+    let response = Call::unbounded_wait(token_canister_id, "batch_mint_rewards")
+        .with_arg(mint_requests)
+        .await
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|response| {
+            response
+                .candid::<Vec<Result<Nat, String>>>()
+                .map_err(|e| format!("{:?}", e))
+        });
+    match response {
+        Ok(mint_results) => {
             for result in mint_results {
                 match result {
                     Ok(block_index) => results.push(Ok(format!("Minted tokens, block: {}", block_index))),
@@ -246,7 +289,7 @@ pub async fn batch_mint_ride_rewards(winners: Vec<ClusterWinner>, validation_id:
             }
         },
         Err(e) => {
-            results.push(Err(format!("Batch mint call failed: {:?}", e)));
+            results.push(Err(format!("Batch mint call failed: {}", e)));
         }
     }
     
@@ -275,6 +318,35 @@ pub fn get_token_canister() -> Option<Principal> {
     TOKEN_CANISTER_ID.with(|token_id| *token_id.borrow())
 }
 
+#[query]
+pub fn derive_reward_subaccount(purpose: RewardPurpose) -> [u8; 32] {
+    derive_subaccount(&purpose)
+}
+
+// Deterministic subaccount for a reward purpose: SHA-256 over a domain tag,
+// a purpose tag byte and the big-endian / length-prefixed payload
+fn derive_subaccount(purpose: &RewardPurpose) -> [u8; 32] {
+    use sha2::{Sha256, Digest};
+    let mut hasher = Sha256::new();
+    hasher.update(b"bikera-reward-subaccount");
+    match purpose {
+        RewardPurpose::Season(season_id) => {
+            hasher.update([1u8]);
+            hasher.update(season_id.to_be_bytes());
+        }
+        RewardPurpose::TeamPool(team) => {
+            hasher.update([2u8]);
+            hasher.update((team.len() as u32).to_be_bytes());
+            hasher.update(team.as_bytes());
+        }
+        RewardPurpose::VestingTranche(tranche) => {
+            hasher.update([3u8]);
+            hasher.update(tranche.to_be_bytes());
+        }
+    }
+    hasher.finalize().into()
+}
+
 fn calculate_reward(participants: u8) -> u64 {
     // More participants = more reward (network effect)
     // Return in token units (with 6 decimals: 1 iMERA = 1,000,000 units)
@@ -285,3 +357,50 @@ fn calculate_reward(participants: u8) -> u64 {
         _ => 1_000_000_000,        // 1000 iMERA
     }
 }
+
+#[test]
+fn should_decode_user_rewards_stored_before_the_envelope() {
+    let hex = include_str!("../fixtures/user_rewards_v0.hex").trim();
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect();
+
+    let rewards: UserRewards = stable_schema::decode(&bytes).unwrap();
+    assert_eq!(rewards.user_id, "rider-42");
+    assert_eq!(rewards.total_rewards, 2_000);
+    assert_eq!(rewards.pending_rewards, 500);
+    assert_eq!(rewards.principal, Some(Principal::from_slice(&[7; 29])));
+}
+
+#[test]
+fn should_derive_the_same_subaccount_for_the_same_purpose() {
+    assert_eq!(
+        derive_subaccount(&RewardPurpose::TeamPool("north".to_string())),
+        derive_subaccount(&RewardPurpose::TeamPool("north".to_string()))
+    );
+    assert_eq!(
+        derive_reward_subaccount(RewardPurpose::Season(3)),
+        derive_subaccount(&RewardPurpose::Season(3))
+    );
+}
+
+#[test]
+fn should_derive_separate_subaccounts_per_purpose() {
+    let subaccounts = [
+        derive_subaccount(&RewardPurpose::Season(1)),
+        derive_subaccount(&RewardPurpose::Season(2)),
+        derive_subaccount(&RewardPurpose::VestingTranche(1)),
+        derive_subaccount(&RewardPurpose::TeamPool("1".to_string())),
+        derive_subaccount(&RewardPurpose::TeamPool("north".to_string())),
+        derive_subaccount(&RewardPurpose::TeamPool("north ".to_string())),
+    ];
+
+    for (i, a) in subaccounts.iter().enumerate() {
+        for b in &subaccounts[i + 1..] {
+            assert_ne!(a, b);
+        }
+    }
+    // Never the default subaccount
+    assert!(subaccounts.iter().all(|s| s != &[0u8; 32]));
+}
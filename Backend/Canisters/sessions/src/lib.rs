@@ -0,0 +1,625 @@
+// sessions/src/lib.rs - Ride session ledger with privacy-preserving commitments
+//
+// The app or worker commits a salted hash of every ride session (distance, duration and
+// checkpoints), per user and day; the session itself stays off-chain. At the end of the day
+// the user's commitments are Merkle-rooted and anchored, and the root is the `data_hash` of
+// the XP mint for that day. A user can later reveal one session and prove that it is part of
+// the anchor the mint referenced, without revealing the other sessions of the day.
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk_macros::*;
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell};
+use sha2::{Digest, Sha256};
+use stable_schema::{SchemaRegistry, Versioned};
+use std::cell::RefCell;
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+const SESSION_DOMAIN: &[u8] = b"bikera-session-v1";
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+const MAX_SESSIONS_PER_DAY: usize = 500;
+
+// ============= TYPES =============
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Checkpoint {
+    pub lat: i32, // microdegrees
+    pub lon: i32, // microdegrees
+    pub t: u64,   // seconds
+}
+
+/// A session as revealed by its owner. `salt` is the random value the app mixed into the
+/// commitment so that it cannot be brute-forced from plausible distances.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SessionReveal {
+    pub session_id: String,
+    pub distance_meters: u64,
+    pub duration_seconds: u64,
+    pub checkpoints: Vec<Checkpoint>,
+    pub salt: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct SessionCommitment {
+    pub user: Principal,
+    pub day: String, // YYYY-MM-DD, like the XP mint periods
+    pub session_hash: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DailyAnchor {
+    pub user: Principal,
+    pub day: String,
+    /// Hex Merkle root of the day's sessions; the `data_hash` of the XP mint
+    pub root: String,
+    pub session_count: u32,
+    pub anchored_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct UserDay {
+    pub user: Principal,
+    pub day: String,
+    pub session_hashes: Vec<String>,
+    pub anchor: Option<DailyAnchor>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ProofStep {
+    pub sibling: String,
+    pub sibling_is_left: bool,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct InclusionProof {
+    pub leaf_index: u32,
+    pub steps: Vec<ProofStep>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct SessionConfig {
+    committers: Vec<Principal>,
+}
+
+stable_schema::versioned_since_raw_candid!(UserDay, "sessions::UserDay");
+stable_schema::versioned_since_raw_candid!(SessionConfig, "sessions::SessionConfig");
+
+// ============= STORAGE =============
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    static CONFIG: RefCell<StableCell<SessionConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))),
+            SessionConfig::default()
+        ).unwrap()
+    );
+
+    // "<principal>/<day>" -> the user's commitments of that day
+    static DAYS: RefCell<StableBTreeMap<String, UserDay, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))))
+    );
+
+    // Anchored root -> day key, to go from an XP mint's `data_hash` to its sessions
+    static ANCHORS: RefCell<StableBTreeMap<String, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))))
+    );
+
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> = RefCell::new(
+        SchemaRegistry::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))))
+    );
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    // Rewrite the config once per SessionConfig version bump; DAYS grows with every user and
+    // day, so its values are migrated on read
+    let migrated = SCHEMA_REGISTRY.with(|registry| {
+        registry.borrow_mut().migrate::<SessionConfig>("config", || {
+            CONFIG.with(|config| stable_schema::rewrite_cell(&mut config.borrow_mut()))
+        })
+    });
+    if let Some(count) = migrated {
+        ic_cdk::println!("Migrated {} session config to schema v{}", count, SessionConfig::VERSION);
+    }
+}
+
+fn day_key(user: &Principal, day: &str) -> String {
+    format!("{}/{}", user, day)
+}
+
+fn require_committer() -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    if ic_cdk::api::is_controller(&caller)
+        || CONFIG.with(|c| c.borrow().get().committers.contains(&caller))
+    {
+        Ok(())
+    } else {
+        Err("Caller is not allowed to commit sessions".to_string())
+    }
+}
+
+fn validate_day(day: &str) -> Result<(), String> {
+    let bytes = day.as_bytes();
+    let valid = bytes.len() == 10
+        && bytes.iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid day {}, expected YYYY-MM-DD", day))
+    }
+}
+
+fn decode_hash(hash: &str) -> Result<[u8; 32], String> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("Invalid hash {}, expected 32 hex-encoded bytes", hash))
+}
+
+// ============= HASHING =============
+
+/// Commitment of a session: SHA-256 over the domain, then every field with variable-length
+/// fields prefixed by their big-endian `u32` length and integers in big-endian.
+pub fn session_hash(session: &SessionReveal) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(SESSION_DOMAIN);
+    hasher.update((session.salt.len() as u32).to_be_bytes());
+    hasher.update(&session.salt);
+    hasher.update((session.session_id.len() as u32).to_be_bytes());
+    hasher.update(session.session_id.as_bytes());
+    hasher.update(session.distance_meters.to_be_bytes());
+    hasher.update(session.duration_seconds.to_be_bytes());
+    hasher.update((session.checkpoints.len() as u32).to_be_bytes());
+    for checkpoint in &session.checkpoints {
+        hasher.update(checkpoint.lat.to_be_bytes());
+        hasher.update(checkpoint.lon.to_be_bytes());
+        hasher.update(checkpoint.t.to_be_bytes());
+    }
+    hasher.finalize().into()
+}
+
+fn leaf_hash(session_hash: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(session_hash);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Levels of the Merkle tree, from the leaves up to the root. A node without a sibling is
+/// carried up unchanged rather than paired with itself.
+fn merkle_levels(session_hashes: &[[u8; 32]]) -> Vec<Vec<[u8; 32]>> {
+    let mut levels = vec![session_hashes.iter().map(leaf_hash).collect::<Vec<_>>()];
+    while levels.last().map(|l| l.len() > 1).unwrap_or(false) {
+        let level = levels.last().unwrap();
+        let next = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node_hash(left, right),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+fn merkle_proof(session_hashes: &[[u8; 32]], leaf_index: usize) -> InclusionProof {
+    let mut steps = Vec::new();
+    let mut index = leaf_index;
+    for level in merkle_levels(session_hashes).iter() {
+        let sibling = index ^ 1;
+        if sibling < level.len() {
+            steps.push(ProofStep {
+                sibling: hex::encode(level[sibling]),
+                sibling_is_left: sibling < index,
+            });
+        }
+        index /= 2;
+    }
+    InclusionProof {
+        leaf_index: leaf_index as u32,
+        steps,
+    }
+}
+
+/// Folds `proof` over the session's leaf and returns the root it leads to.
+pub fn proof_root(session_hash: &[u8; 32], proof: &InclusionProof) -> Result<[u8; 32], String> {
+    let mut node = leaf_hash(session_hash);
+    for step in &proof.steps {
+        let sibling = decode_hash(&step.sibling)?;
+        node = if step.sibling_is_left {
+            node_hash(&sibling, &node)
+        } else {
+            node_hash(&node, &sibling)
+        };
+    }
+    Ok(node)
+}
+
+fn decoded_hashes(day: &UserDay) -> Vec<[u8; 32]> {
+    day.session_hashes
+        .iter()
+        .map(|hash| decode_hash(hash).expect("stored session hashes are valid"))
+        .collect()
+}
+
+// ============= API =============
+
+#[update]
+pub fn set_committers(committers: Vec<Principal>) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::api::msg_caller()) {
+        return Err("Only controllers can set committers".to_string());
+    }
+    CONFIG.with(|c| {
+        c.borrow_mut()
+            .set(SessionConfig { committers })
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store config: {:?}", e))
+    })
+}
+
+/// Records the commitment of one session; returns its index within the user's day.
+#[update]
+pub fn commit_session(commitment: SessionCommitment) -> Result<u32, String> {
+    require_committer()?;
+    record_commitment(commitment)
+}
+
+fn record_commitment(commitment: SessionCommitment) -> Result<u32, String> {
+    validate_day(&commitment.day)?;
+    let session_hash = hex::encode(decode_hash(&commitment.session_hash)?);
+    let key = day_key(&commitment.user, &commitment.day);
+
+    DAYS.with(|d| {
+        let mut days = d.borrow_mut();
+        let mut day = days.get(&key).unwrap_or(UserDay {
+            user: commitment.user,
+            day: commitment.day.clone(),
+            session_hashes: Vec::new(),
+            anchor: None,
+        });
+        if day.anchor.is_some() {
+            return Err(format!("Day {} is already anchored", commitment.day));
+        }
+        if day.session_hashes.contains(&session_hash) {
+            return Err("Session already committed".to_string());
+        }
+        if day.session_hashes.len() >= MAX_SESSIONS_PER_DAY {
+            return Err(format!("At most {} sessions per day", MAX_SESSIONS_PER_DAY));
+        }
+        day.session_hashes.push(session_hash);
+        let index = day.session_hashes.len() as u32 - 1;
+        days.insert(key, day);
+        Ok(index)
+    })
+}
+
+/// Closes the user's day and anchors the Merkle root of its sessions. Anchoring an anchored
+/// day returns the existing anchor, so the worker can safely retry before minting XP.
+#[update]
+pub fn anchor_day(user: Principal, day: String) -> Result<DailyAnchor, String> {
+    require_committer()?;
+    close_day(user, day, ic_cdk::api::time())
+}
+
+fn close_day(user: Principal, day: String, now: u64) -> Result<DailyAnchor, String> {
+    validate_day(&day)?;
+    let key = day_key(&user, &day);
+    let mut user_day = DAYS
+        .with(|d| d.borrow().get(&key))
+        .ok_or_else(|| format!("No sessions committed for {} on {}", user, day))?;
+    if let Some(anchor) = user_day.anchor {
+        return Ok(anchor);
+    }
+
+    let levels = merkle_levels(&decoded_hashes(&user_day));
+    let root = hex::encode(levels.last().unwrap()[0]);
+    if ANCHORS.with(|a| a.borrow().contains_key(&root)) {
+        // Identical sessions committed for another user or day
+        return Err("Root is already anchored for another day".to_string());
+    }
+    let anchor = DailyAnchor {
+        user,
+        day,
+        root: root.clone(),
+        session_count: user_day.session_hashes.len() as u32,
+        anchored_at: now,
+    };
+    user_day.anchor = Some(anchor.clone());
+    DAYS.with(|d| d.borrow_mut().insert(key.clone(), user_day));
+    ANCHORS.with(|a| a.borrow_mut().insert(root, key));
+    Ok(anchor)
+}
+
+#[query]
+pub fn get_user_day(user: Principal, day: String) -> Option<UserDay> {
+    DAYS.with(|d| d.borrow().get(&day_key(&user, &day)))
+}
+
+/// Looks up the anchor an XP mint referenced through its `data_hash`.
+#[query]
+pub fn get_anchor_by_data_hash(data_hash: String) -> Option<DailyAnchor> {
+    let key = ANCHORS.with(|a| a.borrow().get(&data_hash.to_lowercase()))?;
+    DAYS.with(|d| d.borrow().get(&key)).and_then(|day| day.anchor)
+}
+
+#[query]
+pub fn get_inclusion_proof(
+    user: Principal,
+    day: String,
+    session_hash: String,
+) -> Result<InclusionProof, String> {
+    let session_hash = hex::encode(decode_hash(&session_hash)?);
+    let user_day = get_user_day(user, day.clone())
+        .ok_or_else(|| format!("No sessions committed for {} on {}", user, day))?;
+    if user_day.anchor.is_none() {
+        return Err(format!("Day {} is not anchored yet", day));
+    }
+    let leaf_index = user_day
+        .session_hashes
+        .iter()
+        .position(|hash| *hash == session_hash)
+        .ok_or("Session not committed on that day")?;
+    Ok(merkle_proof(&decoded_hashes(&user_day), leaf_index))
+}
+
+/// Checks that the revealed session was committed in the anchor `data_hash` refers to, and
+/// returns that anchor.
+#[query]
+pub fn verify_session(
+    session: SessionReveal,
+    proof: InclusionProof,
+    data_hash: String,
+) -> Result<DailyAnchor, String> {
+    let anchor = get_anchor_by_data_hash(data_hash.clone())
+        .ok_or_else(|| format!("No anchor with root {}", data_hash))?;
+    let root = proof_root(&session_hash(&session), &proof)?;
+    if hex::encode(root) == anchor.root {
+        Ok(anchor)
+    } else {
+        Err("Session is not included in the anchor".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RIDER: Principal = Principal::from_slice(&[7; 29]);
+    const DAY: &str = "2026-10-18";
+    const ANCHORED_AT: u64 = 1_792_368_000_000_000_000;
+
+    fn session(index: u64) -> SessionReveal {
+        SessionReveal {
+            session_id: format!("session-{}", index),
+            distance_meters: 1_000 + index,
+            duration_seconds: 600,
+            checkpoints: vec![Checkpoint {
+                lat: 46_000_000,
+                lon: 8_000_000,
+                t: 1_792_281_600 + index,
+            }],
+            salt: vec![index as u8; 16],
+        }
+    }
+
+    fn sessions(count: u64) -> Vec<SessionReveal> {
+        (0..count).map(session).collect()
+    }
+
+    fn hashes(sessions: &[SessionReveal]) -> Vec<[u8; 32]> {
+        sessions.iter().map(session_hash).collect()
+    }
+
+    fn root(session_hashes: &[[u8; 32]]) -> [u8; 32] {
+        merkle_levels(session_hashes).last().unwrap()[0]
+    }
+
+    fn commit(user: Principal, day: &str, session: &SessionReveal) -> Result<u32, String> {
+        record_commitment(SessionCommitment {
+            user,
+            day: day.to_string(),
+            session_hash: hex::encode(session_hash(session)),
+        })
+    }
+
+    // Commits the sessions for RIDER on DAY and anchors them; returns the root
+    fn anchor(sessions: &[SessionReveal]) -> String {
+        for session in sessions {
+            commit(RIDER, DAY, session).unwrap();
+        }
+        close_day(RIDER, DAY.to_string(), ANCHORED_AT).unwrap().root
+    }
+
+    #[test]
+    fn should_hash_single_sessions_and_pairs() {
+        let session_hashes = hashes(&sessions(2));
+
+        assert_eq!(root(&session_hashes[..1]), leaf_hash(&session_hashes[0]));
+        assert!(merkle_proof(&session_hashes[..1], 0).steps.is_empty());
+        assert_eq!(
+            root(&session_hashes),
+            node_hash(&leaf_hash(&session_hashes[0]), &leaf_hash(&session_hashes[1]))
+        );
+    }
+
+    #[test]
+    fn should_carry_unpaired_nodes_up() {
+        let session_hashes = hashes(&sessions(3));
+        let levels = merkle_levels(&session_hashes);
+
+        assert_eq!(levels.iter().map(Vec::len).collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert_eq!(levels[1][1], leaf_hash(&session_hashes[2]));
+        assert_eq!(merkle_proof(&session_hashes, 2).steps.len(), 1);
+        assert_eq!(merkle_proof(&session_hashes, 0).steps.len(), 2);
+    }
+
+    #[test]
+    fn should_lead_every_proof_back_to_the_root() {
+        for count in [1, 2, 3, 5, 9, 17] {
+            let session_hashes = hashes(&sessions(count));
+            let root = root(&session_hashes);
+            for (index, session_hash) in session_hashes.iter().enumerate() {
+                let proof = merkle_proof(&session_hashes, index);
+                assert_eq!(proof.leaf_index as usize, index);
+                assert_eq!(
+                    proof_root(session_hash, &proof),
+                    Ok(root),
+                    "leaf {} of {}",
+                    index,
+                    count
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn should_verify_revealed_sessions_against_their_anchor() {
+        let sessions = sessions(5);
+        let data_hash = anchor(&sessions);
+        let session_hashes = hashes(&sessions);
+
+        for (index, session) in sessions.iter().enumerate() {
+            let proof = merkle_proof(&session_hashes, index);
+            let anchor = verify_session(session.clone(), proof, data_hash.to_uppercase());
+            assert_eq!(anchor.map(|a| a.session_count), Ok(5));
+        }
+    }
+
+    #[test]
+    fn should_reject_tampered_sessions_and_proofs() {
+        let sessions = sessions(5);
+        let data_hash = anchor(&sessions);
+        let proof = merkle_proof(&hashes(&sessions), 3);
+        let verify = |session: &SessionReveal, proof: &InclusionProof| {
+            verify_session(session.clone(), proof.clone(), data_hash.clone()).map(|_| ())
+        };
+        assert_eq!(verify(&sessions[3], &proof), Ok(()));
+
+        let mut salted = sessions[3].clone();
+        salted.salt[0] ^= 1;
+        assert!(verify(&salted, &proof).is_err());
+
+        // Another committed session with this session's proof
+        assert!(verify(&sessions[2], &proof).is_err());
+
+        let mut flipped = proof.clone();
+        flipped.steps[0].sibling_is_left = !flipped.steps[0].sibling_is_left;
+        assert!(verify(&sessions[3], &flipped).is_err());
+
+        let mut forged = proof.clone();
+        forged.steps[1].sibling = hex::encode([0u8; 32]);
+        assert!(verify(&sessions[3], &forged).is_err());
+
+        let mut truncated = proof.clone();
+        truncated.steps.pop();
+        assert!(verify(&sessions[3], &truncated).is_err());
+
+        let mut malformed = proof.clone();
+        malformed.steps[0].sibling = "not hex".to_string();
+        assert!(verify(&sessions[3], &malformed).is_err());
+
+        let unknown = verify_session(sessions[3].clone(), proof, hex::encode([1u8; 32]));
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn should_reject_duplicate_sessions() {
+        assert_eq!(commit(RIDER, DAY, &session(0)), Ok(0));
+        assert_eq!(commit(RIDER, DAY, &session(1)), Ok(1));
+        assert!(commit(RIDER, DAY, &session(0)).is_err());
+
+        // Hashes are compared after normalizing their hex
+        let uppercase = record_commitment(SessionCommitment {
+            user: RIDER,
+            day: DAY.to_string(),
+            session_hash: hex::encode_upper(session_hash(&session(1))),
+        });
+        assert!(uppercase.is_err());
+        // The same session on another day is a separate commitment
+        assert_eq!(commit(RIDER, "2026-10-19", &session(0)), Ok(0));
+    }
+
+    #[test]
+    fn should_cap_the_sessions_of_a_day() {
+        for index in 0..MAX_SESSIONS_PER_DAY as u64 {
+            commit(RIDER, DAY, &session(index)).unwrap();
+        }
+
+        let over = session(MAX_SESSIONS_PER_DAY as u64);
+        assert_eq!(
+            commit(RIDER, DAY, &over),
+            Err(format!("At most {} sessions per day", MAX_SESSIONS_PER_DAY))
+        );
+        assert_eq!(commit(RIDER, "2026-10-19", &over), Ok(0));
+    }
+
+    #[test]
+    fn should_refuse_commits_to_anchored_days() {
+        anchor(&sessions(2));
+
+        assert_eq!(
+            commit(RIDER, DAY, &session(2)),
+            Err(format!("Day {} is already anchored", DAY))
+        );
+        assert_eq!(get_user_day(RIDER, DAY.to_string()).unwrap().session_hashes.len(), 2);
+    }
+
+    #[test]
+    fn should_return_the_existing_anchor_on_retry() {
+        let root = anchor(&sessions(3));
+
+        let retried = close_day(RIDER, DAY.to_string(), ANCHORED_AT + 60_000_000_000).unwrap();
+        assert_eq!(retried.root, root);
+        assert_eq!(retried.anchored_at, ANCHORED_AT);
+        assert_eq!(retried.session_count, 3);
+    }
+
+    #[test]
+    fn should_not_anchor_a_root_twice() {
+        let sessions = sessions(2);
+        let root = anchor(&sessions);
+
+        // The same sessions committed for another day lead to the same root
+        for session in &sessions {
+            commit(RIDER, "2026-10-19", session).unwrap();
+        }
+        assert_eq!(
+            close_day(RIDER, "2026-10-19".to_string(), ANCHORED_AT).err().as_deref(),
+            Some("Root is already anchored for another day")
+        );
+        assert_eq!(get_anchor_by_data_hash(root).map(|anchor| anchor.day), Some(DAY.to_string()));
+        assert!(get_user_day(RIDER, "2026-10-19".to_string()).unwrap().anchor.is_none());
+    }
+
+    #[test]
+    fn should_read_days_stored_before_the_envelope() {
+        let day = UserDay {
+            user: RIDER,
+            day: DAY.to_string(),
+            session_hashes: vec![hex::encode(session_hash(&session(0)))],
+            anchor: None,
+        };
+
+        let bytes = candid::encode_one(&day).unwrap();
+        let stored: UserDay = stable_schema::decode(&bytes).unwrap();
+        assert_eq!(stored.user, RIDER);
+        assert_eq!(stored.session_hashes, day.session_hashes);
+    }
+}
@@ -0,0 +1,288 @@
+// stable_schema/src/lib.rs - Versioned envelope for values kept in stable structures
+//
+// The canisters used to store `Encode!(value)` and `unwrap()` the `Decode!`, so a schema
+// change either failed to decode after an upgrade or, for bounded types, overflowed the bound
+// at runtime. With the envelope every value is stored as
+//
+//     b"BKSV" | version: u16 big-endian | candid payload
+//
+// and decoded through its type's `Versioned::migrate` when the stored version is older than
+// the current one. Values written before the envelope start with the candid magic `DIDL` and
+// are read as version 0.
+//
+// Keys are never enveloped: their bytes define the order of a map and must not change.
+use candid::CandidType;
+use ic_stable_structures::{Memory, StableBTreeMap, StableCell, StableVec, Storable};
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+pub use ic_stable_structures;
+
+#[cfg(test)]
+mod tests;
+
+pub const MAGIC: &[u8; 4] = b"BKSV";
+pub const HEADER_LEN: usize = MAGIC.len() + 2;
+const CANDID_MAGIC: &[u8; 4] = b"DIDL";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// Neither an envelope nor raw candid
+    UnknownFormat,
+    /// Written by a newer build (e.g. after a downgrade), or no migration for that version
+    UnsupportedVersion {
+        type_name: &'static str,
+        version: u16,
+    },
+    Candid {
+        type_name: &'static str,
+        version: u16,
+        message: String,
+    },
+    TooLarge {
+        type_name: &'static str,
+        size: usize,
+        max_size: u32,
+    },
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::UnknownFormat => write!(f, "value is neither enveloped nor candid"),
+            SchemaError::UnsupportedVersion { type_name, version } => {
+                write!(
+                    f,
+                    "{} has no decoder for schema version {}",
+                    type_name, version
+                )
+            }
+            SchemaError::Candid {
+                type_name,
+                version,
+                message,
+            } => {
+                write!(
+                    f,
+                    "failed to decode {} v{}: {}",
+                    type_name, version, message
+                )
+            }
+            SchemaError::TooLarge {
+                type_name,
+                size,
+                max_size,
+            } => write!(
+                f,
+                "{} is {} bytes once encoded, over its {}-byte bound",
+                type_name, size, max_size
+            ),
+        }
+    }
+}
+
+/// A value with a schema version, stored through [`versioned_storable!`].
+pub trait Versioned: CandidType + DeserializeOwned {
+    /// Name used in error messages and in the [`SchemaRegistry`]
+    const NAME: &'static str;
+    /// Version written with every value, starting at 1; bump it whenever the candid type
+    /// changes
+    const VERSION: u16;
+
+    /// Decodes a payload written with an older `version` (0 for values stored before the
+    /// envelope) into the current type.
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, SchemaError>;
+}
+
+/// Decodes a candid `payload` as `T`; the building block of [`Versioned::migrate`].
+pub fn decode_candid<T: CandidType + DeserializeOwned>(
+    type_name: &'static str,
+    version: u16,
+    payload: &[u8],
+) -> Result<T, SchemaError> {
+    candid::decode_one(payload).map_err(|e| SchemaError::Candid {
+        type_name,
+        version,
+        message: e.to_string(),
+    })
+}
+
+/// Schema version of stored bytes.
+pub fn stored_version(bytes: &[u8]) -> Result<u16, SchemaError> {
+    if bytes.starts_with(MAGIC) && bytes.len() >= HEADER_LEN {
+        Ok(u16::from_be_bytes([bytes[4], bytes[5]]))
+    } else if bytes.starts_with(CANDID_MAGIC) {
+        Ok(0)
+    } else {
+        Err(SchemaError::UnknownFormat)
+    }
+}
+
+pub fn encode<T: Versioned>(value: &T) -> Vec<u8> {
+    let payload =
+        candid::encode_one(value).unwrap_or_else(|e| panic!("failed to encode {}: {}", T::NAME, e));
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&T::VERSION.to_be_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Like [`encode`], but fails instead of overflowing the bound of a bounded type.
+pub fn encode_bounded<T: Versioned>(value: &T, max_size: u32) -> Result<Vec<u8>, SchemaError> {
+    let bytes = encode(value);
+    if bytes.len() > max_size as usize {
+        return Err(SchemaError::TooLarge {
+            type_name: T::NAME,
+            size: bytes.len(),
+            max_size,
+        });
+    }
+    Ok(bytes)
+}
+
+pub fn decode<T: Versioned>(bytes: &[u8]) -> Result<T, SchemaError> {
+    match stored_version(bytes)? {
+        0 => T::migrate(0, bytes),
+        version if version == T::VERSION => decode_candid(T::NAME, version, &bytes[HEADER_LEN..]),
+        version if version < T::VERSION => T::migrate(version, &bytes[HEADER_LEN..]),
+        version => Err(SchemaError::UnsupportedVersion {
+            type_name: T::NAME,
+            version,
+        }),
+    }
+}
+
+/// Implements `Storable` for a [`Versioned`] type through the envelope.
+///
+/// `versioned_storable!(T)` is unbounded; `versioned_storable!(T, max_size = N)` keeps an
+/// existing bound (e.g. for `StableVec`) and traps with the sizes if a value outgrows it.
+#[macro_export]
+macro_rules! versioned_storable {
+    ($t:ty) => {
+        impl $crate::ic_stable_structures::Storable for $t {
+            const BOUND: $crate::ic_stable_structures::storable::Bound =
+                $crate::ic_stable_structures::storable::Bound::Unbounded;
+
+            fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+                std::borrow::Cow::Owned($crate::encode(self))
+            }
+
+            fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                $crate::decode(bytes.as_ref()).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+    ($t:ty, max_size = $max_size:expr) => {
+        impl $crate::ic_stable_structures::Storable for $t {
+            const BOUND: $crate::ic_stable_structures::storable::Bound =
+                $crate::ic_stable_structures::storable::Bound::Bounded {
+                    max_size: $max_size,
+                    is_fixed_size: false,
+                };
+
+            fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+                std::borrow::Cow::Owned(
+                    $crate::encode_bounded(self, $max_size).unwrap_or_else(|e| panic!("{}", e)),
+                )
+            }
+
+            fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+                $crate::decode(bytes.as_ref()).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+/// First envelope version of a type that used to be stored as raw candid: implements
+/// [`Versioned`] with `VERSION = 1`, reading version 0 as the same record, and `Storable`.
+#[macro_export]
+macro_rules! versioned_since_raw_candid {
+    ($t:ty, $name:expr) => {
+        impl $crate::Versioned for $t {
+            const NAME: &'static str = $name;
+            const VERSION: u16 = 1;
+
+            fn migrate(version: u16, payload: &[u8]) -> Result<Self, $crate::SchemaError> {
+                match version {
+                    0 => $crate::decode_candid(Self::NAME, version, payload),
+                    _ => Err($crate::SchemaError::UnsupportedVersion {
+                        type_name: Self::NAME,
+                        version,
+                    }),
+                }
+            }
+        }
+
+        $crate::versioned_storable!($t);
+    };
+}
+
+// ============= MIGRATIONS =============
+
+/// Schema version each stable collection was last rewritten with, in its own memory.
+///
+/// Decoding already migrates old values on read; `migrate` additionally rewrites a whole
+/// collection once after each version bump, from `post_upgrade`, so that old encodings do not
+/// linger and migrations can eventually be removed.
+pub struct SchemaRegistry<M: Memory> {
+    versions: StableBTreeMap<String, u16, M>,
+}
+
+impl<M: Memory> SchemaRegistry<M> {
+    pub fn init(memory: M) -> Self {
+        Self {
+            versions: StableBTreeMap::init(memory),
+        }
+    }
+
+    pub fn version_of(&self, collection: &str) -> Option<u16> {
+        self.versions.get(&collection.to_string())
+    }
+
+    /// Runs `rewrite` unless `collection` was already rewritten at `T::VERSION`; returns the
+    /// number of values rewritten, or `None` if there was nothing to do.
+    pub fn migrate<T: Versioned>(
+        &mut self,
+        collection: &str,
+        rewrite: impl FnOnce() -> u64,
+    ) -> Option<u64> {
+        if self.version_of(collection) == Some(T::VERSION) {
+            return None;
+        }
+        let rewritten = rewrite();
+        self.versions.insert(collection.to_string(), T::VERSION);
+        Some(rewritten)
+    }
+}
+
+pub fn rewrite_btree_map<K, V, M>(map: &mut StableBTreeMap<K, V, M>) -> u64
+where
+    K: Storable + Ord + Clone,
+    V: Storable,
+    M: Memory,
+{
+    let keys: Vec<K> = map.iter().map(|(key, _)| key).collect();
+    for key in &keys {
+        if let Some(value) = map.get(key) {
+            map.insert(key.clone(), value);
+        }
+    }
+    keys.len() as u64
+}
+
+pub fn rewrite_vec<T: Storable, M: Memory>(vec: &StableVec<T, M>) -> u64 {
+    for index in 0..vec.len() {
+        if let Some(value) = vec.get(index) {
+            vec.set(index, &value);
+        }
+    }
+    vec.len()
+}
+
+pub fn rewrite_cell<T: Storable + Clone, M: Memory>(cell: &mut StableCell<T, M>) -> u64 {
+    let value = cell.get().clone();
+    cell.set(value)
+        .unwrap_or_else(|e| panic!("failed to rewrite stable cell: {:?}", e));
+    1
+}
@@ -0,0 +1,212 @@
+use crate::*;
+use candid::{Deserialize, Nat, Principal};
+use ic_stable_structures::{StableBTreeMap, VectorMemory};
+
+// Schema history of `Profile`, mirrored by the fixtures in `fixtures/`:
+//
+// v0 (raw candid, before the envelope): record { user_id: text; total: nat64 }
+// v1: record { user_id: text; total: nat64; badges: vec text }
+// v2 (current): `total` becomes a `nat` and an optional principal is added
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+struct Profile {
+    user_id: String,
+    total: Nat,
+    badges: Vec<String>,
+    principal: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ProfileV0 {
+    user_id: String,
+    total: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ProfileV1 {
+    user_id: String,
+    total: u64,
+    badges: Vec<String>,
+}
+
+impl Versioned for Profile {
+    const NAME: &'static str = "Profile";
+    const VERSION: u16 = 2;
+
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, SchemaError> {
+        let v1 = match version {
+            0 => {
+                let v0: ProfileV0 = decode_candid(Self::NAME, version, payload)?;
+                ProfileV1 {
+                    user_id: v0.user_id,
+                    total: v0.total,
+                    badges: Vec::new(),
+                }
+            }
+            1 => decode_candid(Self::NAME, version, payload)?,
+            _ => {
+                return Err(SchemaError::UnsupportedVersion {
+                    type_name: Self::NAME,
+                    version,
+                })
+            }
+        };
+        Ok(Profile {
+            user_id: v1.user_id,
+            total: Nat::from(v1.total),
+            badges: v1.badges,
+            principal: None,
+        })
+    }
+}
+
+versioned_storable!(Profile);
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+struct Note {
+    text: String,
+}
+
+impl Versioned for Note {
+    const NAME: &'static str = "Note";
+    const VERSION: u16 = 1;
+
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, SchemaError> {
+        decode_candid(Self::NAME, version, payload)
+    }
+}
+
+versioned_storable!(Note, max_size = 32);
+
+fn fixture(hex: &str) -> Vec<u8> {
+    let hex = hex.trim();
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn profile_v0() -> Vec<u8> {
+    fixture(include_str!("../fixtures/profile_v0.hex"))
+}
+
+fn profile_v1() -> Vec<u8> {
+    fixture(include_str!("../fixtures/profile_v1.hex"))
+}
+
+fn current_profile() -> Profile {
+    Profile {
+        user_id: "rider-42".to_string(),
+        total: Nat::from(1_500u64),
+        badges: vec!["first_ride".to_string(), "10k".to_string()],
+        principal: Some(Principal::from_slice(&[7; 29])),
+    }
+}
+
+#[test]
+fn should_decode_raw_candid_from_before_the_envelope() {
+    let bytes = profile_v0();
+    assert_eq!(stored_version(&bytes), Ok(0));
+
+    assert_eq!(
+        decode::<Profile>(&bytes).unwrap(),
+        Profile {
+            user_id: "rider-42".to_string(),
+            total: Nat::from(1_500u64),
+            badges: vec![],
+            principal: None,
+        }
+    );
+}
+
+#[test]
+fn should_migrate_older_envelope() {
+    let bytes = profile_v1();
+    assert_eq!(stored_version(&bytes), Ok(1));
+
+    assert_eq!(
+        decode::<Profile>(&bytes).unwrap(),
+        Profile {
+            principal: None,
+            ..current_profile()
+        }
+    );
+}
+
+#[test]
+fn should_round_trip_current_version() {
+    let bytes = encode(&current_profile());
+
+    assert!(bytes.starts_with(MAGIC));
+    assert_eq!(stored_version(&bytes), Ok(2));
+    assert_eq!(decode::<Profile>(&bytes).unwrap(), current_profile());
+}
+
+#[test]
+fn should_reject_versions_from_a_newer_build() {
+    let mut bytes = encode(&current_profile());
+    bytes[4..HEADER_LEN].copy_from_slice(&3u16.to_be_bytes());
+
+    assert_eq!(
+        decode::<Profile>(&bytes),
+        Err(SchemaError::UnsupportedVersion {
+            type_name: "Profile",
+            version: 3
+        })
+    );
+}
+
+#[test]
+fn should_reject_unknown_formats() {
+    assert_eq!(
+        decode::<Profile>(b"not candid"),
+        Err(SchemaError::UnknownFormat)
+    );
+    assert_eq!(decode::<Profile>(&[]), Err(SchemaError::UnknownFormat));
+}
+
+#[test]
+fn should_refuse_values_over_the_bound() {
+    let short = Note {
+        text: "ok".to_string(),
+    };
+    let long = Note {
+        text: "x".repeat(64),
+    };
+
+    assert!(encode_bounded(&short, 32).is_ok());
+    assert!(matches!(
+        encode_bounded(&long, 32),
+        Err(SchemaError::TooLarge {
+            type_name: "Note",
+            max_size: 32,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn should_rewrite_collection_once_per_version() {
+    let memory = VectorMemory::default();
+    let mut raw: StableBTreeMap<u64, Vec<u8>, _> = StableBTreeMap::init(memory.clone());
+    raw.insert(1, profile_v0());
+    raw.insert(2, profile_v1());
+
+    let mut registry = SchemaRegistry::init(VectorMemory::default());
+    let mut profiles: StableBTreeMap<u64, Profile, _> = StableBTreeMap::init(memory.clone());
+
+    assert_eq!(
+        registry.migrate::<Profile>("profiles", || rewrite_btree_map(&mut profiles)),
+        Some(2)
+    );
+    assert_eq!(registry.version_of("profiles"), Some(2));
+    assert_eq!(
+        registry.migrate::<Profile>("profiles", || rewrite_btree_map(&mut profiles)),
+        None
+    );
+
+    let raw: StableBTreeMap<u64, Vec<u8>, _> = StableBTreeMap::init(memory);
+    for (_, bytes) in raw.iter() {
+        assert_eq!(stored_version(&bytes), Ok(2));
+    }
+}
@@ -0,0 +1,614 @@
+// Interval-based distance competition
+//
+// On-chain port of the worker's `calculateWinners`: every interval gets a target distance
+// between 0 and `max_target_meters`, and the riders whose distance is closest to it win.
+//
+// The target is derived from `raw_rand` bytes that are committed (hashed) when the interval
+// opens and revealed when it closes, so anyone can check that the target was fixed before the
+// submissions were known. Ranking is deterministic, and the daily-win cap and the
+// no-consecutive-wins rule that the worker kept in KV / `user_stats` are tracked here. Wins
+// count towards the same `RiderHistory` the anti-gaming rules use, and the daily cap is their
+// `max_wins_per_day` rule.
+
+use crate::rules::{self, RuleConfig, RuleContext, RuleResult};
+use crate::{
+    compute_merkle_root, memory, ClusterWinner, CompactSubmission, IntervalResult, Memory,
+};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk_macros::*;
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+// ============= TYPES =============
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CompetitionConfig {
+    /// Principals allowed to open and close intervals, besides the controllers
+    pub workers: Vec<Principal>,
+    pub max_target_meters: u32,
+    /// Share of the participants that wins, in basis points (worker: 10%)
+    pub winner_ratio_bps: u32,
+    pub max_winners: u32,
+    pub max_daily_wins: u32,
+    /// Maximum accepted average speed, in millimetres per second (worker: 50 km/h)
+    pub max_speed_mm_per_s: u64,
+    pub max_distance_meters: u32,
+}
+
+impl Default for CompetitionConfig {
+    fn default() -> Self {
+        Self {
+            workers: Vec::new(),
+            max_target_meters: 5_000,
+            winner_ratio_bps: 1_000,
+            max_winners: 50,
+            max_daily_wins: 38,
+            max_speed_mm_per_s: 13_890,
+            max_distance_meters: 500_000,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DistanceSubmission {
+    pub uid: u32,
+    pub distance_meters: u32,
+    pub duration_seconds: u32,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExclusionReason {
+    InvalidDistance,
+    SpeedExceeded,
+    DailyLimitExceeded,
+    ConsecutiveWinPrevented,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DistanceWinner {
+    pub uid: u32,
+    pub distance_meters: u32,
+    /// Absolute difference to the target distance
+    pub deviation_meters: u32,
+    pub rank: u32,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Exclusion {
+    pub uid: u32,
+    pub reason: ExclusionReason,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum CompetitionStatus {
+    Open,
+    Closed,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Competition {
+    pub interval_id: u64,
+    pub status: CompetitionStatus,
+    /// Hex SHA-256 of the domain, the interval id and the seed, published when opening
+    pub commitment: String,
+    /// Revealed once the competition is closed
+    pub seed: Option<Vec<u8>>,
+    pub target_distance_meters: Option<u32>,
+    pub opened_at: u64,
+    pub closed_at: Option<u64>,
+    pub participants: u32,
+    pub winners: Vec<DistanceWinner>,
+    pub excluded: Vec<Exclusion>,
+    pub merkle_root: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CompetitionResult {
+    pub competition: Competition,
+    /// Winners in the format submitted to consensus and rewards. A distance competition has no
+    /// location, so `cluster_center` is `(0, 0)` and `participants` counts the whole interval.
+    pub interval_result: IntervalResult,
+}
+
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct CompetitionState {
+    config: CompetitionConfig,
+    last_closed_interval: Option<u64>,
+}
+
+stable_schema::versioned_since_raw_candid!(Competition, "validator::Competition");
+stable_schema::versioned_since_raw_candid!(CompetitionState, "validator::CompetitionState");
+
+// ============= STORAGE =============
+
+thread_local! {
+    static STATE: RefCell<StableCell<CompetitionState, Memory>> = RefCell::new(
+        StableCell::init(memory(0), CompetitionState::default())
+            .expect("failed to initialize the competition state")
+    );
+
+    static COMPETITIONS: RefCell<StableBTreeMap<u64, Competition, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(1)));
+
+    // Memory 2 held per-day win counts, which now live in the rider history
+}
+
+fn read_competition_state<R>(f: impl FnOnce(&CompetitionState) -> R) -> R {
+    STATE.with(|s| f(s.borrow().get()))
+}
+
+fn mutate_competition_state<R>(f: impl FnOnce(&mut CompetitionState) -> R) -> R {
+    STATE.with(|s| {
+        let mut cell = s.borrow_mut();
+        let mut state = cell.get().clone();
+        let result = f(&mut state);
+        cell.set(state)
+            .expect("failed to write the competition state");
+        result
+    })
+}
+
+// Closed competitions accumulate with every interval and are migrated on read
+pub fn migrate_stable_schema() {
+    crate::migrate_collection::<CompetitionState>("competition_state", || {
+        STATE.with(|s| stable_schema::rewrite_cell(&mut s.borrow_mut()))
+    });
+}
+
+pub(crate) fn require_worker() -> Result<(), String> {
+    let caller = ic_cdk::api::msg_caller();
+    if ic_cdk::api::is_controller(&caller)
+        || read_competition_state(|s| s.config.workers.contains(&caller))
+    {
+        Ok(())
+    } else {
+        Err("Caller is not a competition worker".to_string())
+    }
+}
+
+// ============= RANDOMNESS =============
+
+pub fn commitment(interval_id: u64, seed: &[u8]) -> String {
+    canonical_hash::competition_commitment(interval_id, seed)
+}
+
+/// Target distance in `0..=max_target_meters` derived from the revealed seed.
+pub fn target_distance(interval_id: u64, seed: &[u8], max_target_meters: u32) -> u32 {
+    let digest = canonical_hash::competition_target_digest(interval_id, seed);
+    let value = u64::from_be_bytes(digest[..8].try_into().unwrap());
+    (value % (max_target_meters as u64 + 1)) as u32
+}
+
+// ============= RANKING =============
+
+fn check_submission(config: &CompetitionConfig, s: &DistanceSubmission) -> Option<ExclusionReason> {
+    if s.distance_meters > config.max_distance_meters {
+        return Some(ExclusionReason::InvalidDistance);
+    }
+    if s.duration_seconds > 0
+        && s.distance_meters as u64 * 1_000 > config.max_speed_mm_per_s * s.duration_seconds as u64
+    {
+        return Some(ExclusionReason::SpeedExceeded);
+    }
+    None
+}
+
+fn winner_count(config: &CompetitionConfig, participants: usize) -> usize {
+    let share = participants as u64 * config.winner_ratio_bps as u64 / 10_000;
+    (share.max(1) as usize)
+        .min(config.max_winners as usize)
+        .min(participants)
+}
+
+/// Ranks the submissions of one interval by closeness to `target`.
+///
+/// Keeps the latest submission of each rider, drops invalid ones, orders by deviation from the
+/// target with ties broken by uid, and fills the winner slots in that order while skipping the
+/// riders `is_capped` reports. Returns the winners and every exclusion, both in rank order.
+pub fn rank_submissions(
+    config: &CompetitionConfig,
+    target: u32,
+    submissions: &[DistanceSubmission],
+    is_capped: impl Fn(u32) -> Option<ExclusionReason>,
+) -> (Vec<DistanceWinner>, Vec<Exclusion>, u32) {
+    let mut latest: BTreeMap<u32, &DistanceSubmission> = BTreeMap::new();
+    for submission in submissions {
+        let entry = latest.entry(submission.uid).or_insert(submission);
+        if (submission.timestamp, submission.distance_meters)
+            > (entry.timestamp, entry.distance_meters)
+        {
+            *entry = submission;
+        }
+    }
+    let participants = latest.len();
+
+    let mut excluded = Vec::new();
+    let mut ranked: Vec<(u32, &DistanceSubmission)> = Vec::new();
+    for submission in latest.values() {
+        match check_submission(config, submission) {
+            Some(reason) => excluded.push(Exclusion {
+                uid: submission.uid,
+                reason,
+            }),
+            None => ranked.push((submission.distance_meters.abs_diff(target), submission)),
+        }
+    }
+    ranked.sort_by_key(|(deviation, s)| (*deviation, s.uid));
+
+    let slots = winner_count(config, ranked.len());
+    let mut winners = Vec::new();
+    for (deviation, submission) in ranked {
+        if winners.len() >= slots {
+            break;
+        }
+        if let Some(reason) = is_capped(submission.uid) {
+            excluded.push(Exclusion {
+                uid: submission.uid,
+                reason,
+            });
+            continue;
+        }
+        winners.push(DistanceWinner {
+            uid: submission.uid,
+            distance_meters: submission.distance_meters,
+            deviation_meters: deviation,
+            rank: winners.len() as u32 + 1,
+        });
+    }
+
+    (winners, excluded, participants as u32)
+}
+
+/// Scores a rider against the daily-win cap at `t` (seconds), from the wins in its history.
+///
+/// A distance submission has no location, so only the `max_wins_per_day` rule applies; the
+/// track is a single point at the rider's last known position.
+pub fn daily_cap_results(config: &CompetitionConfig, uid: u32, t: u64) -> Vec<RuleResult> {
+    let Some(history) = rules::rider_history(uid) else {
+        return Vec::new();
+    };
+    let cap = rules::build_rules(&[RuleConfig::MaxWinsPerDay {
+        max_wins: config.max_daily_wins,
+    }]);
+    let now = CompactSubmission {
+        uid,
+        lat: history.last_lat,
+        lon: history.last_lon,
+        t,
+    };
+    rules::evaluate_rules(
+        &cap,
+        &RuleContext {
+            uid,
+            track: &[&now],
+            history: Some(&history),
+        },
+    )
+}
+
+/// Counts a win at `t` (seconds) in each winner's rider history.
+pub fn record_wins(winners: &[DistanceWinner], t: u64) {
+    for winner in winners {
+        let mut history = rules::rider_history(winner.uid).unwrap_or_default();
+        history.record_win(t);
+        rules::save_rider_history(winner.uid, history);
+    }
+}
+
+// ============= API =============
+
+/// Opens the competition of `interval_id` and commits to a random seed.
+#[update]
+pub async fn open_competition(interval_id: u64) -> Result<String, String> {
+    require_worker()?;
+    if COMPETITIONS.with(|c| c.borrow().contains_key(&interval_id)) {
+        return Err(format!("Competition {} already exists", interval_id));
+    }
+
+    let seed = ic_cdk::management_canister::raw_rand()
+        .await
+        .map_err(|e| format!("raw_rand failed: {:?}", e))?;
+
+    let commitment = commitment(interval_id, &seed);
+    COMPETITIONS.with(|c| {
+        let mut competitions = c.borrow_mut();
+        // Checked again: another call may have opened the interval during the await
+        if competitions.contains_key(&interval_id) {
+            return Err(format!("Competition {} already exists", interval_id));
+        }
+        competitions.insert(
+            interval_id,
+            Competition {
+                interval_id,
+                status: CompetitionStatus::Open,
+                commitment: commitment.clone(),
+                seed: Some(seed),
+                target_distance_meters: None,
+                opened_at: ic_cdk::api::time(),
+                closed_at: None,
+                participants: 0,
+                winners: Vec::new(),
+                excluded: Vec::new(),
+                merkle_root: None,
+            },
+        );
+        Ok(commitment)
+    })
+}
+
+/// Closes the competition of `interval_id` with the interval's `distance_submissions`,
+/// reveals the seed and records the winners against the daily-win caps.
+#[update]
+pub fn close_competition(
+    interval_id: u64,
+    submissions: Vec<DistanceSubmission>,
+) -> Result<CompetitionResult, String> {
+    require_worker()?;
+    let mut competition = COMPETITIONS
+        .with(|c| c.borrow().get(&interval_id))
+        .ok_or_else(|| format!("Competition {} not found", interval_id))?;
+    if let CompetitionStatus::Closed = competition.status {
+        return Err(format!("Competition {} is already closed", interval_id));
+    }
+
+    let now = ic_cdk::api::time();
+    let now_seconds = now / NANOS_PER_SECOND;
+    let (config, last_closed) =
+        read_competition_state(|s| (s.config.clone(), s.last_closed_interval));
+    let last_winners: Vec<u32> = last_closed
+        .and_then(|id| COMPETITIONS.with(|c| c.borrow().get(&id)))
+        .map(|c| c.winners.iter().map(|w| w.uid).collect())
+        .unwrap_or_default();
+
+    let seed = competition.seed.clone().unwrap_or_default();
+    let target = target_distance(interval_id, &seed, config.max_target_meters);
+    let rule_results = RefCell::new(Vec::new());
+    let (winners, excluded, participants) =
+        rank_submissions(&config, target, &submissions, |uid| {
+            let capped = daily_cap_results(&config, uid, now_seconds);
+            if !capped.is_empty() {
+                rule_results.borrow_mut().extend(capped);
+                Some(ExclusionReason::DailyLimitExceeded)
+            } else if last_winners.contains(&uid) {
+                Some(ExclusionReason::ConsecutiveWinPrevented)
+            } else {
+                None
+            }
+        });
+    record_wins(&winners, now_seconds);
+
+    let cluster_winners: Vec<ClusterWinner> = winners
+        .iter()
+        .map(|w| ClusterWinner {
+            uid: w.uid,
+            cluster_center: (0, 0),
+            participants: participants.min(u8::MAX as u32) as u8,
+        })
+        .collect();
+    let merkle_root = compute_merkle_root(&cluster_winners);
+
+    competition.status = CompetitionStatus::Closed;
+    competition.target_distance_meters = Some(target);
+    competition.closed_at = Some(now);
+    competition.participants = participants;
+    competition.winners = winners;
+    competition.excluded = excluded;
+    competition.merkle_root = Some(merkle_root.clone());
+    COMPETITIONS.with(|c| c.borrow_mut().insert(interval_id, competition.clone()));
+    mutate_competition_state(|s| s.last_closed_interval = Some(interval_id));
+
+    Ok(CompetitionResult {
+        interval_result: IntervalResult {
+            valid: !cluster_winners.is_empty(),
+            interval_id,
+            merkle_root,
+            valid_submissions: participants
+                - competition
+                    .excluded
+                    .iter()
+                    .filter(|e| {
+                        matches!(
+                            e.reason,
+                            ExclusionReason::InvalidDistance | ExclusionReason::SpeedExceeded
+                        )
+                    })
+                    .count() as u32,
+            cluster_winners,
+            rule_results: rule_results.into_inner(),
+        },
+        competition,
+    })
+}
+
+/// Returns the competition of `interval_id`; the seed is hidden while it is open.
+#[query]
+pub fn get_competition(interval_id: u64) -> Option<Competition> {
+    COMPETITIONS
+        .with(|c| c.borrow().get(&interval_id))
+        .map(|mut competition| {
+            if let CompetitionStatus::Open = competition.status {
+                competition.seed = None;
+            }
+            competition
+        })
+}
+
+#[query]
+pub fn get_daily_wins(uid: u32) -> u32 {
+    let day = ic_cdk::api::time() / NANOS_PER_SECOND / rules::SECONDS_PER_DAY;
+    rules::rider_history(uid).map_or(0, |history| history.wins_on(day))
+}
+
+#[query]
+pub fn get_competition_config() -> CompetitionConfig {
+    read_competition_state(|s| s.config.clone())
+}
+
+#[update]
+pub fn set_competition_config(config: CompetitionConfig) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::api::msg_caller()) {
+        return Err("Only controllers can configure the competition".to_string());
+    }
+    if config.max_winners == 0 || config.winner_ratio_bps == 0 || config.winner_ratio_bps > 10_000 {
+        return Err("Winner ratio must be in 1..=10000 bps and max_winners positive".to_string());
+    }
+    mutate_competition_state(|s| s.config = config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECONDS_PER_DAY: u64 = rules::SECONDS_PER_DAY;
+
+    fn submission(uid: u32, distance_meters: u32, timestamp: u64) -> DistanceSubmission {
+        DistanceSubmission {
+            uid,
+            distance_meters,
+            duration_seconds: 600,
+            timestamp,
+        }
+    }
+
+    fn config(winner_ratio_bps: u32, max_winners: u32) -> CompetitionConfig {
+        CompetitionConfig {
+            winner_ratio_bps,
+            max_winners,
+            ..CompetitionConfig::default()
+        }
+    }
+
+    fn ranked_uids(winners: &[DistanceWinner]) -> Vec<(u32, u32)> {
+        winners.iter().map(|w| (w.rank, w.uid)).collect()
+    }
+
+    #[test]
+    fn should_derive_the_target_from_the_revealed_seed() {
+        let seed: Vec<u8> = (0..32).collect();
+
+        // First 8 bytes of the `competition_target` test vector digest, modulo max + 1
+        assert_eq!(target_distance(5_843_210, &seed, 5_000), 4_625);
+        assert_eq!(target_distance(5_843_210, &seed, 1_000), 794);
+        assert_eq!(target_distance(5_843_210, &seed, 0), 0);
+        assert_eq!(
+            commitment(5_843_210, &seed),
+            "9d8efca89ae688af9f7de5b2cc973ddcdf2f109851837ed2708f9503a4a70587"
+        );
+
+        for interval_id in 0..100 {
+            assert!(target_distance(interval_id, &seed, 5_000) <= 5_000);
+        }
+    }
+
+    #[test]
+    fn should_rank_by_deviation_with_ties_broken_by_uid() {
+        let submissions = vec![
+            submission(7, 1_100, 1),
+            submission(3, 900, 1),
+            submission(5, 1_000, 1),
+            submission(9, 2_000, 1),
+        ];
+
+        let (winners, excluded, participants) =
+            rank_submissions(&config(10_000, 50), 1_000, &submissions, |_| None);
+
+        assert_eq!(ranked_uids(&winners), vec![(1, 5), (2, 3), (3, 7), (4, 9)]);
+        assert_eq!(winners[1].deviation_meters, 100);
+        assert!(excluded.is_empty());
+        assert_eq!(participants, 4);
+    }
+
+    #[test]
+    fn should_keep_the_latest_submission_of_each_rider() {
+        let submissions = vec![
+            submission(1, 1_000, 10),
+            submission(1, 4_000, 20),
+            submission(2, 3_000, 15),
+        ];
+
+        let (winners, _, participants) =
+            rank_submissions(&config(10_000, 50), 1_000, &submissions, |_| None);
+
+        assert_eq!(ranked_uids(&winners), vec![(1, 2), (2, 1)]);
+        assert_eq!(winners[1].distance_meters, 4_000);
+        assert_eq!(participants, 2);
+    }
+
+    #[test]
+    fn should_exclude_invalid_submissions() {
+        let too_fast = DistanceSubmission {
+            duration_seconds: 60,
+            ..submission(2, 1_000, 1)
+        };
+        let submissions = vec![submission(1, 600_000, 1), too_fast, submission(3, 1_500, 1)];
+
+        let (winners, excluded, participants) =
+            rank_submissions(&config(10_000, 50), 1_000, &submissions, |_| None);
+
+        assert_eq!(ranked_uids(&winners), vec![(1, 3)]);
+        let reasons: Vec<_> = excluded.iter().map(|e| (e.uid, e.reason)).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (1, ExclusionReason::InvalidDistance),
+                (2, ExclusionReason::SpeedExceeded)
+            ]
+        );
+        assert_eq!(participants, 3);
+    }
+
+    #[test]
+    fn should_fill_winner_slots_past_capped_riders() {
+        let submissions: Vec<_> = (1..=20)
+            .map(|uid| submission(uid, 1_000 + uid * 10, 1))
+            .collect();
+
+        // 10% of 20 riders win
+        let (winners, excluded, _) =
+            rank_submissions(&config(1_000, 50), 1_000, &submissions, |uid| {
+                (uid == 1).then_some(ExclusionReason::ConsecutiveWinPrevented)
+            });
+
+        assert_eq!(ranked_uids(&winners), vec![(1, 2), (2, 3)]);
+        assert_eq!(excluded.len(), 1);
+        assert_eq!(excluded[0].reason, ExclusionReason::ConsecutiveWinPrevented);
+
+        // At least one winner, at most `max_winners`
+        let (winners, _, _) = rank_submissions(&config(1, 50), 1_000, &submissions, |_| None);
+        assert_eq!(winners.len(), 1);
+        let (winners, _, _) = rank_submissions(&config(10_000, 5), 1_000, &submissions, |_| None);
+        assert_eq!(winners.len(), 5);
+    }
+
+    #[test]
+    fn should_cap_daily_wins_through_the_rider_history() {
+        let config = CompetitionConfig {
+            max_daily_wins: 2,
+            ..CompetitionConfig::default()
+        };
+        let winner = DistanceWinner {
+            uid: 4,
+            distance_meters: 1_000,
+            deviation_meters: 0,
+            rank: 1,
+        };
+        let morning = 20_000 * SECONDS_PER_DAY + 3_600;
+
+        assert!(daily_cap_results(&config, 4, morning).is_empty());
+        record_wins(std::slice::from_ref(&winner), morning);
+        record_wins(std::slice::from_ref(&winner), morning + 600);
+
+        let results = daily_cap_results(&config, 4, morning + 1_200);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].rule, "max_wins_per_day");
+        assert_eq!(rules::rider_history(4).unwrap().wins_on(20_000), 2);
+
+        // The count starts over on the next UTC day
+        assert!(daily_cap_results(&config, 4, 20_001 * SECONDS_PER_DAY).is_empty());
+    }
+}
@@ -1,7 +1,44 @@
 use candid::{CandidType, Deserialize};
 use ic_cdk_macros::*;
-use sha2::{Sha256, Digest};
-use std::collections::HashMap;
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::DefaultMemoryImpl;
+use canonical_hash::WinnerLeaf;
+use rules::{RiderHistory, RuleContext, RuleResult};
+use stable_schema::{SchemaRegistry, Versioned};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+
+mod competition;
+mod rules;
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+// Memories 0-5 belong to the competition and rules modules
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> =
+        RefCell::new(SchemaRegistry::init(memory(6)));
+}
+
+fn memory(id: u8) -> Memory {
+    MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(id)))
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    competition::migrate_stable_schema();
+    rules::migrate_stable_schema();
+}
+
+// Rewrites `collection` once per schema version bump of `T`
+fn migrate_collection<T: Versioned>(collection: &str, rewrite: impl FnOnce() -> u64) {
+    let migrated = SCHEMA_REGISTRY.with(|registry| registry.borrow_mut().migrate::<T>(collection, rewrite));
+    if let Some(count) = migrated {
+        ic_cdk::println!("Migrated {} {} to schema v{}", count, collection, T::VERSION);
+    }
+}
 
 #[derive(CandidType, Deserialize)]
 pub struct BatchValidationRequest {
@@ -31,6 +68,8 @@ pub struct IntervalResult {
     pub merkle_root: String,
     pub cluster_winners: Vec<ClusterWinner>,
     pub valid_submissions: u32,
+    /// Non-zero anti-gaming rule scores of the interval's riders
+    pub rule_results: Vec<RuleResult>,
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -42,80 +81,238 @@ pub struct ClusterWinner {
 
 #[query]
 pub fn validate_batch(request: BatchValidationRequest) -> BatchValidationResult {
-    // Basic validation
+    evaluate_batch(&request, &mut BTreeMap::new())
+}
+
+/// Same as `validate_batch`, but records the rider history and the interval results so that
+/// the anti-gaming rules take them into account in later intervals.
+#[update]
+pub fn finalize_batch(request: BatchValidationRequest) -> Result<BatchValidationResult, String> {
+    competition::require_worker()?;
+    check_new_intervals(&request.interval_ids)?;
+    let mut histories = BTreeMap::new();
+    let result = evaluate_batch(&request, &mut histories);
+
+    for (uid, history) in histories {
+        rules::save_rider_history(uid, history);
+    }
+    for interval_result in &result.results {
+        rules::save_interval_result(interval_result.clone());
+    }
+    Ok(result)
+}
+
+// A finalized interval already counted towards the rider history; finalizing it again would
+// count its wins twice
+fn check_new_intervals(interval_ids: &[u64]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for &interval_id in interval_ids {
+        if !seen.insert(interval_id) {
+            return Err(format!("Interval {} appears more than once", interval_id));
+        }
+        if rules::interval_result(interval_id).is_some() {
+            return Err(format!("Interval {} is already finalized", interval_id));
+        }
+    }
+    Ok(())
+}
+
+// `histories` holds the rider history updated by the intervals already evaluated in this
+// batch, on top of the stored history
+fn evaluate_batch(
+    request: &BatchValidationRequest,
+    histories: &mut BTreeMap<u32, RiderHistory>,
+) -> BatchValidationResult {
+    let settings = rules::rule_settings();
+    let active_rules = rules::build_rules(&settings.rules);
     let mut all_winners = Vec::new();
-    let mut total_valid = 0u32;
-    
-    for (_interval_idx, submissions) in request.submissions_batch.iter().enumerate() {
+    let mut results = Vec::new();
+
+    for (&interval_id, submissions) in request.interval_ids.iter().zip(&request.submissions_batch) {
         // Validate each submission
         let valid_submissions: Vec<_> = submissions
             .iter()
             .filter(|s| is_valid_location(s.lat, s.lon))
             .collect();
-        
-        total_valid += valid_submissions.len() as u32;
-        
-        // Simple clustering - group by approximate location
-        let mut location_groups: HashMap<(i32, i32), Vec<&CompactSubmission>> = HashMap::new();
-        
+
+        // Score every rider against the anti-gaming rules
+        let mut tracks: BTreeMap<u32, Vec<&CompactSubmission>> = BTreeMap::new();
+        for submission in &valid_submissions {
+            tracks.entry(submission.uid).or_default().push(submission);
+        }
+        let mut rule_results = Vec::new();
+        let mut rejected = HashSet::new();
+        for (&uid, track) in tracks.iter_mut() {
+            track.sort_by_key(|s| s.t);
+            let history = histories.get(&uid).cloned().or_else(|| rules::rider_history(uid));
+            let context = RuleContext {
+                uid,
+                track: track.as_slice(),
+                history: history.as_ref(),
+            };
+            let scores = rules::evaluate_rules(&active_rules, &context);
+            if scores.iter().map(|r| r.score).sum::<u32>() >= settings.rejection_score {
+                rejected.insert(uid);
+            }
+            rule_results.extend(scores);
+        }
+
+        // Simple clustering - group by approximate location. Ordered by cluster center so the
+        // winners, and so the merkle root, come out in the same order on every replica
+        let mut location_groups: BTreeMap<(i32, i32), Vec<&CompactSubmission>> = BTreeMap::new();
+
         for submission in &valid_submissions {
             // Grid-based clustering (1km precision)
             let grid_lat = submission.lat / 1000;
             let grid_lon = submission.lon / 1000;
             location_groups.entry((grid_lat, grid_lon)).or_default().push(submission);
         }
-        
-        // Select winners from each cluster
+
+        // Select winners from each cluster, among the riders the rules did not reject
+        let mut winners = Vec::new();
         for ((grid_lat, grid_lon), group) in location_groups {
-            if group.len() >= 1 {  // Minimum 2 people per cluster
-                let winner_idx = (grid_lat + grid_lon) as usize % group.len();
-                let winner = group[winner_idx];
-                
-                all_winners.push(ClusterWinner {
-                    uid: winner.uid,
-                    cluster_center: (grid_lat, grid_lon),
-                    participants: group.len() as u8,
-                    });
-                }
+            let eligible: Vec<_> = group.iter().filter(|s| !rejected.contains(&s.uid)).collect();
+            if eligible.is_empty() {
+                continue;
             }
-      }
-        let merkle_root = compute_merkle_root(&all_winners); 
-           
-        let interval_results: Vec<IntervalResult> = request.interval_ids.iter().map(|&interval_id| {
-             IntervalResult {
-                valid: !all_winners.is_empty(),
-                interval_id,
-                merkle_root: merkle_root.clone(),
-                cluster_winners: all_winners.clone(),
-                valid_submissions: total_valid,
-                }
-          }).collect();
-          
-    
-    
+            let winner_idx = (grid_lat + grid_lon) as usize % eligible.len();
+            let winner = eligible[winner_idx];
+
+            winners.push(ClusterWinner {
+                uid: winner.uid,
+                cluster_center: (grid_lat, grid_lon),
+                participants: group.len() as u8,
+            });
+        }
+
+        let winner_uids: HashSet<u32> = winners.iter().map(|w| w.uid).collect();
+        for (uid, track) in &tracks {
+            histories
+                .entry(*uid)
+                .or_insert_with(|| rules::rider_history(*uid).unwrap_or_default())
+                .record(track, winner_uids.contains(uid));
+        }
+
+        results.push(IntervalResult {
+            valid: !winners.is_empty(),
+            interval_id,
+            merkle_root: compute_merkle_root(&winners),
+            cluster_winners: winners.clone(),
+            valid_submissions: valid_submissions.len() as u32,
+            rule_results,
+        });
+        all_winners.extend(winners);
+    }
+
     BatchValidationResult {
-        results: interval_results,
-        batch_merkle_root: merkle_root,
+        results,
+        batch_merkle_root: compute_merkle_root(&all_winners),
     }
 }
 
 fn is_valid_location(lat: i32, lon: i32) -> bool {
     // Lat/lon are in microdegrees (-90 to 90, -180 to 180)
-    lat >= -90_000_000 && lat <= 90_000_000 && 
-    lon >= -180_000_000 && lon <= 180_000_000
+    (-90_000_000..=90_000_000).contains(&lat) && (-180_000_000..=180_000_000).contains(&lon)
 }
 
 fn compute_merkle_root(winners: &[ClusterWinner]) -> String {
-    if winners.is_empty() {
-        return "empty".to_string();
+    // Canonical encoding shared with the edge servers, see canonical_hash/test_vectors.json
+    let leaves: Vec<WinnerLeaf> = winners
+        .iter()
+        .map(|winner| WinnerLeaf {
+            uid: winner.uid,
+            lat: winner.cluster_center.0,
+            lon: winner.cluster_center.1,
+            participants: winner.participants,
+        })
+        .collect();
+    canonical_hash::cluster_winners_hash(&leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interval_result(interval_id: u64) -> IntervalResult {
+        IntervalResult {
+            valid: true,
+            interval_id,
+            merkle_root: String::new(),
+            cluster_winners: Vec::new(),
+            valid_submissions: 0,
+            rule_results: Vec::new(),
+        }
     }
-    
-    let mut hasher = Sha256::new();
-    for winner in winners {
-        hasher.update(&winner.uid.to_string());
-        hasher.update(&winner.cluster_center.0.to_be_bytes());
-        hasher.update(&winner.cluster_center.1.to_be_bytes());
+
+    #[test]
+    fn should_finalize_each_interval_once() {
+        assert!(check_new_intervals(&[1, 2]).is_ok());
+        assert!(check_new_intervals(&[1, 2, 1]).is_err());
+
+        rules::save_interval_result(interval_result(2));
+        assert_eq!(
+            check_new_intervals(&[1, 2]),
+            Err("Interval 2 is already finalized".to_string())
+        );
+        assert!(check_new_intervals(&[3]).is_ok());
+    }
+
+    fn submission(uid: u32, lat: i32, lon: i32) -> CompactSubmission {
+        CompactSubmission { uid, lat, lon, t: 1_000 }
+    }
+
+    #[test]
+    fn should_order_winners_by_cluster_center() {
+        // Four clusters, listed out of order
+        let submissions = vec![
+            submission(7, 47_376_887, 8_541_694),
+            submission(3, -33_868_820, -151_209_295),
+            submission(9, 47_376_100, 8_541_200),
+            submission(5, 40_712_776, -74_005_974),
+            submission(1, 51_507_351, -127_758),
+        ];
+        let request = BatchValidationRequest {
+            interval_ids: vec![42],
+            submissions_batch: vec![submissions],
+            signature: String::new(),
+        };
+
+        let result = evaluate_batch(&request, &mut BTreeMap::new());
+        let interval = &result.results[0];
+        let centers: Vec<_> = interval.cluster_winners.iter().map(|w| w.cluster_center).collect();
+        assert_eq!(
+            centers,
+            vec![(-33_868, -151_209), (40_712, -74_005), (47_376, 8_541), (51_507, -127)]
+        );
+        let uids: Vec<_> = interval.cluster_winners.iter().map(|w| w.uid).collect();
+        assert_eq!(uids, vec![3, 5, 9, 1]);
+        assert_eq!(
+            interval.merkle_root,
+            "dca3b5451e46a74229ee57674e3fb272c35b628e9313e4885a6f5a0fde5d26c6"
+        );
+        assert_eq!(result.batch_merkle_root, interval.merkle_root);
+    }
+
+    #[test]
+    fn should_read_values_stored_as_raw_candid() {
+        let mut stored = interval_result(7);
+        stored.merkle_root = "ab".repeat(32);
+        let bytes = candid::encode_one(&stored).unwrap();
+
+        let decoded: IntervalResult = stable_schema::decode(&bytes).unwrap();
+        assert_eq!(decoded.interval_id, 7);
+        assert_eq!(decoded.merkle_root, stored.merkle_root);
+    }
+
+    #[test]
+    fn should_rewrite_the_settings_cells_once_on_upgrade() {
+        let version_of = |collection: &str| SCHEMA_REGISTRY.with(|registry| registry.borrow().version_of(collection));
+        assert_eq!(version_of("rule_settings"), None);
+
+        post_upgrade();
+
+        assert_eq!(version_of("rule_settings"), Some(1));
+        assert_eq!(version_of("competition_state"), Some(1));
+        assert_eq!(version_of("interval_results"), None);
     }
-    
-    hex::encode(hasher.finalize())
 }
@@ -0,0 +1,590 @@
+// Anti-gaming rule engine
+//
+// Enforces on-chain what the dashboard views (`suspicious_activity`,
+// `consecutive_win_attempts`, `user_win_rates`) only report. Every configured rule scores each
+// rider of an interval from 0 (clean) to 100; riders whose total score reaches
+// `rejection_score` cannot win a cluster. Non-zero scores are kept, with their reason, in the
+// `IntervalResult`.
+
+use crate::{memory, CompactSubmission, IntervalResult, Memory};
+use candid::{CandidType, Deserialize};
+use ic_cdk_macros::*;
+use ic_stable_structures::{StableBTreeMap, StableCell};
+use std::cell::RefCell;
+
+pub const MAX_SCORE: u32 = 100;
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+// ============= TYPES =============
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum RuleConfig {
+    /// Wins allowed per UTC day (worker: 38)
+    MaxWinsPerDay { max_wins: u32 },
+    /// Seconds a winner has to wait before winning again (worker: one interval)
+    WinCooldown { seconds: u64 },
+    /// Fastest plausible speed between two points of the same interval
+    SpeedOutlier { max_speed_kmh: u32 },
+    /// Fastest plausible speed from the rider's last known position to this interval
+    Teleport { max_speed_kmh: u32 },
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct RuleSettings {
+    pub rules: Vec<RuleConfig>,
+    pub rejection_score: u32,
+}
+
+impl Default for RuleSettings {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                RuleConfig::MaxWinsPerDay { max_wins: 38 },
+                RuleConfig::WinCooldown { seconds: 300 },
+                RuleConfig::SpeedOutlier { max_speed_kmh: 50 },
+                RuleConfig::Teleport { max_speed_kmh: 120 },
+            ],
+            rejection_score: MAX_SCORE,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, PartialEq, Debug)]
+pub struct RuleResult {
+    pub uid: u32,
+    pub rule: String,
+    pub score: u32,
+    pub reason: String,
+}
+
+/// What the rules know about a rider from earlier intervals.
+#[derive(CandidType, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct RiderHistory {
+    pub last_lat: i32,
+    pub last_lon: i32,
+    pub last_t: u64,
+    pub last_win_t: Option<u64>,
+    pub win_day: u64,
+    pub wins_today: u32,
+}
+
+impl RiderHistory {
+    pub fn wins_on(&self, day: u64) -> u32 {
+        if self.win_day == day {
+            self.wins_today
+        } else {
+            0
+        }
+    }
+
+    /// Moves the rider to the last point of `track` and counts a win at that time if `won`.
+    pub fn record(&mut self, track: &[&CompactSubmission], won: bool) {
+        let Some(last) = track.last() else {
+            return;
+        };
+        self.last_lat = last.lat;
+        self.last_lon = last.lon;
+        self.last_t = last.t;
+        if won {
+            self.record_win(last.t);
+        }
+    }
+
+    /// Counts a win at `t` (seconds) without moving the rider.
+    pub fn record_win(&mut self, t: u64) {
+        let day = t / SECONDS_PER_DAY;
+        self.wins_today = self.wins_on(day) + 1;
+        self.win_day = day;
+        self.last_win_t = Some(t);
+    }
+}
+
+/// The submissions of one rider in one interval, in time order, and their history.
+pub struct RuleContext<'a> {
+    pub uid: u32,
+    pub track: &'a [&'a CompactSubmission],
+    pub history: Option<&'a RiderHistory>,
+}
+
+pub trait Rule {
+    fn name(&self) -> &'static str;
+
+    /// Returns a score in `1..=MAX_SCORE` and the reason, or `None` if the rider looks clean.
+    fn evaluate(&self, ctx: &RuleContext) -> Option<(u32, String)>;
+}
+
+// ============= RULES =============
+
+struct MaxWinsPerDay {
+    max_wins: u32,
+}
+
+impl Rule for MaxWinsPerDay {
+    fn name(&self) -> &'static str {
+        "max_wins_per_day"
+    }
+
+    fn evaluate(&self, ctx: &RuleContext) -> Option<(u32, String)> {
+        let day = ctx.track.last()?.t / SECONDS_PER_DAY;
+        let wins = ctx.history?.wins_on(day);
+        (wins >= self.max_wins).then(|| {
+            (
+                MAX_SCORE,
+                format!("{} wins today, limit is {}", wins, self.max_wins),
+            )
+        })
+    }
+}
+
+struct WinCooldown {
+    seconds: u64,
+}
+
+impl Rule for WinCooldown {
+    fn name(&self) -> &'static str {
+        "win_cooldown"
+    }
+
+    fn evaluate(&self, ctx: &RuleContext) -> Option<(u32, String)> {
+        let last_win = ctx.history?.last_win_t?;
+        let elapsed = ctx.track.first()?.t.saturating_sub(last_win);
+        (elapsed < self.seconds).then(|| {
+            (
+                MAX_SCORE,
+                format!("won {}s ago, cooldown is {}s", elapsed, self.seconds),
+            )
+        })
+    }
+}
+
+struct SpeedOutlier {
+    max_speed_kmh: u32,
+}
+
+impl Rule for SpeedOutlier {
+    fn name(&self) -> &'static str {
+        "speed_outlier"
+    }
+
+    fn evaluate(&self, ctx: &RuleContext) -> Option<(u32, String)> {
+        let fastest = ctx
+            .track
+            .windows(2)
+            .map(|pair| speed_kmh(pair[0], pair[1]))
+            .fold(0.0, f64::max);
+        over_limit(fastest, self.max_speed_kmh).map(|score| {
+            (
+                score,
+                format!(
+                    "moved at {:.0} km/h within the interval, limit is {} km/h",
+                    fastest, self.max_speed_kmh
+                ),
+            )
+        })
+    }
+}
+
+struct Teleport {
+    max_speed_kmh: u32,
+}
+
+impl Rule for Teleport {
+    fn name(&self) -> &'static str {
+        "teleport"
+    }
+
+    fn evaluate(&self, ctx: &RuleContext) -> Option<(u32, String)> {
+        let history = ctx.history?;
+        let first = ctx.track.first()?;
+        let last_seen = CompactSubmission {
+            uid: ctx.uid,
+            lat: history.last_lat,
+            lon: history.last_lon,
+            t: history.last_t,
+        };
+        let speed = speed_kmh(&last_seen, first);
+        over_limit(speed, self.max_speed_kmh).map(|score| {
+            (
+                score,
+                format!(
+                    "jumped from the last interval at {:.0} km/h, limit is {} km/h",
+                    speed, self.max_speed_kmh
+                ),
+            )
+        })
+    }
+}
+
+pub fn build_rules(configs: &[RuleConfig]) -> Vec<Box<dyn Rule>> {
+    configs
+        .iter()
+        .map(|config| -> Box<dyn Rule> {
+            match *config {
+                RuleConfig::MaxWinsPerDay { max_wins } => Box::new(MaxWinsPerDay { max_wins }),
+                RuleConfig::WinCooldown { seconds } => Box::new(WinCooldown { seconds }),
+                RuleConfig::SpeedOutlier { max_speed_kmh } => {
+                    Box::new(SpeedOutlier { max_speed_kmh })
+                }
+                RuleConfig::Teleport { max_speed_kmh } => Box::new(Teleport { max_speed_kmh }),
+            }
+        })
+        .collect()
+}
+
+/// Runs every rule against a rider and returns the non-zero results.
+pub fn evaluate_rules(rules: &[Box<dyn Rule>], ctx: &RuleContext) -> Vec<RuleResult> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            rule.evaluate(ctx).map(|(score, reason)| RuleResult {
+                uid: ctx.uid,
+                rule: rule.name().to_string(),
+                score: score.min(MAX_SCORE),
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// Score for exceeding `limit`: half at the limit, full at twice the limit.
+fn over_limit(observed: f64, limit: u32) -> Option<u32> {
+    let limit = limit as f64;
+    if observed <= limit {
+        return None;
+    }
+    let excess = ((observed - limit) / limit).min(1.0);
+    Some((MAX_SCORE as f64 * (0.5 + excess / 2.0)).round() as u32)
+}
+
+/// Great-circle speed between two submissions (microdegrees, seconds).
+fn speed_kmh(from: &CompactSubmission, to: &CompactSubmission) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6_371.0;
+    let to_radians = |microdegrees: i32| (microdegrees as f64 / 1_000_000.0).to_radians();
+    let (lat1, lat2) = (to_radians(from.lat), to_radians(to.lat));
+    let d_lat = lat2 - lat1;
+    let d_lon = to_radians(to.lon) - to_radians(from.lon);
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    let km = 2.0 * EARTH_RADIUS_KM * a.sqrt().asin();
+    // Points in the same second count as one second apart
+    let hours = to.t.abs_diff(from.t).max(1) as f64 / 3_600.0;
+    km / hours
+}
+
+// ============= STORAGE =============
+
+thread_local! {
+    static SETTINGS: RefCell<StableCell<RuleSettings, Memory>> = RefCell::new(
+        StableCell::init(memory(3), RuleSettings::default())
+            .expect("failed to initialize the rule settings")
+    );
+
+    static RIDER_HISTORY: RefCell<StableBTreeMap<u32, RiderHistory, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(4)));
+
+    static INTERVAL_RESULTS: RefCell<StableBTreeMap<u64, IntervalResult, Memory>> =
+        RefCell::new(StableBTreeMap::init(memory(5)));
+}
+
+stable_schema::versioned_since_raw_candid!(RuleSettings, "validator::RuleSettings");
+stable_schema::versioned_since_raw_candid!(RiderHistory, "validator::RiderHistory");
+stable_schema::versioned_since_raw_candid!(IntervalResult, "validator::IntervalResult");
+
+// Rider histories and interval results grow with every rider and interval, so only the
+// settings are rewritten; the maps are migrated on read
+pub fn migrate_stable_schema() {
+    crate::migrate_collection::<RuleSettings>("rule_settings", || {
+        SETTINGS.with(|s| stable_schema::rewrite_cell(&mut s.borrow_mut()))
+    });
+}
+
+pub fn rule_settings() -> RuleSettings {
+    SETTINGS.with(|s| s.borrow().get().clone())
+}
+
+pub fn rider_history(uid: u32) -> Option<RiderHistory> {
+    RIDER_HISTORY.with(|h| h.borrow().get(&uid))
+}
+
+pub fn save_rider_history(uid: u32, history: RiderHistory) {
+    RIDER_HISTORY.with(|h| h.borrow_mut().insert(uid, history));
+}
+
+pub fn interval_result(interval_id: u64) -> Option<IntervalResult> {
+    INTERVAL_RESULTS.with(|r| r.borrow().get(&interval_id))
+}
+
+pub fn save_interval_result(result: IntervalResult) {
+    INTERVAL_RESULTS.with(|r| r.borrow_mut().insert(result.interval_id, result));
+}
+
+// ============= API =============
+
+#[query]
+pub fn get_rule_settings() -> RuleSettings {
+    rule_settings()
+}
+
+#[update]
+pub fn set_rule_settings(settings: RuleSettings) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::api::msg_caller()) {
+        return Err("Only controllers can configure anti-gaming rules".to_string());
+    }
+    if settings.rejection_score == 0 {
+        return Err("Rejection score must be positive".to_string());
+    }
+    SETTINGS.with(|s| {
+        s.borrow_mut()
+            .set(settings)
+            .map(|_| ())
+            .map_err(|e| format!("Failed to store rule settings: {:?}", e))
+    })
+}
+
+#[query]
+pub fn get_rider_history(uid: u32) -> Option<RiderHistory> {
+    rider_history(uid)
+}
+
+#[query]
+pub fn get_interval_result(interval_id: u64) -> Option<IntervalResult> {
+    interval_result(interval_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluate_batch, BatchValidationRequest};
+    use std::collections::BTreeMap;
+
+    const DAY: u64 = 20_000;
+    const T0: u64 = DAY * SECONDS_PER_DAY + 3_600;
+    // 0.01° of latitude, about 1.112 km
+    const STEP: i32 = 10_000;
+
+    fn point(uid: u32, lat: i32, t: u64) -> CompactSubmission {
+        CompactSubmission {
+            uid,
+            lat,
+            lon: 8_000_000,
+            t,
+        }
+    }
+
+    fn history(
+        last_lat: i32,
+        last_t: u64,
+        last_win_t: Option<u64>,
+        wins_today: u32,
+    ) -> RiderHistory {
+        RiderHistory {
+            last_lat,
+            last_lon: 8_000_000,
+            last_t,
+            last_win_t,
+            win_day: DAY,
+            wins_today,
+        }
+    }
+
+    fn evaluate(
+        config: RuleConfig,
+        track: &[CompactSubmission],
+        history: Option<&RiderHistory>,
+    ) -> Option<u32> {
+        let track: Vec<&CompactSubmission> = track.iter().collect();
+        let context = RuleContext {
+            uid: 1,
+            track: &track,
+            history,
+        };
+        let results = evaluate_rules(&build_rules(&[config]), &context);
+        assert!(results.len() <= 1);
+        results.first().map(|result| result.score)
+    }
+
+    #[test]
+    fn should_measure_great_circle_speed() {
+        let speed = speed_kmh(
+            &point(1, 46_000_000, T0),
+            &point(1, 46_000_000 + STEP, T0 + 60),
+        );
+        assert!((speed - 66.717).abs() < 0.01, "{}", speed);
+
+        // Same point, and two points in the same second
+        assert_eq!(speed_kmh(&point(1, 0, T0), &point(1, 0, T0 + 60)), 0.0);
+        let same_second = speed_kmh(&point(1, 0, T0), &point(1, STEP, T0));
+        assert!(
+            (same_second - 1.112 * 3_600.0).abs() < 1.0,
+            "{}",
+            same_second
+        );
+    }
+
+    #[test]
+    fn should_score_half_at_the_limit_and_full_at_twice_the_limit() {
+        assert_eq!(over_limit(50.0, 50), None);
+        assert_eq!(over_limit(50.5, 50), Some(51));
+        assert_eq!(over_limit(75.0, 50), Some(75));
+        assert_eq!(over_limit(100.0, 50), Some(MAX_SCORE));
+        assert_eq!(over_limit(1_000.0, 50), Some(MAX_SCORE));
+    }
+
+    #[test]
+    fn should_cap_wins_per_day() {
+        let rule = || RuleConfig::MaxWinsPerDay { max_wins: 2 };
+        let track = [point(1, 0, T0)];
+
+        assert_eq!(evaluate(rule(), &track, None), None);
+        assert_eq!(
+            evaluate(rule(), &track, Some(&history(0, T0, Some(T0), 1))),
+            None
+        );
+        assert_eq!(
+            evaluate(rule(), &track, Some(&history(0, T0, Some(T0), 2))),
+            Some(MAX_SCORE)
+        );
+
+        // Wins of an earlier day do not count
+        let tomorrow = [point(1, 0, T0 + SECONDS_PER_DAY)];
+        assert_eq!(
+            evaluate(rule(), &tomorrow, Some(&history(0, T0, Some(T0), 2))),
+            None
+        );
+    }
+
+    #[test]
+    fn should_enforce_the_cooldown_between_wins() {
+        let rule = || RuleConfig::WinCooldown { seconds: 300 };
+        let won = history(0, T0, Some(T0), 1);
+
+        assert_eq!(
+            evaluate(rule(), &[point(1, 0, T0 + 299)], Some(&won)),
+            Some(MAX_SCORE)
+        );
+        assert_eq!(evaluate(rule(), &[point(1, 0, T0 + 300)], Some(&won)), None);
+        assert_eq!(
+            evaluate(
+                rule(),
+                &[point(1, 0, T0 + 1)],
+                Some(&history(0, T0, None, 0))
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn should_flag_speed_outliers_within_an_interval() {
+        let rule = || RuleConfig::SpeedOutlier { max_speed_kmh: 50 };
+
+        // 66.7 km/h
+        let fast = [
+            point(1, 0, T0),
+            point(1, STEP, T0 + 60),
+            point(1, STEP, T0 + 120),
+        ];
+        assert_eq!(evaluate(rule(), &fast, None), Some(67));
+        // 33.4 km/h
+        let slow = [point(1, 0, T0), point(1, STEP, T0 + 120)];
+        assert_eq!(evaluate(rule(), &slow, None), None);
+        assert_eq!(evaluate(rule(), &[point(1, 0, T0)], None), None);
+    }
+
+    #[test]
+    fn should_flag_teleports_from_the_last_known_position() {
+        let rule = || RuleConfig::Teleport { max_speed_kmh: 120 };
+        let last_seen = history(0, T0, None, 0);
+
+        // 133 km/h from the last position
+        assert_eq!(
+            evaluate(rule(), &[point(1, STEP, T0 + 30)], Some(&last_seen)),
+            Some(56)
+        );
+        assert_eq!(
+            evaluate(rule(), &[point(1, STEP, T0 + 60)], Some(&last_seen)),
+            None
+        );
+        assert_eq!(evaluate(rule(), &[point(1, STEP, T0 + 30)], None), None);
+    }
+
+    #[test]
+    fn should_report_only_rules_that_fire() {
+        let rules = build_rules(&RuleSettings::default().rules);
+        let track = [point(7, 0, T0 + 60)];
+        let track: Vec<&CompactSubmission> = track.iter().collect();
+        let won = history(0, T0, Some(T0), 1);
+
+        let results = evaluate_rules(
+            &rules,
+            &RuleContext {
+                uid: 7,
+                track: &track,
+                history: Some(&won),
+            },
+        );
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].uid, 7);
+        assert_eq!(results[0].rule, "win_cooldown");
+        assert_eq!(results[0].reason, "won 60s ago, cooldown is 300s");
+    }
+
+    #[test]
+    fn should_count_wins_per_utc_day() {
+        let mut history = RiderHistory::default();
+        let before_midnight = (DAY + 1) * SECONDS_PER_DAY - 1;
+
+        history.record(&[&point(1, 0, T0)], true);
+        history.record(&[&point(1, STEP, before_midnight)], true);
+        assert_eq!(history.wins_on(DAY), 2);
+        assert_eq!(history.last_win_t, Some(before_midnight));
+
+        // Moving without winning keeps the count
+        history.record(&[&point(1, 2 * STEP, before_midnight + 1)], false);
+        assert_eq!(history.last_lat, 2 * STEP);
+        assert_eq!(history.last_t, before_midnight + 1);
+        assert_eq!(history.wins_on(DAY), 2);
+        assert_eq!(history.wins_on(DAY + 1), 0);
+
+        history.record(&[&point(1, 2 * STEP, before_midnight + 2)], true);
+        assert_eq!(history.wins_on(DAY), 0);
+        assert_eq!(history.wins_on(DAY + 1), 1);
+
+        // An empty track changes nothing
+        let unchanged = history.clone();
+        history.record(&[], true);
+        assert_eq!(history, unchanged);
+    }
+
+    #[test]
+    fn should_reject_riders_at_the_rejection_score() {
+        let request = || BatchValidationRequest {
+            interval_ids: vec![1],
+            submissions_batch: vec![vec![
+                point(1, 46_000_000, T0),
+                point(1, 46_000_000 + STEP, T0 + 60),
+                point(2, 45_000_000, T0),
+            ]],
+            signature: String::new(),
+        };
+        let winners = |rejection_score| {
+            SETTINGS
+                .with(|s| {
+                    s.borrow_mut().set(RuleSettings {
+                        rules: vec![RuleConfig::SpeedOutlier { max_speed_kmh: 50 }],
+                        rejection_score,
+                    })
+                })
+                .unwrap();
+            let result = evaluate_batch(&request(), &mut BTreeMap::new());
+            let interval = &result.results[0];
+            assert_eq!(interval.rule_results.len(), 1);
+            assert_eq!(interval.rule_results[0].score, 67);
+            let mut uids: Vec<u32> = interval.cluster_winners.iter().map(|w| w.uid).collect();
+            uids.sort();
+            uids
+        };
+
+        assert_eq!(winners(100), vec![1, 1, 2]);
+        assert_eq!(winners(67), vec![2]);
+    }
+}
@@ -0,0 +1,44 @@
+// Caller and clock of the current message
+//
+// The IC system API traps outside a canister, so unit tests swap both for values
+// they set through `env::testing`.
+
+#[cfg(not(test))]
+pub fn caller() -> candid::Principal {
+    ic_cdk::api::msg_caller()
+}
+
+#[cfg(not(test))]
+pub fn time() -> u64 {
+    ic_cdk::api::time()
+}
+
+#[cfg(test)]
+pub use testing::{caller, time};
+
+#[cfg(test)]
+pub mod testing {
+    use candid::Principal;
+    use std::cell::Cell;
+
+    thread_local! {
+        static CALLER: Cell<Principal> = const { Cell::new(Principal::anonymous()) };
+        static TIME: Cell<u64> = const { Cell::new(0) };
+    }
+
+    pub fn caller() -> Principal {
+        CALLER.with(Cell::get)
+    }
+
+    pub fn time() -> u64 {
+        TIME.with(Cell::get)
+    }
+
+    pub fn set_caller(caller: Principal) {
+        CALLER.with(|c| c.set(caller));
+    }
+
+    pub fn set_time(time: u64) {
+        TIME.with(|t| t.set(time));
+    }
+}
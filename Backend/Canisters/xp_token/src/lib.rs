@@ -2,13 +2,25 @@
 // 1 XP = 1 KM traveled
 // Located at: Backend/Canisters/xp_token/src/lib.rs
 
-use candid::{CandidType, Deserialize, Principal};
+use candid::{CandidType, Deserialize, Int, Nat, Principal};
 use ic_cdk_macros::*;
-use ic_cdk::api::time;
+use ic_stable_structures::{
+    memory_manager::{MemoryId, MemoryManager, VirtualMemory},
+    DefaultMemoryImpl, StableBTreeMap, StableCell, StableLog,
+};
+use stable_schema::{rewrite_cell, SchemaError, SchemaRegistry, Versioned};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use serde::Serialize;
 
+mod env;
+#[cfg(test)]
+mod tests;
+
+use env::{caller, time};
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
 // ============= DATA STRUCTURES =============
 
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
@@ -27,6 +39,7 @@ pub struct MintEvent {
     pub timestamp: u64,
     pub data_hash: String,
     pub minted_by: Principal,
+    pub batch_id: Option<u64>,
 }
 
 #[derive(CandidType, Deserialize, Serialize)]
@@ -45,6 +58,30 @@ pub struct BatchMintRequest {
     pub timestamp: u64,
 }
 
+#[derive(CandidType, Deserialize, Clone)]
+pub enum MintError {
+    Unauthorized,
+    InvalidRequest(String),
+    // The same data_hash or an overlapping period was already minted
+    AlreadyMinted {
+        reason: String,
+        event_index: u64,
+        original: Box<MintEvent>,
+    },
+    // Batch-level failures, by entry index
+    BatchRejected(Vec<(u32, MintError)>),
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub struct BatchRecord {
+    pub batch_id: u64,
+    pub batch_hash: String,
+    pub minted_by: Principal,
+    pub entries: u32,
+    pub total_minted: u64,
+    pub timestamp: u64,
+}
+
 #[derive(CandidType, Deserialize, Serialize)]
 pub struct XPStats {
     pub total_supply: u64,
@@ -53,6 +90,157 @@ pub struct XPStats {
     pub average_balance: u64,
 }
 
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq)]
+pub enum BadgeCriterion {
+    // Lifetime XP in smallest units (100_000 = 100 KM)
+    TotalXp(u64),
+    // Consecutive days covered by minted periods
+    StreakDays(u32),
+    // Granted by an authorized minter, e.g. from off-chain cluster rankings
+    Awarded,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct BadgeDefinition {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub criterion: BadgeCriterion,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AchievementConfig {
+    // XP (smallest units) needed to reach level i + 1; starts at 0 and strictly increases
+    pub level_thresholds: Vec<u64>,
+    pub badges: Vec<BadgeDefinition>,
+}
+
+impl Default for AchievementConfig {
+    fn default() -> Self {
+        AchievementConfig {
+            level_thresholds: vec![
+                0, 10_000, 50_000, 100_000, 250_000, 500_000,
+                1_000_000, 2_500_000, 5_000_000, 10_000_000,
+            ],
+            badges: vec![
+                BadgeDefinition {
+                    id: "first_100km".to_string(),
+                    name: "First 100 KM".to_string(),
+                    description: "Travel your first 100 km".to_string(),
+                    criterion: BadgeCriterion::TotalXp(100_000),
+                },
+                BadgeDefinition {
+                    id: "streak_30".to_string(),
+                    name: "30-Day Streak".to_string(),
+                    description: "Ride on 30 consecutive days".to_string(),
+                    criterion: BadgeCriterion::StreakDays(30),
+                },
+                BadgeDefinition {
+                    id: "cluster_champion".to_string(),
+                    name: "Cluster Champion".to_string(),
+                    description: "Top rider of your cluster".to_string(),
+                    criterion: BadgeCriterion::Awarded,
+                },
+            ],
+        }
+    }
+}
+
+// Soulbound: badges are only ever added, never transferred or revoked
+#[derive(CandidType, Deserialize, Clone)]
+pub struct EarnedBadge {
+    pub badge_id: String,
+    pub earned_at: u64,
+    pub mint_event: Option<u64>,
+    pub token_id: Option<u64>, // ICRC-7 token id
+}
+
+#[derive(CandidType, Deserialize, Clone, Default)]
+pub struct UserAchievements {
+    pub level_token: Option<u64>, // ICRC-7 token id of the user's level token
+    pub badges: Vec<EarnedBadge>,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub last_active_day: Option<u32>, // YYYYMMDD
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct UserLevel {
+    pub principal: Principal,
+    pub balance: u64,
+    pub level: u32,
+    pub current_level_xp: u64,
+    pub next_level_xp: Option<u64>,
+    pub progress_basis_points: u32, // 0..=10_000 towards the next level
+    pub badges: Vec<EarnedBadge>,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+}
+
+// ============= ICRC-7 TYPES =============
+
+#[derive(CandidType, Deserialize, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<[u8; 32]>,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum Value {
+    Blob(Vec<u8>),
+    Text(String),
+    Nat(Nat),
+    Int(Int),
+    Array(Vec<Value>),
+    Map(Vec<(String, Value)>),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub enum AchievementTokenKind {
+    Level,
+    Badge(String),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct AchievementToken {
+    pub owner: Principal,
+    pub kind: AchievementTokenKind,
+    pub minted_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct Icrc7TransferArg {
+    pub from_subaccount: Option<[u8; 32]>,
+    pub to: Account,
+    pub token_id: Nat,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub enum Icrc7TransferError {
+    NonExistingTokenId,
+    InvalidRecipient,
+    Unauthorized,
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    GenericError { error_code: Nat, message: String },
+    GenericBatchError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct StandardRecord {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Copy)]
+pub enum LeaderboardWindow {
+    Weekly,  // last 7 days of mints
+    Monthly, // last 30 days of mints
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct LeaderboardEntry {
     pub rank: u32,
@@ -61,22 +249,42 @@ pub struct LeaderboardEntry {
     pub username: Option<String>,
 }
 
-#[derive(Default)]
-struct XPLedger {
-    balances: HashMap<Principal, XPBalance>,
-    total_supply: u64,
-    minting_history: Vec<MintEvent>,
-    authorized_minters: Vec<Principal>,
+// Owner, minters and running totals, kept in a single stable cell
+#[derive(CandidType, Deserialize, Clone, Default)]
+struct LedgerConfig {
     owner: Option<Principal>,
+    authorized_minters: Vec<Principal>,
+    total_supply: u64,
     metadata: TokenMetadata,
 }
 
+// Optional post_upgrade argument; unset fields keep the stored config
+#[derive(CandidType, Deserialize, Default)]
+pub struct XpUpgradeArgs {
+    pub owner: Option<Principal>,
+    // Replaces the whole minter list
+    pub authorized_minters: Option<Vec<Principal>>,
+    pub logo: Option<String>,
+}
+
+// Heap ledger contents from deployments that predate stable memory.
+// Those builds had no upgrade hooks, so the snapshot has to be rebuilt off-chain
+// (balance queries + mint records) and handed to `import_legacy_ledger`.
+#[derive(CandidType, Deserialize)]
+pub struct LegacyLedgerSnapshot {
+    pub balances: Vec<XPBalance>,
+    pub minting_history: Vec<MintEvent>,
+    pub authorized_minters: Vec<Principal>,
+}
+
 #[derive(CandidType, Deserialize, Serialize, Clone)]
 struct TokenMetadata {
     name: String,
     symbol: String,
     decimals: u8,
     description: String,
+    // URL or data URL of the ICRC-7 collection logo
+    logo: Option<String>,
 }
 
 impl Default for TokenMetadata {
@@ -86,24 +294,238 @@ impl Default for TokenMetadata {
             symbol: "XP".to_string(),
             decimals: 3, // 1 XP = 1.000 units for precision
             description: "Non-transferable achievement tokens. 1 XP = 1 KM traveled.".to_string(),
+            logo: None,
+        }
+    }
+}
+
+// ============= STABLE STORAGE =============
+
+// Values go through the versioned `stable_schema` envelope; everything stored before it is
+// raw candid and reads as version 0
+stable_schema::versioned_since_raw_candid!(XPBalance, "xp_token::XPBalance");
+stable_schema::versioned_since_raw_candid!(MintEvent, "xp_token::MintEvent");
+stable_schema::versioned_since_raw_candid!(BatchRecord, "xp_token::BatchRecord");
+stable_schema::versioned_since_raw_candid!(AchievementConfig, "xp_token::AchievementConfig");
+stable_schema::versioned_since_raw_candid!(UserAchievements, "xp_token::UserAchievements");
+stable_schema::versioned_since_raw_candid!(AchievementToken, "xp_token::AchievementToken");
+
+impl Versioned for LedgerConfig {
+    const NAME: &'static str = "xp_token::LedgerConfig";
+    const VERSION: u16 = 1;
+
+    fn migrate(version: u16, payload: &[u8]) -> Result<Self, SchemaError> {
+        match version {
+            // Raw candid. Configs written before the collection logo have no
+            // `metadata.logo`, which candid reads as `None`
+            0 => stable_schema::decode_candid(Self::NAME, version, payload),
+            _ => Err(SchemaError::UnsupportedVersion { type_name: Self::NAME, version }),
         }
     }
 }
 
+stable_schema::versioned_storable!(LedgerConfig);
+
 // ============= STATE MANAGEMENT =============
 
+const BALANCES_MEMORY_ID: u8 = 0;
+const MINT_HISTORY_INDEX_MEMORY_ID: u8 = 1;
+const MINT_HISTORY_DATA_MEMORY_ID: u8 = 2;
+const CONFIG_MEMORY_ID: u8 = 3;
+const BATCHES_MEMORY_ID: u8 = 4;
+const BATCH_HASHES_MEMORY_ID: u8 = 5;
+const MINTED_PERIODS_MEMORY_ID: u8 = 6;
+const DATA_HASHES_MEMORY_ID: u8 = 7;
+const ACHIEVEMENT_CONFIG_MEMORY_ID: u8 = 8;
+const ACHIEVEMENTS_MEMORY_ID: u8 = 9;
+const LEADERBOARD_MEMORY_ID: u8 = 10;
+const USERNAMES_MEMORY_ID: u8 = 11;
+const USERNAME_OWNERS_MEMORY_ID: u8 = 12;
+const ACHIEVEMENT_TOKENS_MEMORY_ID: u8 = 13;
+const TOKENS_BY_OWNER_MEMORY_ID: u8 = 14;
+const SCHEMA_REGISTRY_MEMORY_ID: u8 = 15;
+
+const MAX_LEADERBOARD_SIZE: usize = 100;
+// Rank index bands are 1 XP wide; the last band holds every balance above it
+const RANK_BANDS: usize = 1 << 20;
+const ICRC7_MAX_QUERY_BATCH_SIZE: usize = 100;
+const ICRC7_DEFAULT_TAKE: usize = 100;
+const ICRC7_MAX_MEMO_SIZE: usize = 32;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+const MAX_SINGLE_MINT: u64 = 1_000_000; // 1000 XP
+const MAX_BATCH_SIZE: usize = 1000;
+
 thread_local! {
-    static LEDGER: RefCell<XPLedger> = RefCell::new(XPLedger::default());
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = 
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    static BALANCES: RefCell<StableBTreeMap<Principal, XPBalance, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(BALANCES_MEMORY_ID)))
+        )
+    );
+
+    static MINT_HISTORY: RefCell<StableLog<MintEvent, Memory, Memory>> = RefCell::new(
+        StableLog::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(MINT_HISTORY_INDEX_MEMORY_ID))),
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(MINT_HISTORY_DATA_MEMORY_ID))),
+        ).expect("failed to initialize the mint history log")
+    );
+
+    static BATCHES: RefCell<StableBTreeMap<u64, BatchRecord, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(BATCHES_MEMORY_ID)))
+        )
+    );
+
+    // batch_hash -> batch_id, used to reject replayed batches
+    static BATCH_HASHES: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(BATCH_HASHES_MEMORY_ID)))
+        )
+    );
+
+    // (user, start_day << 32 | end_day) -> mint history index, days as YYYYMMDD
+    static MINTED_PERIODS: RefCell<StableBTreeMap<(Principal, u64), u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(MINTED_PERIODS_MEMORY_ID)))
+        )
+    );
+
+    // data_hash -> mint history index
+    static DATA_HASHES: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(DATA_HASHES_MEMORY_ID)))
+        )
+    );
+
+    static ACHIEVEMENT_CONFIG: RefCell<StableCell<AchievementConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(ACHIEVEMENT_CONFIG_MEMORY_ID))),
+            AchievementConfig::default(),
+        ).expect("failed to initialize the achievement config cell")
+    );
+
+    static ACHIEVEMENTS: RefCell<StableBTreeMap<Principal, UserAchievements, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(ACHIEVEMENTS_MEMORY_ID)))
+        )
+    );
+
+    // (u64::MAX - balance, user): iterating in key order walks balances from highest to lowest
+    static LEADERBOARD: RefCell<StableBTreeMap<(u64, Principal), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(LEADERBOARD_MEMORY_ID)))
+        )
+    );
+
+    // Holders per rank band as a Fenwick tree (1-based), derived from LEADERBOARD and
+    // rebuilt on upgrade, so a rank is a prefix sum plus a scan of the user's own band
+    static RANK_INDEX: RefCell<Vec<u32>> = RefCell::new(vec![0; RANK_BANDS + 1]);
+
+    static USERNAMES: RefCell<StableBTreeMap<Principal, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(USERNAMES_MEMORY_ID)))
+        )
+    );
+
+    // Lowercased username -> owner, keeps usernames unique
+    static USERNAME_OWNERS: RefCell<StableBTreeMap<String, Principal, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(USERNAME_OWNERS_MEMORY_ID)))
+        )
+    );
+
+    // ICRC-7 view of levels and badges, token id -> token
+    static ACHIEVEMENT_TOKENS: RefCell<StableBTreeMap<u64, AchievementToken, Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(ACHIEVEMENT_TOKENS_MEMORY_ID)))
+        )
+    );
+
+    static TOKENS_BY_OWNER: RefCell<StableBTreeMap<(Principal, u64), (), Memory>> = RefCell::new(
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(TOKENS_BY_OWNER_MEMORY_ID)))
+        )
+    );
+
+    static CONFIG: RefCell<StableCell<LedgerConfig, Memory>> = RefCell::new(
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(CONFIG_MEMORY_ID))),
+            LedgerConfig::default(),
+        ).expect("failed to initialize the ledger config cell")
+    );
+
+    static SCHEMA_REGISTRY: RefCell<SchemaRegistry<Memory>> = RefCell::new(
+        SchemaRegistry::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(SCHEMA_REGISTRY_MEMORY_ID)))
+        )
+    );
+}
+
+fn read_config<R>(f: impl FnOnce(&LedgerConfig) -> R) -> R {
+    CONFIG.with(|config| f(config.borrow().get()))
+}
+
+fn mutate_config<R>(f: impl FnOnce(&mut LedgerConfig) -> R) -> R {
+    CONFIG.with(|config| {
+        let mut cell = config.borrow_mut();
+        let mut updated = cell.get().clone();
+        let result = f(&mut updated);
+        cell.set(updated).expect("failed to write the ledger config cell");
+        result
+    })
+}
+
+// Appends to the mint history and indexes the event's data_hash and period
+fn append_mint_event(event: &MintEvent) -> u64 {
+    let index = MINT_HISTORY.with(|history| {
+        history.borrow().append(event).expect("failed to append to the mint history log")
+    });
+    
+    DATA_HASHES.with(|hashes| hashes.borrow_mut().insert(event.data_hash.clone(), index));
+    if let Some((start, end)) = event.date_range.split_once(" to ") {
+        if let (Some(start), Some(end)) = (period_day(start), period_day(end)) {
+            MINTED_PERIODS.with(|periods| {
+                periods.borrow_mut().insert((event.user, period_key(start, end)), index)
+            });
+        }
+    }
+    
+    index
+}
+
+// Parses the leading YYYY-MM-DD of a date or ISO timestamp into YYYYMMDD
+fn period_day(value: &str) -> Option<u32> {
+    let mut parts = value.get(..10)?.split('-');
+    let year: u32 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(year * 10_000 + month * 100 + day)
+}
+
+fn period_key(start: u32, end: u32) -> u64 {
+    ((start as u64) << 32) | end as u64
+}
+
+fn already_minted(reason: String, event_index: u64) -> MintError {
+    let original = MINT_HISTORY.with(|history| history.borrow().get(event_index))
+        .expect("indexed mint event missing from history");
+    MintError::AlreadyMinted { reason, event_index, original: Box::new(original) }
 }
 
 // ============= INITIALIZATION =============
 
 #[init]
 fn init() {
-    LEDGER.with(|ledger| {
-        let mut l = ledger.borrow_mut();
-        l.owner = Some(ic_cdk::caller());
-        l.authorized_minters.push(ic_cdk::caller());
+    mutate_config(|config| {
+        config.owner = Some(caller());
+        config.authorized_minters.push(caller());
         
         // Add the consensus and rewards canisters as authorized minters
         // These should be passed as init arguments in production
@@ -112,26 +534,293 @@ fn init() {
     ic_cdk::println!("XP Token canister initialized");
 }
 
+// Balances, history and config all live in stable memory, so there is nothing
+// to save before an upgrade and nothing to restore after it
+#[post_upgrade]
+fn post_upgrade(args: Option<XpUpgradeArgs>) {
+    migrate_stable_schema();
+    apply_upgrade(args.unwrap_or_default());
+    
+    ic_cdk::println!(
+        "XP Token canister upgraded: {} holders, {} mint events",
+        BALANCES.with(|balances| balances.borrow().len()),
+        MINT_HISTORY.with(|history| history.borrow().len())
+    );
+}
+
+// Rewrites the config cells once per schema version bump. Balances, mint history, batches,
+// achievements and tokens grow with every holder, so their values are migrated on read
+fn migrate_stable_schema() {
+    fn migrate<T: Versioned>(collection: &str, rewrite: impl FnOnce() -> u64) {
+        let migrated = SCHEMA_REGISTRY.with(|registry| {
+            registry.borrow_mut().migrate::<T>(collection, rewrite)
+        });
+        if let Some(count) = migrated {
+            ic_cdk::println!("Migrated {} {} to schema v{}", count, collection, T::VERSION);
+        }
+    }
+
+    migrate::<LedgerConfig>("config", || {
+        CONFIG.with(|config| rewrite_cell(&mut config.borrow_mut()))
+    });
+    migrate::<AchievementConfig>("achievement_config", || {
+        ACHIEVEMENT_CONFIG.with(|config| rewrite_cell(&mut config.borrow_mut()))
+    });
+}
+
+fn apply_upgrade(args: XpUpgradeArgs) {
+    // The heap-based builds kept owner and minters on the heap, so upgrading from them
+    // starts from an empty config: the controller running the upgrade becomes the owner
+    let upgrader = caller();
+    mutate_config(|config| {
+        if let Some(owner) = args.owner {
+            config.owner = Some(owner);
+        } else if config.owner.is_none() {
+            config.owner = Some(upgrader);
+        }
+        if let Some(minters) = args.authorized_minters {
+            config.authorized_minters = minters;
+        }
+        if args.logo.is_some() {
+            config.metadata.logo = args.logo;
+        }
+    });
+    
+    rebuild_rank_index();
+    
+    // Builds that predate the leaderboard index only have balances
+    let needs_backfill = LEADERBOARD.with(|index| index.borrow().is_empty())
+        && !BALANCES.with(|balances| balances.borrow().is_empty());
+    if needs_backfill {
+        BALANCES.with(|balances| {
+            for (principal, balance) in balances.borrow().iter() {
+                update_leaderboard(principal, None, balance.balance);
+            }
+        });
+    }
+    
+    // Every mint gives its holder a level token, so only holders from before the ICRC-7 view
+    // lack one; backfill them on the first upgrade that has the view, not on every upgrade
+    let backfilled = SCHEMA_REGISTRY.with(|registry| {
+        registry.borrow_mut().migrate::<UserAchievements>("level_tokens", backfill_level_tokens)
+    });
+    if let Some(count) = backfilled {
+        ic_cdk::println!("Minted {} level tokens for existing holders", count);
+    }
+}
+
+fn backfill_level_tokens() -> u64 {
+    let holders: Vec<Principal> = BALANCES.with(|balances| balances.borrow().keys().collect());
+    let mut minted = 0;
+    for holder in holders {
+        ACHIEVEMENTS.with(|a| {
+            let mut a = a.borrow_mut();
+            let mut achievements = a.get(&holder).unwrap_or_default();
+            if achievements.level_token.is_none() {
+                achievements.level_token = Some(mint_achievement_token(holder, AchievementTokenKind::Level));
+                a.insert(holder, achievements);
+                minted += 1;
+            }
+        });
+    }
+    minted
+}
+
+#[update]
+fn import_legacy_ledger(snapshot: LegacyLedgerSnapshot) -> Result<String, String> {
+    if !is_owner() {
+        return Err("Only owner can import a legacy ledger".to_string());
+    }
+    
+    let is_empty = BALANCES.with(|balances| balances.borrow().is_empty())
+        && MINT_HISTORY.with(|history| history.borrow().is_empty());
+    if !is_empty {
+        return Err("Ledger already holds data; legacy import is only allowed once".to_string());
+    }
+    
+    let mut total_supply = 0u64;
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        for balance in snapshot.balances.iter() {
+            total_supply += balance.balance;
+            balances.insert(balance.principal, balance.clone());
+            update_leaderboard(balance.principal, None, balance.balance);
+        }
+    });
+    
+    for event in snapshot.minting_history.iter() {
+        append_mint_event(event);
+    }
+    
+    mutate_config(|config| {
+        config.total_supply = total_supply;
+        for minter in snapshot.authorized_minters {
+            if !config.authorized_minters.contains(&minter) {
+                config.authorized_minters.push(minter);
+            }
+        }
+    });
+    
+    Ok(format!(
+        "Imported {} balances and {} mint events ({} XP total)",
+        snapshot.balances.len(),
+        snapshot.minting_history.len(),
+        total_supply / 1000
+    ))
+}
+
 // ============= MINTING FUNCTIONS =============
 
 #[update]
-fn mint_xp(request: MintRequest) -> Result<String, String> {
+fn mint_xp(request: MintRequest) -> Result<String, MintError> {
     // Check authorization
     if !is_authorized_minter() {
-        return Err("Unauthorized: Only authorized minters can mint XP".to_string());
+        return Err(MintError::Unauthorized);
+    }
+    
+    validate_mint(&request)?;
+    
+    let xp_amount = request.xp_amount;
+    let user = request.user_principal;
+    let balance = apply_mint(request, None);
+    
+    Ok(format!(
+        "Minted {} XP for principal {}. New balance: {}",
+        xp_amount / 1000, // Convert to display units
+        user,
+        balance.balance / 1000
+    ))
+}
+
+#[update]
+fn batch_mint_xp(batch: BatchMintRequest) -> Result<BatchMintResult, MintError> {
+    // Check authorization
+    if !is_authorized_minter() {
+        return Err(MintError::Unauthorized);
+    }
+    
+    // Validate batch size
+    if batch.distributions.is_empty() {
+        return Err(MintError::InvalidRequest("Batch is empty".to_string()));
+    }
+    if batch.distributions.len() > MAX_BATCH_SIZE {
+        return Err(MintError::InvalidRequest(format!("Batch size exceeds maximum ({})", MAX_BATCH_SIZE)));
+    }
+    if batch.batch_hash.is_empty() {
+        return Err(MintError::InvalidRequest("Batch hash is required".to_string()));
+    }
+    
+    // Reject replays of an already applied batch
+    if let Some(batch_id) = BATCH_HASHES.with(|hashes| hashes.borrow().get(&batch.batch_hash)) {
+        return Err(MintError::InvalidRequest(format!(
+            "Batch {} was already minted as batch #{}", batch.batch_hash, batch_id
+        )));
+    }
+    
+    // Validate every entry before touching any balance
+    let mut seen_principals = HashSet::new();
+    let mut seen_hashes = HashSet::new();
+    let mut errors = Vec::new();
+    for (index, request) in batch.distributions.iter().enumerate() {
+        let index = index as u32;
+        if let Err(e) = validate_mint(request) {
+            errors.push((index, e));
+        }
+        if !seen_principals.insert(request.user_principal) {
+            errors.push((index, MintError::InvalidRequest("Duplicate principal in batch".to_string())));
+        }
+        if !seen_hashes.insert(request.data_hash.as_str()) {
+            errors.push((index, MintError::InvalidRequest("Duplicate data_hash in batch".to_string())));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(MintError::BatchRejected(errors));
+    }
+    
+    // Apply all entries under a single batch id
+    let batch_id = BATCHES.with(|batches| batches.borrow().len()) + 1;
+    let timestamp = time();
+    let successful_mints = batch.distributions.len() as u32;
+    let mut total_minted = 0u64;
+    
+    for request in batch.distributions {
+        total_minted += request.xp_amount;
+        apply_mint(request, Some(batch_id));
     }
     
+    BATCHES.with(|batches| {
+        batches.borrow_mut().insert(batch_id, BatchRecord {
+            batch_id,
+            batch_hash: batch.batch_hash.clone(),
+            minted_by: caller(),
+            entries: successful_mints,
+            total_minted,
+            timestamp,
+        });
+    });
+    BATCH_HASHES.with(|hashes| hashes.borrow_mut().insert(batch.batch_hash, batch_id));
+    
+    Ok(BatchMintResult {
+        batch_id,
+        successful_mints,
+        total_minted,
+        timestamp,
+    })
+}
+
+// Checks a single mint without applying it
+fn validate_mint(request: &MintRequest) -> Result<(), MintError> {
+    if request.xp_amount == 0 {
+        return Err(MintError::InvalidRequest("Mint amount must be greater than zero".to_string()));
+    }
+
     // Validate amount (max 1000 km per mint for safety)
-    if request.xp_amount > 1_000_000 {
-        return Err("Amount exceeds maximum single mint limit (1000 XP)".to_string());
+    if request.xp_amount > MAX_SINGLE_MINT {
+        return Err(MintError::InvalidRequest("Amount exceeds maximum single mint limit (1000 XP)".to_string()));
     }
     
-    LEDGER.with(|ledger| {
-        let mut l = ledger.borrow_mut();
+    // Each aggregate can only be minted once
+    if request.data_hash.is_empty() {
+        return Err(MintError::InvalidRequest("data_hash is required".to_string()));
+    }
+    if let Some(index) = DATA_HASHES.with(|hashes| hashes.borrow().get(&request.data_hash)) {
+        return Err(already_minted(format!("data_hash {} was already minted", request.data_hash), index));
+    }
+    
+    let (start, end) = match (period_day(&request.period_start), period_day(&request.period_end)) {
+        (Some(start), Some(end)) if start <= end => (start, end),
+        _ => return Err(MintError::InvalidRequest(format!(
+            "Invalid period {} to {}", request.period_start, request.period_end
+        ))),
+    };
+    
+    // Any overlap with a period already minted for this user is a double mint
+    let overlap = MINTED_PERIODS.with(|periods| {
+        periods.borrow()
+            .range((request.user_principal, 0)..=(request.user_principal, u64::MAX))
+            .find(|((_, key), _)| {
+                let minted_start = (key >> 32) as u32;
+                let minted_end = *key as u32;
+                minted_start <= end && minted_end >= start
+            })
+            .map(|(_, index)| index)
+    });
+    if let Some(index) = overlap {
+        return Err(already_minted(format!(
+            "Period {} to {} overlaps an already minted period", request.period_start, request.period_end
+        ), index));
+    }
+    
+    Ok(())
+}
+
+// Credits a validated mint and records its history event; callers check authorization
+fn apply_mint(request: MintRequest, batch_id: Option<u64>) -> XPBalance {
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
         
         // Get or create balance
-        let mut balance = l.balances.get(&request.user_principal)
-            .cloned()
+        let mut balance = balances.get(&request.user_principal)
             .unwrap_or(XPBalance {
                 principal: request.user_principal,
                 balance: 0,
@@ -140,66 +829,32 @@ fn mint_xp(request: MintRequest) -> Result<String, String> {
             });
         
         // Update balance
+        let previous_balance = balance.balance;
         balance.balance += request.xp_amount;
         balance.last_mint = time();
         balance.total_mints += 1;
         
         // Update ledger
-        l.balances.insert(request.user_principal, balance.clone());
-        l.total_supply += request.xp_amount;
+        balances.insert(request.user_principal, balance.clone());
+        update_leaderboard(request.user_principal, Some(previous_balance), balance.balance);
+        mutate_config(|config| config.total_supply += request.xp_amount);
         
         // Record mint event
-        l.minting_history.push(MintEvent {
+        let event_index = append_mint_event(&MintEvent {
             user: request.user_principal,
             amount: request.xp_amount,
             date_range: format!("{} to {}", request.period_start, request.period_end),
             timestamp: time(),
             data_hash: request.data_hash,
-            minted_by: ic_cdk::caller(),
+            minted_by: caller(),
+            batch_id,
         });
         
-        Ok(format!(
-            "Minted {} XP for principal {}. New balance: {}",
-            request.xp_amount / 1000, // Convert to display units
-            request.user_principal,
-            balance.balance / 1000
-        ))
-    })
-}
-
-#[update]
-fn batch_mint_xp(batch: BatchMintRequest) -> Result<BatchMintResult, String> {
-    // Check authorization
-    if !is_authorized_minter() {
-        return Err("Unauthorized: Only authorized minters can mint XP".to_string());
-    }
-    
-    // Validate batch size
-    if batch.distributions.len() > 1000 {
-        return Err("Batch size exceeds maximum (1000)".to_string());
-    }
-    
-    let mut successful_mints = 0;
-    let mut failed_mints = Vec::new();
-    let mut total_minted = 0u64;
-    
-    for request in batch.distributions {
-        match mint_xp(request.clone()) {
-            Ok(_) => {
-                successful_mints += 1;
-                total_minted += request.xp_amount;
-            },
-            Err(e) => {
-                failed_mints.push((request.user_principal.to_string(), e));
-            }
-        }
-    }
-    
-    Ok(BatchMintResult {
-        successful_mints,
-        failed_mints,
-        total_minted,
-        timestamp: time(),
+        // Periods were validated before applying
+        let period = period_day(&request.period_start).zip(period_day(&request.period_end));
+        evaluate_achievements(&balance, period, event_index);
+        
+        balance
     })
 }
 
@@ -207,9 +862,8 @@ fn batch_mint_xp(batch: BatchMintRequest) -> Result<BatchMintResult, String> {
 
 #[query]
 fn get_balance(user: Principal) -> u64 {
-    LEDGER.with(|ledger| {
-        ledger.borrow()
-            .balances
+    BALANCES.with(|balances| {
+        balances.borrow()
             .get(&user)
             .map(|b| b.balance)
             .unwrap_or(0)
@@ -218,77 +872,213 @@ fn get_balance(user: Principal) -> u64 {
 
 #[query]
 fn get_balance_details(user: Principal) -> Option<XPBalance> {
-    LEDGER.with(|ledger| {
-        ledger.borrow().balances.get(&user).cloned()
-    })
+    BALANCES.with(|balances| balances.borrow().get(&user))
 }
 
 #[query]
 fn get_total_supply() -> u64 {
-    LEDGER.with(|ledger| ledger.borrow().total_supply)
+    read_config(|config| config.total_supply)
 }
 
 #[query]
 fn get_stats() -> XPStats {
-    LEDGER.with(|ledger| {
-        let l = ledger.borrow();
-        let total_users = l.balances.len() as u64;
-        let average_balance = if total_users > 0 {
-            l.total_supply / total_users
-        } else {
-            0
-        };
-        
-        XPStats {
-            total_supply: l.total_supply,
-            total_users,
-            total_mints: l.minting_history.len() as u64,
-            average_balance,
-        }
-    })
+    let total_supply = get_total_supply();
+    let total_users = BALANCES.with(|balances| balances.borrow().len());
+    let average_balance = total_supply.checked_div(total_users).unwrap_or(0);
+    
+    XPStats {
+        total_supply,
+        total_users,
+        total_mints: MINT_HISTORY.with(|history| history.borrow().len()),
+        average_balance,
+    }
 }
 
 #[query]
 fn get_leaderboard(limit: usize) -> Vec<LeaderboardEntry> {
-    LEDGER.with(|ledger| {
-        let mut balances: Vec<_> = ledger.borrow()
-            .balances
+    LEADERBOARD.with(|index| {
+        index.borrow()
             .iter()
-            .map(|(principal, balance)| (*principal, balance.balance))
-            .collect();
-        
-        // Sort by balance descending
-        balances.sort_by(|a, b| b.1.cmp(&a.1));
-        
-        // Take top N and create leaderboard entries
-        balances.iter()
-            .take(limit.min(100)) // Max 100 entries
+            .take(limit.min(MAX_LEADERBOARD_SIZE))
             .enumerate()
-            .map(|(index, (principal, balance))| LeaderboardEntry {
-                rank: (index + 1) as u32,
-                principal: *principal,
-                balance: *balance,
-                username: None, // Could be fetched from user registry
+            .map(|(position, ((inverted_balance, principal), _))| LeaderboardEntry {
+                rank: (position + 1) as u32,
+                principal,
+                balance: u64::MAX - inverted_balance,
+                username: get_username(principal),
             })
             .collect()
     })
 }
 
+// Rank among all holders; users with equal balances are ordered by principal.
+// Holders in higher bands are counted by the rank index in O(log bands); only the
+// holders ahead of the user within their own 1 XP band are scanned.
+#[query]
+fn get_user_rank(user: Principal) -> Option<u32> {
+    let balance = get_balance_details(user)?.balance;
+    let band = rank_band(balance);
+    let above = RANK_INDEX.with(|index| {
+        let index = index.borrow();
+        rank_index_prefix(&index, RANK_BANDS) - rank_index_prefix(&index, band + 1)
+    });
+    
+    let band_top = if band == RANK_BANDS - 1 { u64::MAX } else { (band as u64 + 1) * 1000 - 1 };
+    let ahead_in_band = LEADERBOARD.with(|index| {
+        index.borrow()
+            .range((u64::MAX - band_top, Principal::from_slice(&[]))..(u64::MAX - balance, user))
+            .count() as u64
+    });
+    
+    Some((above + ahead_in_band + 1) as u32)
+}
+
+// Sums mints newer than the window; the history is append-only, so the scan
+// walks backwards and stops at the first event older than the window
+#[query]
+fn get_windowed_leaderboard(window: LeaderboardWindow, limit: usize) -> Vec<LeaderboardEntry> {
+    let days = match window {
+        LeaderboardWindow::Weekly => 7,
+        LeaderboardWindow::Monthly => 30,
+    };
+    let since = time().saturating_sub(days * NANOS_PER_DAY);
+    
+    let mut totals: HashMap<Principal, u64> = HashMap::new();
+    MINT_HISTORY.with(|history| {
+        let history = history.borrow();
+        for event in (0..history.len()).rev().filter_map(|index| history.get(index)) {
+            if event.timestamp < since {
+                break;
+            }
+            *totals.entry(event.user).or_insert(0) += event.amount;
+        }
+    });
+    
+    let mut ranked: Vec<_> = totals.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    
+    ranked.into_iter()
+        .take(limit.min(MAX_LEADERBOARD_SIZE))
+        .enumerate()
+        .map(|(position, (principal, balance))| LeaderboardEntry {
+            rank: (position + 1) as u32,
+            principal,
+            balance,
+            username: get_username(principal),
+        })
+        .collect()
+}
+
+#[query]
+fn get_username(user: Principal) -> Option<String> {
+    USERNAMES.with(|usernames| usernames.borrow().get(&user))
+}
+
+// Sets or clears (None) the caller's display name on leaderboards
+#[update]
+fn set_username(username: Option<String>) -> Result<String, String> {
+    let caller = caller();
+    
+    if let Some(name) = username.as_ref() {
+        if name.len() < 3 || name.len() > 32 {
+            return Err("Username must be between 3 and 32 characters".to_string());
+        }
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err("Username may only contain letters, digits, '_' and '-'".to_string());
+        }
+        let owner = USERNAME_OWNERS.with(|owners| owners.borrow().get(&name.to_ascii_lowercase()));
+        if owner.is_some_and(|owner| owner != caller) {
+            return Err(format!("Username {} is already taken", name));
+        }
+    }
+    
+    if let Some(previous) = get_username(caller) {
+        USERNAME_OWNERS.with(|owners| owners.borrow_mut().remove(&previous.to_ascii_lowercase()));
+    }
+    
+    match username {
+        Some(name) => {
+            USERNAME_OWNERS.with(|owners| owners.borrow_mut().insert(name.to_ascii_lowercase(), caller));
+            USERNAMES.with(|usernames| usernames.borrow_mut().insert(caller, name.clone()));
+            Ok(format!("Username set to {}", name))
+        }
+        None => {
+            USERNAMES.with(|usernames| usernames.borrow_mut().remove(&caller));
+            Ok("Username cleared".to_string())
+        }
+    }
+}
+
+// Moves a user's entry in the leaderboard index from `previous` to `current`
+fn update_leaderboard(user: Principal, previous: Option<u64>, current: u64) {
+    LEADERBOARD.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(previous) = previous {
+            index.remove(&(u64::MAX - previous, user));
+        }
+        index.insert((u64::MAX - current, user), ());
+    });
+    
+    if let Some(previous) = previous {
+        rank_index_add(rank_band(previous), -1);
+    }
+    rank_index_add(rank_band(current), 1);
+}
+
+fn rank_band(balance: u64) -> usize {
+    ((balance / 1000) as usize).min(RANK_BANDS - 1)
+}
+
+fn rank_index_add(band: usize, delta: i32) {
+    RANK_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let mut position = band + 1;
+        while position <= RANK_BANDS {
+            index[position] = index[position].wrapping_add_signed(delta);
+            position += position.isolate_lowest_one();
+        }
+    });
+}
+
+// Holders in the first `bands` bands
+fn rank_index_prefix(index: &[u32], bands: usize) -> u64 {
+    let mut position = bands;
+    let mut holders = 0u64;
+    while position > 0 {
+        holders += index[position] as u64;
+        position &= position - 1;
+    }
+    holders
+}
+
+fn rebuild_rank_index() {
+    RANK_INDEX.with(|index| index.borrow_mut().fill(0));
+    LEADERBOARD.with(|index| {
+        for ((inverted_balance, _), _) in index.borrow().iter() {
+            rank_index_add(rank_band(u64::MAX - inverted_balance), 1);
+        }
+    });
+}
+
+#[query]
+fn get_batch(batch_id: u64) -> Option<BatchRecord> {
+    BATCHES.with(|batches| batches.borrow().get(&batch_id))
+}
+
 #[query]
 fn get_metadata() -> TokenMetadata {
-    LEDGER.with(|ledger| ledger.borrow().metadata.clone())
+    read_config(|config| config.metadata.clone())
 }
 
 #[query]
 fn get_mint_history(user: Principal, limit: usize) -> Vec<MintEvent> {
-    LEDGER.with(|ledger| {
-        ledger.borrow()
-            .minting_history
-            .iter()
-            .filter(|event| event.user == user)
+    MINT_HISTORY.with(|history| {
+        let history = history.borrow();
+        (0..history.len())
             .rev()
+            .filter_map(|index| history.get(index))
+            .filter(|event| event.user == user)
             .take(limit.min(50))
-            .cloned()
             .collect()
     })
 }
@@ -301,10 +1091,9 @@ fn add_authorized_minter(minter: Principal) -> Result<String, String> {
         return Err("Only owner can add authorized minters".to_string());
     }
     
-    LEDGER.with(|ledger| {
-        let mut l = ledger.borrow_mut();
-        if !l.authorized_minters.contains(&minter) {
-            l.authorized_minters.push(minter);
+    mutate_config(|config| {
+        if !config.authorized_minters.contains(&minter) {
+            config.authorized_minters.push(minter);
             Ok(format!("Added {} as authorized minter", minter))
         } else {
             Err("Minter already authorized".to_string())
@@ -318,34 +1107,433 @@ fn remove_authorized_minter(minter: Principal) -> Result<String, String> {
         return Err("Only owner can remove authorized minters".to_string());
     }
     
-    LEDGER.with(|ledger| {
-        let mut l = ledger.borrow_mut();
-        l.authorized_minters.retain(|&x| x != minter);
+    mutate_config(|config| {
+        config.authorized_minters.retain(|&x| x != minter);
         Ok(format!("Removed {} from authorized minters", minter))
     })
 }
 
 #[query]
 fn get_authorized_minters() -> Vec<Principal> {
-    LEDGER.with(|ledger| ledger.borrow().authorized_minters.clone())
+    read_config(|config| config.authorized_minters.clone())
 }
 
 // ============= HELPER FUNCTIONS =============
 
 fn is_authorized_minter() -> bool {
-    let caller = ic_cdk::caller();
-    LEDGER.with(|ledger| {
-        ledger.borrow().authorized_minters.contains(&caller)
-    })
+    let caller = caller();
+    read_config(|config| config.authorized_minters.contains(&caller))
 }
 
 fn is_owner() -> bool {
-    let caller = ic_cdk::caller();
-    LEDGER.with(|ledger| {
-        ledger.borrow().owner == Some(caller)
+    let caller = caller();
+    read_config(|config| config.owner == Some(caller))
+}
+
+// ============= ACHIEVEMENTS =============
+
+#[query]
+fn get_user_level(user: Principal) -> UserLevel {
+    let balance = get_balance(user);
+    let achievements = ACHIEVEMENTS.with(|a| a.borrow().get(&user)).unwrap_or_default();
+    let thresholds = ACHIEVEMENT_CONFIG.with(|c| c.borrow().get().level_thresholds.clone());
+    
+    let level = thresholds.iter().filter(|&&threshold| balance >= threshold).count() as u32;
+    let current_level_xp = level.checked_sub(1)
+        .map(|index| thresholds[index as usize])
+        .unwrap_or(0);
+    let next_level_xp = thresholds.get(level as usize).copied();
+    let progress_basis_points = match next_level_xp {
+        Some(next) => ((balance - current_level_xp) as u128 * 10_000
+            / (next - current_level_xp) as u128) as u32,
+        None => 10_000,
+    };
+    
+    UserLevel {
+        principal: user,
+        balance,
+        level,
+        current_level_xp,
+        next_level_xp,
+        progress_basis_points,
+        badges: achievements.badges,
+        current_streak: achievements.current_streak,
+        longest_streak: achievements.longest_streak,
+    }
+}
+
+#[query]
+fn get_achievement_config() -> AchievementConfig {
+    ACHIEVEMENT_CONFIG.with(|c| c.borrow().get().clone())
+}
+
+#[update]
+fn set_achievement_config(config: AchievementConfig) -> Result<String, String> {
+    if !is_owner() {
+        return Err("Only owner can change the achievement config".to_string());
+    }
+    
+    if config.level_thresholds.first() != Some(&0) {
+        return Err("Level thresholds must start at 0".to_string());
+    }
+    if config.level_thresholds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err("Level thresholds must be strictly increasing".to_string());
+    }
+    let mut ids = HashSet::new();
+    if let Some(badge) = config.badges.iter().find(|badge| !ids.insert(badge.id.as_str())) {
+        return Err(format!("Duplicate badge id {}", badge.id));
+    }
+    
+    // Badges already earned stay earned even if their definition is removed
+    ACHIEVEMENT_CONFIG.with(|c| c.borrow_mut().set(config))
+        .map_err(|_| "Failed to store the achievement config".to_string())?;
+    Ok("Achievement config updated".to_string())
+}
+
+#[update]
+fn award_badge(user: Principal, badge_id: String) -> Result<String, String> {
+    if !is_authorized_minter() {
+        return Err("Unauthorized: Only authorized minters can award badges".to_string());
+    }
+    
+    let criterion = ACHIEVEMENT_CONFIG.with(|c| {
+        c.borrow().get().badges.iter()
+            .find(|badge| badge.id == badge_id)
+            .map(|badge| badge.criterion.clone())
+    }).ok_or_else(|| format!("Unknown badge {}", badge_id))?;
+    if criterion != BadgeCriterion::Awarded {
+        return Err(format!("Badge {} is earned automatically and cannot be awarded", badge_id));
+    }
+    
+    ACHIEVEMENTS.with(|a| {
+        let mut a = a.borrow_mut();
+        let mut achievements = a.get(&user).unwrap_or_default();
+        if achievements.badges.iter().any(|earned| earned.badge_id == badge_id) {
+            return Err(format!("{} already holds badge {}", user, badge_id));
+        }
+        achievements.badges.push(EarnedBadge {
+            badge_id: badge_id.clone(),
+            earned_at: time(),
+            mint_event: None,
+            token_id: Some(mint_achievement_token(user, AchievementTokenKind::Badge(badge_id.clone()))),
+        });
+        a.insert(user, achievements);
+        Ok(format!("Awarded {} to {}", badge_id, user))
     })
 }
 
+// Updates the user's streak with the minted period and grants any newly met badges
+fn evaluate_achievements(balance: &XPBalance, period: Option<(u32, u32)>, event_index: u64) {
+    let config = ACHIEVEMENT_CONFIG.with(|c| c.borrow().get().clone());
+    
+    ACHIEVEMENTS.with(|a| {
+        let mut a = a.borrow_mut();
+        let mut achievements = a.get(&balance.principal).unwrap_or_default();
+        
+        // Every XP holder gets one level token whose metadata tracks the current level
+        if achievements.level_token.is_none() {
+            achievements.level_token = Some(mint_achievement_token(balance.principal, AchievementTokenKind::Level));
+        }
+        
+        if let Some((start, end)) = period {
+            let (start, end) = (day_number(start), day_number(end));
+            let last = achievements.last_active_day.map(day_number);
+            match last {
+                // Period continues (or overlaps) the current streak
+                Some(last) if start <= last + 1 && end > last => {
+                    achievements.current_streak += (end - last) as u32;
+                }
+                // Period is entirely inside days already counted
+                Some(last) if end <= last => {}
+                _ => achievements.current_streak = (end - start + 1) as u32,
+            }
+            if last.is_none_or(|last| end > last) {
+                achievements.last_active_day = period.map(|(_, end)| end);
+            }
+            achievements.longest_streak = achievements.longest_streak.max(achievements.current_streak);
+        }
+        
+        for badge in config.badges.iter() {
+            if achievements.badges.iter().any(|earned| earned.badge_id == badge.id) {
+                continue;
+            }
+            let earned = match badge.criterion {
+                BadgeCriterion::TotalXp(min_xp) => balance.balance >= min_xp,
+                BadgeCriterion::StreakDays(days) => achievements.longest_streak >= days,
+                BadgeCriterion::Awarded => false,
+            };
+            if earned {
+                achievements.badges.push(EarnedBadge {
+                    badge_id: badge.id.clone(),
+                    earned_at: time(),
+                    mint_event: Some(event_index),
+                    token_id: Some(mint_achievement_token(
+                        balance.principal,
+                        AchievementTokenKind::Badge(badge.id.clone()),
+                    )),
+                });
+            }
+        }
+        
+        a.insert(balance.principal, achievements);
+    });
+}
+
+// Days since 1970-01-01 for a YYYYMMDD value, so consecutive dates differ by one
+fn day_number(yyyymmdd: u32) -> i64 {
+    let (year, month, day) = ((yyyymmdd / 10_000) as i64, ((yyyymmdd / 100) % 100) as i64, (yyyymmdd % 100) as i64);
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// ============= ICRC-7 VIEW (READ-ONLY) =============
+// Levels and badges exposed as soulbound NFTs so wallets can display them.
+// Tokens are only created by `evaluate_achievements` and `award_badge`.
+
+fn mint_achievement_token(owner: Principal, kind: AchievementTokenKind) -> u64 {
+    let token_id = ACHIEVEMENT_TOKENS.with(|tokens| tokens.borrow().len()) + 1;
+    ACHIEVEMENT_TOKENS.with(|tokens| {
+        tokens.borrow_mut().insert(token_id, AchievementToken {
+            owner,
+            kind,
+            minted_at: time(),
+        })
+    });
+    TOKENS_BY_OWNER.with(|index| index.borrow_mut().insert((owner, token_id), ()));
+    token_id
+}
+
+fn token_id_of(id: &Nat) -> Option<u64> {
+    u64::try_from(id.0.clone()).ok()
+}
+
+fn get_token(id: &Nat) -> Option<AchievementToken> {
+    token_id_of(id).and_then(|id| ACHIEVEMENT_TOKENS.with(|tokens| tokens.borrow().get(&id)))
+}
+
+// Tokens are always held by the owner's default account
+fn is_default_account(account: &Account) -> bool {
+    account.subaccount.is_none_or(|subaccount| subaccount == [0u8; 32])
+}
+
+fn take_limit(take: Option<Nat>) -> usize {
+    take.and_then(|take| token_id_of(&take))
+        .map(|take| (take as usize).min(ICRC7_MAX_QUERY_BATCH_SIZE))
+        .unwrap_or(ICRC7_DEFAULT_TAKE)
+}
+
+#[query]
+fn icrc7_name() -> String {
+    "Bikera Achievements".to_string()
+}
+
+#[query]
+fn icrc7_symbol() -> String {
+    "BKACH".to_string()
+}
+
+#[query]
+fn icrc7_description() -> Option<String> {
+    Some("Soulbound Bikera levels and badges. Non-transferable.".to_string())
+}
+
+#[query]
+fn icrc7_total_supply() -> Nat {
+    Nat::from(ACHIEVEMENT_TOKENS.with(|tokens| tokens.borrow().len()))
+}
+
+#[query]
+fn icrc7_supply_cap() -> Option<Nat> {
+    None
+}
+
+#[query]
+fn icrc7_max_query_batch_size() -> Option<Nat> {
+    Some(Nat::from(ICRC7_MAX_QUERY_BATCH_SIZE))
+}
+
+#[query]
+fn icrc7_max_update_batch_size() -> Option<Nat> {
+    Some(Nat::from(1u64))
+}
+
+#[query]
+fn icrc7_default_take_value() -> Option<Nat> {
+    Some(Nat::from(ICRC7_DEFAULT_TAKE))
+}
+
+#[query]
+fn icrc7_max_take_value() -> Option<Nat> {
+    Some(Nat::from(ICRC7_MAX_QUERY_BATCH_SIZE))
+}
+
+#[query]
+fn icrc7_max_memo_size() -> Option<Nat> {
+    Some(Nat::from(ICRC7_MAX_MEMO_SIZE))
+}
+
+#[query]
+fn icrc7_atomic_batch_transfers() -> Option<bool> {
+    Some(false)
+}
+
+// Transfers are always rejected, so nothing is ever deduplicated
+#[query]
+fn icrc7_tx_window() -> Option<Nat> {
+    None
+}
+
+#[query]
+fn icrc7_permitted_drift() -> Option<Nat> {
+    None
+}
+
+#[query]
+fn icrc7_logo() -> Option<String> {
+    read_config(|config| config.metadata.logo.clone())
+}
+
+#[query]
+fn icrc7_collection_metadata() -> Vec<(String, Value)> {
+    let mut metadata = vec![
+        ("icrc7:name".to_string(), Value::Text(icrc7_name())),
+        ("icrc7:symbol".to_string(), Value::Text(icrc7_symbol())),
+        ("icrc7:description".to_string(), Value::Text(icrc7_description().unwrap_or_default())),
+        ("icrc7:total_supply".to_string(), Value::Nat(icrc7_total_supply())),
+        ("icrc7:max_query_batch_size".to_string(), Value::Nat(Nat::from(ICRC7_MAX_QUERY_BATCH_SIZE))),
+        ("icrc7:max_update_batch_size".to_string(), Value::Nat(Nat::from(1u64))),
+        ("icrc7:default_take_value".to_string(), Value::Nat(Nat::from(ICRC7_DEFAULT_TAKE))),
+        ("icrc7:max_take_value".to_string(), Value::Nat(Nat::from(ICRC7_MAX_QUERY_BATCH_SIZE))),
+        ("icrc7:max_memo_size".to_string(), Value::Nat(Nat::from(ICRC7_MAX_MEMO_SIZE))),
+    ];
+    if let Some(logo) = icrc7_logo() {
+        metadata.push(("icrc7:logo".to_string(), Value::Text(logo)));
+    }
+    metadata
+}
+
+#[query]
+fn icrc7_token_metadata(token_ids: Vec<Nat>) -> Vec<Option<Vec<(String, Value)>>> {
+    token_ids.iter()
+        .take(ICRC7_MAX_QUERY_BATCH_SIZE)
+        .map(|id| get_token(id).map(|token| token_metadata(&token)))
+        .collect()
+}
+
+fn token_metadata(token: &AchievementToken) -> Vec<(String, Value)> {
+    let mut metadata = vec![
+        ("bikera:minted_at".to_string(), Value::Nat(Nat::from(token.minted_at))),
+    ];
+    
+    match &token.kind {
+        AchievementTokenKind::Level => {
+            let level = get_user_level(token.owner);
+            metadata.extend([
+                ("icrc7:name".to_string(), Value::Text(format!("Bikera Level {}", level.level))),
+                ("icrc7:description".to_string(), Value::Text("Rider level, earned from XP".to_string())),
+                ("bikera:level".to_string(), Value::Nat(Nat::from(level.level))),
+                ("bikera:xp".to_string(), Value::Nat(Nat::from(level.balance))),
+            ]);
+        }
+        AchievementTokenKind::Badge(badge_id) => {
+            let definition = ACHIEVEMENT_CONFIG.with(|c| {
+                c.borrow().get().badges.iter().find(|badge| &badge.id == badge_id).cloned()
+            });
+            let (name, description) = definition
+                .map(|badge| (badge.name, badge.description))
+                .unwrap_or_else(|| (badge_id.clone(), String::new()));
+            metadata.extend([
+                ("icrc7:name".to_string(), Value::Text(name)),
+                ("icrc7:description".to_string(), Value::Text(description)),
+                ("bikera:badge_id".to_string(), Value::Text(badge_id.clone())),
+            ]);
+        }
+    }
+    
+    metadata
+}
+
+#[query]
+fn icrc7_owner_of(token_ids: Vec<Nat>) -> Vec<Option<Account>> {
+    token_ids.iter()
+        .take(ICRC7_MAX_QUERY_BATCH_SIZE)
+        .map(|id| get_token(id).map(|token| Account { owner: token.owner, subaccount: None }))
+        .collect()
+}
+
+#[query]
+fn icrc7_balance_of(accounts: Vec<Account>) -> Vec<Nat> {
+    accounts.iter()
+        .take(ICRC7_MAX_QUERY_BATCH_SIZE)
+        .map(|account| {
+            if !is_default_account(account) {
+                return Nat::from(0u64);
+            }
+            let owner = account.owner;
+            let count = TOKENS_BY_OWNER.with(|index| {
+                index.borrow().range((owner, 0)..=(owner, u64::MAX)).count()
+            });
+            Nat::from(count as u64)
+        })
+        .collect()
+}
+
+#[query]
+fn icrc7_tokens(prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    let start = prev.as_ref().and_then(token_id_of).map_or(0, |prev| prev.saturating_add(1));
+    ACHIEVEMENT_TOKENS.with(|tokens| {
+        tokens.borrow()
+            .range(start..)
+            .take(take_limit(take))
+            .map(|(token_id, _)| Nat::from(token_id))
+            .collect()
+    })
+}
+
+#[query]
+fn icrc7_tokens_of(account: Account, prev: Option<Nat>, take: Option<Nat>) -> Vec<Nat> {
+    if !is_default_account(&account) {
+        return Vec::new();
+    }
+    let owner = account.owner;
+    let start = prev.as_ref().and_then(token_id_of).map_or(0, |prev| prev.saturating_add(1));
+    TOKENS_BY_OWNER.with(|index| {
+        index.borrow()
+            .range((owner, start)..=(owner, u64::MAX))
+            .take(take_limit(take))
+            .map(|((_, token_id), _)| Nat::from(token_id))
+            .collect()
+    })
+}
+
+#[update]
+fn icrc7_transfer(args: Vec<Icrc7TransferArg>) -> Vec<Option<Result<Nat, Icrc7TransferError>>> {
+    args.iter()
+        .map(|_| Some(Err(Icrc7TransferError::GenericError {
+            error_code: Nat::from(1u64),
+            message: "Bikera achievements are soulbound and cannot be transferred.".to_string(),
+        })))
+        .collect()
+}
+
+#[query]
+fn icrc10_supported_standards() -> Vec<StandardRecord> {
+    vec![
+        StandardRecord {
+            name: "ICRC-7".to_string(),
+            url: "https://github.com/dfinity/ICRC/tree/main/ICRCs/ICRC-7".to_string(),
+        },
+        StandardRecord {
+            name: "ICRC-10".to_string(),
+            url: "https://github.com/dfinity/ICRC/tree/main/ICRCs/ICRC-10".to_string(),
+        },
+    ]
+}
+
 // ============= TRANSFER FUNCTIONS (INTENTIONALLY NOT IMPLEMENTED) =============
 // XP tokens are soulbound - they cannot be transferred between accounts
 // These functions return errors to make it clear that transfers are not supported
@@ -364,12 +1552,29 @@ fn transfer_from(_from: Principal, _to: Principal, _amount: u64) -> Result<Strin
 
 #[derive(CandidType, Deserialize, Serialize)]
 struct BatchMintResult {
+    batch_id: u64,
     successful_mints: u32,
-    failed_mints: Vec<(String, String)>,
     total_minted: u64,
     timestamp: u64,
 }
 
 // ============= CANDID INTERFACE =============
 
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+
+#[test]
+fn check_candid_interface_compatibility() {
+    use candid_parser::utils::{service_equal, CandidSource};
+
+    let new_interface = __export_service();
+
+    // check the public interface against the actual one
+    let old_interface = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+        .join("xp_token.did");
+
+    service_equal(
+        CandidSource::Text(&new_interface),
+        CandidSource::File(old_interface.as_path()),
+    )
+    .unwrap();
+}
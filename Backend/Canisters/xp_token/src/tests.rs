@@ -0,0 +1,494 @@
+use crate::env::testing::{set_caller, set_time};
+use crate::*;
+
+const CONTROLLER: Principal = Principal::from_slice(&[1; 29]);
+const MINTER: Principal = Principal::from_slice(&[2; 29]);
+const ALICE: Principal = Principal::from_slice(&[10; 29]);
+const BOB: Principal = Principal::from_slice(&[11; 29]);
+
+// 2026-10-18T00:00:00Z
+const NOW: u64 = 1_792_281_600_000_000_000;
+
+fn mint_request(user: Principal, xp_amount: u64, data_hash: &str, start: &str, end: &str) -> MintRequest {
+    MintRequest {
+        user_principal: user,
+        xp_amount,
+        data_hash: data_hash.to_string(),
+        period_start: start.to_string(),
+        period_end: end.to_string(),
+    }
+}
+
+#[test]
+fn should_recover_owner_and_minters_when_upgrading_from_the_heap_ledger() {
+    // The heap-based ledger left nothing in stable memory
+    set_time(NOW);
+    set_caller(CONTROLLER);
+    apply_upgrade(XpUpgradeArgs {
+        owner: None,
+        authorized_minters: Some(vec![MINTER]),
+        logo: None,
+    });
+
+    assert_eq!(read_config(|config| config.owner), Some(CONTROLLER));
+    assert_eq!(get_authorized_minters(), vec![MINTER]);
+
+    let snapshot = LegacyLedgerSnapshot {
+        balances: vec![XPBalance {
+            principal: ALICE,
+            balance: 42_000,
+            last_mint: NOW - 1,
+            total_mints: 3,
+        }],
+        minting_history: Vec::new(),
+        authorized_minters: Vec::new(),
+    };
+    assert!(import_legacy_ledger(snapshot).is_ok());
+    assert_eq!(get_total_supply(), 42_000);
+
+    set_caller(MINTER);
+    assert!(mint_xp(mint_request(BOB, 5_000, "h1", "2026-10-17", "2026-10-17")).is_ok());
+    assert_eq!(get_balance(BOB), 5_000);
+}
+
+#[test]
+fn should_keep_the_stored_owner_on_later_upgrades() {
+    set_caller(CONTROLLER);
+    apply_upgrade(XpUpgradeArgs::default());
+
+    set_caller(MINTER);
+    apply_upgrade(XpUpgradeArgs::default());
+    assert_eq!(read_config(|config| config.owner), Some(CONTROLLER));
+
+    apply_upgrade(XpUpgradeArgs {
+        owner: Some(ALICE),
+        authorized_minters: None,
+        logo: None,
+    });
+    assert_eq!(read_config(|config| config.owner), Some(ALICE));
+}
+
+#[test]
+fn should_backfill_the_leaderboard_on_upgrade() {
+    set_caller(CONTROLLER);
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        for (principal, balance) in [(ALICE, 7_000), (BOB, 9_000)] {
+            balances.insert(principal, XPBalance {
+                principal,
+                balance,
+                last_mint: 0,
+                total_mints: 1,
+            });
+        }
+    });
+
+    apply_upgrade(XpUpgradeArgs::default());
+
+    let board: Vec<_> = get_leaderboard(10).into_iter().map(|entry| entry.principal).collect();
+    assert_eq!(board, vec![BOB, ALICE]);
+}
+
+// Owner CONTROLLER, minter MINTER, called by MINTER at NOW
+fn setup() {
+    set_time(NOW);
+    set_caller(CONTROLLER);
+    apply_upgrade(XpUpgradeArgs {
+        owner: None,
+        authorized_minters: Some(vec![MINTER]),
+        logo: None,
+    });
+    set_caller(MINTER);
+}
+
+fn batch(batch_hash: &str, distributions: Vec<MintRequest>) -> BatchMintRequest {
+    BatchMintRequest {
+        distributions,
+        batch_hash: batch_hash.to_string(),
+        timestamp: NOW,
+    }
+}
+
+#[test]
+fn should_apply_a_valid_batch_under_one_batch_id() {
+    setup();
+
+    let result = batch_mint_xp(batch("b1", vec![
+        mint_request(ALICE, 3_000, "a1", "2026-10-17", "2026-10-17"),
+        mint_request(BOB, 4_000, "b1", "2026-10-17", "2026-10-17"),
+    ]))
+    .unwrap_or_else(|_| panic!("batch should be applied"));
+
+    assert_eq!(result.batch_id, 1);
+    assert_eq!(result.successful_mints, 2);
+    assert_eq!(result.total_minted, 7_000);
+    assert_eq!(get_total_supply(), 7_000);
+    let history = get_mint_history(BOB, 10);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].batch_id, Some(1));
+    assert_eq!(get_batch(1).map(|record| record.entries), Some(2));
+}
+
+#[test]
+fn should_reject_the_whole_batch_when_one_entry_is_invalid() {
+    setup();
+
+    let result = batch_mint_xp(batch("b1", vec![
+        mint_request(ALICE, 3_000, "a1", "2026-10-17", "2026-10-17"),
+        mint_request(BOB, MAX_SINGLE_MINT + 1, "b1", "2026-10-17", "2026-10-17"),
+        mint_request(ALICE, 1_000, "a2", "2026-10-16", "2026-10-16"),
+        mint_request(CONTROLLER, 1_000, "a1", "2026-10-17", "not a date"),
+    ]));
+
+    let Err(MintError::BatchRejected(errors)) = result else {
+        panic!("batch should be rejected");
+    };
+    let indexes: Vec<u32> = errors.iter().map(|(index, _)| *index).collect();
+    // Oversized amount, duplicate principal, then duplicate data_hash and invalid period
+    assert_eq!(indexes, vec![1, 2, 3, 3]);
+    assert_eq!(get_balance(ALICE), 0);
+    assert_eq!(get_total_supply(), 0);
+    assert!(get_batch(1).is_none());
+}
+
+#[test]
+fn should_reject_replayed_batches() {
+    setup();
+    assert!(batch_mint_xp(batch("b1", vec![
+        mint_request(ALICE, 3_000, "a1", "2026-10-17", "2026-10-17"),
+    ])).is_ok());
+
+    let replay = batch_mint_xp(batch("b1", vec![
+        mint_request(ALICE, 3_000, "a2", "2026-10-18", "2026-10-18"),
+    ]));
+    assert!(matches!(replay, Err(MintError::InvalidRequest(_))));
+    assert_eq!(get_balance(ALICE), 3_000);
+}
+
+#[test]
+fn should_reject_batches_from_unauthorized_callers() {
+    setup();
+    set_caller(ALICE);
+
+    let result = batch_mint_xp(batch("b1", vec![
+        mint_request(ALICE, 3_000, "a1", "2026-10-17", "2026-10-17"),
+    ]));
+    assert!(matches!(result, Err(MintError::Unauthorized)));
+}
+
+#[test]
+fn should_reject_a_data_hash_that_was_already_minted() {
+    setup();
+    assert!(mint_xp(mint_request(ALICE, 3_000, "h1", "2026-10-10", "2026-10-10")).is_ok());
+
+    let result = mint_xp(mint_request(BOB, 3_000, "h1", "2026-10-11", "2026-10-11"));
+    let Err(MintError::AlreadyMinted { event_index, original, .. }) = result else {
+        panic!("data_hash should be rejected");
+    };
+    assert_eq!(event_index, 0);
+    assert_eq!(original.user, ALICE);
+    assert_eq!(get_balance(BOB), 0);
+}
+
+#[test]
+fn should_reject_periods_overlapping_a_minted_period() {
+    setup();
+    assert!(mint_xp(mint_request(ALICE, 3_000, "h1", "2026-10-10", "2026-10-12")).is_ok());
+
+    for (start, end) in [
+        ("2026-10-12", "2026-10-14"),
+        ("2026-10-08", "2026-10-10"),
+        ("2026-10-11", "2026-10-11"),
+        ("2026-10-01", "2026-10-31"),
+    ] {
+        let result = mint_xp(mint_request(ALICE, 1_000, "h2", start, end));
+        assert!(
+            matches!(result, Err(MintError::AlreadyMinted { event_index: 0, .. })),
+            "{} to {} overlaps",
+            start,
+            end
+        );
+    }
+
+    // Adjacent periods and other users are fine
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h2", "2026-10-13", "2026-10-13")).is_ok());
+    assert!(mint_xp(mint_request(BOB, 1_000, "h3", "2026-10-10", "2026-10-12")).is_ok());
+    assert_eq!(get_balance(ALICE), 4_000);
+}
+
+#[test]
+fn should_reject_periods_that_end_before_they_start() {
+    setup();
+
+    let result = mint_xp(mint_request(ALICE, 1_000, "h1", "2026-10-12T08:00:00Z", "2026-10-10"));
+    assert!(matches!(result, Err(MintError::InvalidRequest(_))));
+    assert_eq!(period_day("2026-10-12T08:00:00Z"), Some(20261012));
+    assert_eq!(period_day("2026-13-01"), None);
+}
+
+#[test]
+fn should_reject_zero_amount_mints() {
+    setup();
+
+    let result = mint_xp(mint_request(ALICE, 0, "h1", "2026-10-10", "2026-10-10"));
+    assert!(matches!(result, Err(MintError::InvalidRequest(_))));
+    let result = batch_mint_xp(batch("b1", vec![mint_request(BOB, 0, "h2", "2026-10-10", "2026-10-10")]));
+    assert!(matches!(result, Err(MintError::BatchRejected(_))));
+
+    // Neither the data hash nor the period was used up
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h1", "2026-10-10", "2026-10-10")).is_ok());
+    assert_eq!(get_stats().total_mints, 1);
+}
+
+fn achievements(user: Principal) -> UserAchievements {
+    ACHIEVEMENTS.with(|a| a.borrow().get(&user)).unwrap_or_default()
+}
+
+fn badge_ids(user: Principal) -> Vec<String> {
+    achievements(user).badges.into_iter().map(|badge| badge.badge_id).collect()
+}
+
+#[test]
+fn should_extend_and_reset_streaks_with_minted_periods() {
+    setup();
+
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h1", "2026-10-10", "2026-10-12")).is_ok());
+    assert_eq!(achievements(ALICE).current_streak, 3);
+
+    // Continues the streak
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h2", "2026-10-13", "2026-10-13")).is_ok());
+    assert_eq!(achievements(ALICE).current_streak, 4);
+
+    // A gap starts a new streak; the longest one is kept
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h3", "2026-10-20", "2026-10-21")).is_ok());
+    let streaks = achievements(ALICE);
+    assert_eq!((streaks.current_streak, streaks.longest_streak), (2, 4));
+    assert_eq!(streaks.last_active_day, Some(20261021));
+
+    // An older period does not move the streak
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h4", "2026-10-01", "2026-10-01")).is_ok());
+    assert_eq!(achievements(ALICE).current_streak, 2);
+    assert_eq!(achievements(ALICE).last_active_day, Some(20261021));
+}
+
+#[test]
+fn should_count_streaks_across_month_and_year_ends() {
+    assert_eq!(day_number(20270101) - day_number(20261231), 1);
+    assert_eq!(day_number(20240301) - day_number(20240228), 2);
+    assert_eq!(day_number(19700101), 0);
+}
+
+#[test]
+fn should_grant_xp_and_streak_badges_once() {
+    setup();
+
+    assert!(mint_xp(mint_request(ALICE, 99_000, "h1", "2026-09-01", "2026-09-15")).is_ok());
+    assert!(badge_ids(ALICE).is_empty());
+
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h2", "2026-09-16", "2026-09-30")).is_ok());
+    assert_eq!(badge_ids(ALICE), vec!["first_100km", "streak_30"]);
+    let badges = achievements(ALICE).badges;
+    assert_eq!(badges[0].mint_event, Some(1));
+    assert!(badges.iter().all(|badge| badge.token_id.is_some()));
+
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h3", "2026-10-01", "2026-10-01")).is_ok());
+    assert_eq!(badge_ids(ALICE).len(), 2);
+}
+
+#[test]
+fn should_mint_one_level_token_per_holder() {
+    setup();
+
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h1", "2026-10-10", "2026-10-10")).is_ok());
+    assert!(mint_xp(mint_request(ALICE, 60_000, "h2", "2026-10-11", "2026-10-11")).is_ok());
+
+    assert_eq!(achievements(ALICE).level_token, Some(1));
+    assert_eq!(icrc7_total_supply(), Nat::from(1u64));
+    let level = get_user_level(ALICE);
+    assert_eq!(level.level, 3);
+    assert_eq!(level.next_level_xp, Some(100_000));
+    // 11_000 of the 50_000 XP between levels 3 and 4
+    assert_eq!(level.progress_basis_points, 2_200);
+}
+
+#[test]
+fn should_only_award_awarded_badges() {
+    setup();
+
+    assert!(award_badge(ALICE, "cluster_champion".to_string()).is_ok());
+    assert!(award_badge(ALICE, "cluster_champion".to_string()).is_err());
+    assert!(award_badge(ALICE, "streak_30".to_string()).is_err());
+    assert!(award_badge(ALICE, "unknown".to_string()).is_err());
+    assert_eq!(badge_ids(ALICE), vec!["cluster_champion"]);
+
+    set_caller(ALICE);
+    assert!(award_badge(BOB, "cluster_champion".to_string()).is_err());
+}
+
+#[test]
+fn should_rank_holders_across_and_within_bands() {
+    setup();
+    // Ties, both edges of a band, and a balance in the last band
+    let amounts = [0, 500, 999, 1_000, 1_500, 42_000, 999_999];
+    let holders: Vec<(Principal, u64)> = (0..40u8)
+        .map(|i| (Principal::from_slice(&[100 + i; 29]), amounts[i as usize % amounts.len()]))
+        .chain([(ALICE, u64::MAX / 2), (BOB, 1_500)])
+        .collect();
+    for (principal, balance) in holders.iter() {
+        BALANCES.with(|balances| {
+            balances.borrow_mut().insert(*principal, XPBalance {
+                principal: *principal,
+                balance: *balance,
+                last_mint: 0,
+                total_mints: 1,
+            })
+        });
+        update_leaderboard(*principal, None, *balance);
+    }
+    // A mint moves BOB up within the index
+    update_leaderboard(BOB, Some(1_500), 2_000);
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        let mut bob = balances.get(&BOB).unwrap();
+        bob.balance = 2_000;
+        balances.insert(BOB, bob);
+    });
+
+    let expected = |user: Principal| {
+        LEADERBOARD.with(|index| {
+            index.borrow().iter().position(|((_, principal), _)| principal == user).unwrap() as u32 + 1
+        })
+    };
+    for (principal, _) in holders.iter() {
+        assert_eq!(get_user_rank(*principal), Some(expected(*principal)));
+    }
+    assert_eq!(get_user_rank(ALICE), Some(1));
+    assert_eq!(get_user_rank(CONTROLLER), None);
+
+    // The heap index is rebuilt from the stable leaderboard on upgrade
+    RANK_INDEX.with(|index| index.borrow_mut().fill(0));
+    apply_upgrade(XpUpgradeArgs::default());
+    assert_eq!(get_user_rank(BOB), Some(expected(BOB)));
+}
+
+#[test]
+fn should_backfill_level_tokens_of_existing_holders_on_upgrade() {
+    set_time(NOW);
+    // Holders of a build that predates the ICRC-7 view
+    BALANCES.with(|balances| {
+        let mut balances = balances.borrow_mut();
+        for (holder, balance) in [(ALICE, 1_000), (BOB, 5_000)] {
+            balances.insert(holder, XPBalance {
+                principal: holder,
+                balance,
+                last_mint: 0,
+                total_mints: 1,
+            });
+        }
+    });
+
+    set_caller(CONTROLLER);
+    apply_upgrade(XpUpgradeArgs::default());
+    apply_upgrade(XpUpgradeArgs::default());
+
+    assert_eq!(achievements(ALICE).level_token, Some(1));
+    assert_eq!(achievements(BOB).level_token, Some(2));
+    assert_eq!(icrc7_total_supply(), Nat::from(2u64));
+    let bob = Account { owner: BOB, subaccount: None };
+    assert_eq!(icrc7_tokens_of(bob, None, None), vec![Nat::from(2u64)]);
+}
+
+#[test]
+fn should_backfill_level_tokens_only_once() {
+    setup();
+    set_caller(CONTROLLER);
+    apply_upgrade(XpUpgradeArgs::default());
+    // Holders without a level token after the backfill ran are not scanned for again
+    BALANCES.with(|balances| {
+        balances.borrow_mut().insert(BOB, XPBalance {
+            principal: BOB,
+            balance: 5_000,
+            last_mint: 0,
+            total_mints: 1,
+        })
+    });
+
+    apply_upgrade(XpUpgradeArgs::default());
+
+    assert_eq!(achievements(BOB).level_token, None);
+    assert_eq!(icrc7_total_supply(), Nat::from(0u64));
+}
+
+#[test]
+fn should_expose_the_icrc7_limits_and_logo() {
+    setup();
+    assert_eq!(icrc7_default_take_value(), Some(Nat::from(ICRC7_DEFAULT_TAKE)));
+    assert_eq!(icrc7_max_take_value(), Some(Nat::from(ICRC7_MAX_QUERY_BATCH_SIZE)));
+    assert_eq!(icrc7_max_memo_size(), Some(Nat::from(32u64)));
+    assert_eq!(icrc7_atomic_batch_transfers(), Some(false));
+    assert_eq!(icrc7_logo(), None);
+
+    set_caller(CONTROLLER);
+    apply_upgrade(XpUpgradeArgs {
+        logo: Some("data:image/png;base64,iVBORw0KGgo=".to_string()),
+        ..XpUpgradeArgs::default()
+    });
+    assert_eq!(icrc7_logo().as_deref(), Some("data:image/png;base64,iVBORw0KGgo="));
+    assert!(icrc7_collection_metadata().iter().any(|(key, _)| key == "icrc7:logo"));
+}
+
+// LedgerConfig as stored before the envelope and the collection logo
+#[derive(CandidType)]
+struct LegacyTokenMetadata {
+    name: String,
+    symbol: String,
+    decimals: u8,
+    description: String,
+}
+
+#[derive(CandidType)]
+struct LegacyLedgerConfig {
+    owner: Option<Principal>,
+    authorized_minters: Vec<Principal>,
+    total_supply: u64,
+    metadata: LegacyTokenMetadata,
+}
+
+#[test]
+fn should_read_ledger_configs_stored_before_the_envelope() {
+    let legacy = LegacyLedgerConfig {
+        owner: Some(CONTROLLER),
+        authorized_minters: vec![MINTER],
+        total_supply: 42_000,
+        metadata: LegacyTokenMetadata {
+            name: "Bikera Experience Points".to_string(),
+            symbol: "XP".to_string(),
+            decimals: 3,
+            description: "Non-transferable achievement tokens".to_string(),
+        },
+    };
+
+    let bytes = candid::encode_one(&legacy).unwrap();
+    assert_eq!(stable_schema::stored_version(&bytes), Ok(0));
+    let config: LedgerConfig = stable_schema::decode(&bytes).unwrap();
+    assert_eq!(config.owner, Some(CONTROLLER));
+    assert_eq!(config.authorized_minters, vec![MINTER]);
+    assert_eq!(config.total_supply, 42_000);
+    assert_eq!(config.metadata.symbol, "XP");
+    assert_eq!(config.metadata.logo, None);
+}
+
+#[test]
+fn should_rewrite_only_the_config_cells_on_upgrade() {
+    setup();
+    assert!(mint_xp(mint_request(ALICE, 1_000, "h1", "2026-10-10", "2026-10-10")).is_ok());
+
+    set_caller(CONTROLLER);
+    post_upgrade(None);
+
+    let version_of = |collection: &str| SCHEMA_REGISTRY.with(|registry| registry.borrow().version_of(collection));
+    assert_eq!(version_of("config"), Some(LedgerConfig::VERSION));
+    assert_eq!(version_of("achievement_config"), Some(AchievementConfig::VERSION));
+    assert_eq!(version_of("balances"), None);
+    assert_eq!(get_balance(ALICE), 1_000);
+}